@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate criterion;
 
+extern crate rand;
 extern crate rust_experiments;
 
 use criterion::Criterion;
@@ -19,12 +20,70 @@ fn gen_hashset(c: &mut Criterion) {
     });
 }
 
+const LARGE_SIZES: [usize; 4] = [1_000, 10_000, 100_000, 1_000_000];
+
+fn gen_uset_large(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "USet generate map",
+        |b, &&size| b.iter(|| gen_cities_uset(size, 75)),
+        LARGE_SIZES.iter(),
+    );
+}
+
+fn gen_hashset_large(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "HashSet generate map",
+        |b, &&size| b.iter(|| gen_cities_hashset(size, 75)),
+        LARGE_SIZES.iter(),
+    );
+}
+
 fn solve(c: &mut Criterion) {
     let map = gen_cities_uset(1000, 75);
     c.bench_function("Solve map 1000", move |b| {
-        b.iter({ || find_city_distances(&map) })
+        b.iter({ || find_city_distances(&map).unwrap() })
+    });
+}
+
+#[cfg(feature = "parallel")]
+fn solve_parallel(c: &mut Criterion) {
+    let map = gen_cities_uset(1000, 75);
+    c.bench_function("Solve map 1000 in parallel", move |b| {
+        b.iter({ || parallel::find_city_distances_parallel(&map).unwrap() })
     });
 }
 
-criterion_group!(benches, gen_uset, gen_hashset, solve);
-criterion_main!(benches);
+#[cfg(not(feature = "parallel"))]
+fn solve_parallel(_: &mut Criterion) {}
+
+fn shuffle(c: &mut Criterion) {
+    let config = CityGenConfig::new(10_000);
+    let map = gen_cities(&config);
+    c.bench_function("Shuffle map 10000", move |b| {
+        b.iter({ || shuffle_cities(&mut map.clone(), &mut rand::thread_rng()) })
+    });
+}
+
+fn shuffle_by_swapping(c: &mut Criterion) {
+    let config = CityGenConfig::new(10_000);
+    let map = gen_cities(&config);
+    c.bench_function("Shuffle map 10000 by swapping", move |b| {
+        b.iter({ || shuffle_cities_by_swapping(&mut map.clone(), &mut rand::thread_rng()) })
+    });
+}
+
+criterion_group!(
+    benches,
+    gen_uset,
+    gen_hashset,
+    solve,
+    solve_parallel,
+    shuffle,
+    shuffle_by_swapping
+);
+criterion_group! {
+    name = large_benches;
+    config = Criterion::default().sample_size(10);
+    targets = gen_uset_large, gen_hashset_large
+}
+criterion_main!(benches, large_benches);
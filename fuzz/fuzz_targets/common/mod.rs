@@ -0,0 +1,243 @@
+// Shared by all fuzz targets: every target uses only a part of it.
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rust_experiments::utils::umap::UMap;
+use rust_experiments::utils::uset::USet;
+
+// Identifiers are kept below this value so that a single operation never allocates more than
+// a few kilobytes, while still being spread enough to trigger reallocations in both directions.
+const MAX_ID: usize = 4096;
+
+/// Turns the raw fuzzer input into a stream of operation arguments.
+/// When the input runs out, every read returns zero, so any byte string is a valid program.
+pub struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Input { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&b, rest)) => {
+                self.data = rest;
+                b
+            }
+            None => 0,
+        }
+    }
+
+    pub fn id(&mut self) -> usize {
+        let lo = self.byte() as usize;
+        let hi = self.byte() as usize;
+        ((hi << 8) | lo) % MAX_ID
+    }
+
+    pub fn small(&mut self) -> usize {
+        (self.byte() % 32) as usize
+    }
+
+    pub fn ids(&mut self) -> Vec<usize> {
+        let len = self.small();
+        (0..len).map(|_| self.id()).collect()
+    }
+}
+
+pub fn check_set(set: &USet, model: &BTreeSet<usize>) {
+    let expected: Vec<usize> = model.iter().cloned().collect();
+    assert_eq!(set.len(), model.len());
+    assert_eq!(set.min(), model.iter().next().cloned());
+    assert_eq!(set.max(), model.iter().next_back().cloned());
+    if let (Some(min), Some(max)) = (set.min(), set.max()) {
+        assert!(set.offset() <= min);
+        assert!(max - set.offset() < set.capacity());
+    }
+    assert_eq!(set.iter().collect::<Vec<usize>>(), expected);
+    assert!(set.iter().rev().eq(expected.iter().rev().cloned()));
+    for &id in &expected {
+        assert!(set.contains(id));
+    }
+}
+
+/// Decodes the input into a sequence of `USet` operations and applies them both to the set
+/// and to a `BTreeSet` model. Panics on the first disagreement.
+pub fn run_uset(data: &[u8]) {
+    let mut input = Input::new(data);
+    let mut set = USet::new();
+    let mut model = BTreeSet::new();
+
+    while !input.is_empty() {
+        match input.byte() % 12 {
+            0 => {
+                let id = input.id();
+                set.push(id);
+                model.insert(id);
+            }
+            1 => {
+                let id = input.id();
+                set.remove(id);
+                model.remove(&id);
+            }
+            2 => {
+                let len = input.small();
+                set.truncate(len);
+                model = model.into_iter().take(len).collect();
+            }
+            3 => {
+                let len = input.small();
+                let drained = set.drain(len);
+                let rest: BTreeSet<usize> = model.iter().cloned().skip(len).collect();
+                check_set(&drained, &rest);
+                model = model.into_iter().take(len).collect();
+            }
+            4 => {
+                let index = input.small();
+                let expected = model.iter().cloned().nth(index);
+                assert_eq!(set.pop(index), expected);
+                if let Some(id) = expected {
+                    model.remove(&id);
+                }
+            }
+            5 => {
+                let ids = input.ids();
+                set.push_all(&ids);
+                model.extend(ids);
+            }
+            6 => {
+                let ids = input.ids();
+                set = &set + &USet::from_slice(&ids);
+                model.extend(ids);
+            }
+            7 => {
+                let ids = input.ids();
+                set = &set - &USet::from_slice(&ids);
+                let other: BTreeSet<usize> = ids.into_iter().collect();
+                model = model.difference(&other).cloned().collect();
+            }
+            8 => {
+                let ids = input.ids();
+                set = &set * &USet::from_slice(&ids);
+                let other: BTreeSet<usize> = ids.into_iter().collect();
+                model = model.intersection(&other).cloned().collect();
+            }
+            9 => {
+                let ids = input.ids();
+                set = &set ^ &USet::from_slice(&ids);
+                let other: BTreeSet<usize> = ids.into_iter().collect();
+                model = model.symmetric_difference(&other).cloned().collect();
+            }
+            10 => {
+                let (a, b) = (input.id(), input.id());
+                let range = a.min(b)..a.max(b);
+                set = &set + &USet::from(range.clone());
+                model.extend(range);
+            }
+            _ => set.shrink_to_fit(),
+        }
+        check_set(&set, &model);
+    }
+}
+
+pub fn check_map(map: &UMap<u8>, model: &BTreeMap<usize, u8>) {
+    let expected: Vec<(usize, u8)> = model.iter().map(|(&k, &v)| (k, v)).collect();
+    assert_eq!(map.len(), model.len());
+    assert_eq!(map.min(), model.keys().next().cloned());
+    assert_eq!(map.max(), model.keys().next_back().cloned());
+    if let (Some(min), Some(max)) = (map.min(), map.max()) {
+        assert!(map.offset() <= min);
+        assert!(max - map.offset() < map.capacity());
+    }
+    assert_eq!(
+        map.iter().map(|(k, &v)| (k, v)).collect::<Vec<(usize, u8)>>(),
+        expected
+    );
+    assert!(map
+        .iter()
+        .rev()
+        .map(|(k, &v)| (k, v))
+        .eq(expected.iter().rev().cloned()));
+    for &(id, value) in &expected {
+        assert_eq!(map.get(id), Some(value));
+    }
+    assert!(map.keys().iter().eq(model.keys().cloned()));
+}
+
+/// Decodes the input into a sequence of `UMap` operations and applies them both to the map
+/// and to a `BTreeMap` model. Panics on the first disagreement.
+pub fn run_umap(data: &[u8]) {
+    let mut input = Input::new(data);
+    let mut map = UMap::new();
+    let mut model = BTreeMap::new();
+
+    while !input.is_empty() {
+        match input.byte() % 10 {
+            0 => {
+                let (id, value) = (input.id(), input.byte());
+                map.put(id, value);
+                model.insert(id, value);
+            }
+            1 => {
+                let value = input.byte();
+                let id = map.push(value);
+                assert_eq!(id, model.keys().next_back().map_or(1, |max| max + 1));
+                model.insert(id, value);
+            }
+            2 => {
+                let (id, value) = (input.id(), input.byte());
+                map.replace(id, value);
+                model.insert(id, value);
+            }
+            3 => {
+                let id = input.id();
+                assert_eq!(map.remove(id), model.remove(&id));
+            }
+            4 => {
+                let len = input.small();
+                map.truncate(len);
+                model = model.into_iter().take(len).collect();
+            }
+            5 => {
+                let len = input.small();
+                let drained = map.drain(len);
+                let rest: BTreeMap<usize, u8> =
+                    model.iter().map(|(&k, &v)| (k, v)).skip(len).collect();
+                check_map(&drained, &rest);
+                model = model.into_iter().take(len).collect();
+            }
+            6 => {
+                let index = input.small();
+                let expected = model.iter().map(|(&k, &v)| (k, v)).nth(index);
+                assert_eq!(map.pop(index), expected);
+                if let Some((id, _)) = expected {
+                    model.remove(&id);
+                }
+            }
+            7 => {
+                let pairs: Vec<(usize, u8)> = input
+                    .ids()
+                    .into_iter()
+                    .map(|id| (id, input.byte()))
+                    .collect();
+                map.put_all(&pairs);
+                model.extend(pairs);
+            }
+            8 => {
+                let ids = input.ids();
+                map.remove_all(&USet::from_slice(&ids));
+                ids.iter().for_each(|id| {
+                    model.remove(id);
+                });
+            }
+            _ => map.shrink_to_fit(),
+        }
+        check_map(&map, &model);
+    }
+}
@@ -0,0 +1,229 @@
+//! Structural metrics of city maps: the diameter, the centre and the centroid, and distances
+//! between arbitrary cities.
+//!
+//! Like [`find_city_distances`], everything here works on the vector of roads, validates it first,
+//! and treats the roads as two-way when measuring distances.
+//!
+//! [`find_city_distances`]: ../fn.find_city_distances.html
+
+use super::city_map::{CityMap, CityMapError};
+use crate::utils::uset::USet;
+
+/// The longest path between two cities on a map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diameter {
+    /// The number of roads on the path.
+    pub length: usize,
+    /// The cities at both ends of the path. They are the same city if the map has only one.
+    pub endpoints: (usize, usize),
+}
+
+fn farthest_from(map: &CityMap, city: usize) -> (usize, usize) {
+    let distances = map.distances_from_sources(&USet::from_slice(&[city]));
+    distances.iter().enumerate().fold(
+        (city, 0),
+        |(best, max), (c, &d)| {
+            if d > max {
+                (c, d)
+            } else {
+                (best, max)
+            }
+        },
+    )
+}
+
+/// Finds the diameter of the map with two breadth-first searches: the city farthest from
+/// the capital is one of the endpoints, and the city farthest from it is the other one.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::analytics::*;
+///
+/// // 3 - 1 - 0 - 2 - 4 - 5, with 0 as the capital
+/// let city_vec = vec![0, 0, 0, 1, 2, 4];
+/// let diameter = find_diameter(&city_vec).unwrap();
+/// assert_eq!(diameter, Diameter { length: 5, endpoints: (5, 3) });
+/// ```
+pub fn find_diameter(city_vec: &[usize]) -> Result<Diameter, CityMapError> {
+    let map = CityMap::new(city_vec.to_vec())?;
+    let (first, _) = farthest_from(&map, map.capital());
+    let (second, length) = farthest_from(&map, first);
+    Ok(Diameter {
+        length,
+        endpoints: (first, second),
+    })
+}
+
+/// Finds a centre of the map, ie. a city from which the farthest city is as close as possible.
+/// Every map has one or two centres, both in the middle of the diameter; if there are two,
+/// the one with the lower number is returned.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::analytics::*;
+///
+/// let city_vec = vec![0, 0, 0, 1, 2, 4];
+/// assert_eq!(find_centre(&city_vec), Ok(0));
+/// ```
+pub fn find_centre(city_vec: &[usize]) -> Result<usize, CityMapError> {
+    let map = CityMap::new(city_vec.to_vec())?;
+    let scores = map.capital_scores();
+    Ok((0..map.len())
+        .min_by_key(|&city| scores[city].eccentricity)
+        .unwrap())
+}
+
+/// Finds a centroid of the map, ie. a city whose removal splits the map into parts with at most
+/// half of the cities each. Every map has one or two centroids; if there are two, the one
+/// with the lower number is returned.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::analytics::*;
+///
+/// // 1, 2 and 3 lead to 0, which is on the way from 4 and 5 to the capital 6
+/// let city_vec = vec![4, 0, 0, 0, 5, 6, 6];
+/// assert_eq!(find_centroid(&city_vec), Ok(0));
+/// assert_eq!(find_centre(&city_vec), Ok(4));
+/// ```
+pub fn find_centroid(city_vec: &[usize]) -> Result<usize, CityMapError> {
+    let map = CityMap::new(city_vec.to_vec())?;
+    let n = map.len();
+    let largest_part = |city: usize| {
+        map.children(city)
+            .iter()
+            .map(|&child| map.subtree_size(child))
+            .max()
+            .unwrap_or(0)
+            .max(n - map.subtree_size(city))
+    };
+    Ok((0..n).find(|&city| largest_part(city) <= n / 2).unwrap())
+}
+
+/// Finds the number of roads between the cities `a` and `b` in O(n), without any
+/// preprocessing. To answer many queries on the same map, use [`Lca`] instead.
+///
+/// # Panics
+///
+/// Panics if `a` or `b` is not a city on the map.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::analytics::*;
+///
+/// let city_vec = vec![0, 0, 0, 1, 2, 4];
+/// assert_eq!(distance(&city_vec, 3, 5), Ok(5));
+/// assert_eq!(distance(&city_vec, 2, 5), Ok(2));
+/// ```
+///
+/// [`Lca`]: struct.Lca.html
+pub fn distance(city_vec: &[usize], a: usize, b: usize) -> Result<usize, CityMapError> {
+    CityMap::validate(city_vec)?;
+
+    // how many roads it takes from `a` to each city on its way to the capital
+    let mut steps_from_a = vec![usize::MAX; city_vec.len()];
+    let mut city = a;
+    let mut steps = 0;
+    loop {
+        steps_from_a[city] = steps;
+        if city_vec[city] == city {
+            break;
+        }
+        city = city_vec[city];
+        steps += 1;
+    }
+
+    let mut city = b;
+    let mut steps = 0;
+    while steps_from_a[city] == usize::MAX {
+        city = city_vec[city];
+        steps += 1;
+    }
+    Ok(steps + steps_from_a[city])
+}
+
+/// Answers lowest common ancestor queries with binary lifting: after O(n log n) preprocessing,
+/// every query takes O(log n).
+///
+/// The lowest common ancestor of two cities is the city where their roads to the capital meet.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::analytics::Lca;
+///
+/// let lca = Lca::new(&[0, 0, 0, 1, 2, 4]).unwrap();
+/// assert_eq!(lca.lca(3, 5), 0);
+/// assert_eq!(lca.lca(4, 5), 4);
+/// assert_eq!(lca.distance(3, 5), 5);
+/// assert_eq!(lca.ancestor(5, 2), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Lca {
+    // `jumps[k][city]` is the city 2^k roads closer to the capital, or the capital
+    jumps: Vec<Vec<usize>>,
+    depths: Vec<usize>,
+}
+
+impl Lca {
+    /// Prepares the map for queries.
+    pub fn new(city_vec: &[usize]) -> Result<Self, CityMapError> {
+        let map = CityMap::new(city_vec.to_vec())?;
+        let depths: Vec<usize> = (0..map.len()).map(|city| map.depth(city)).collect();
+        let max_depth = depths.iter().cloned().max().unwrap_or(0);
+
+        let mut jumps = vec![city_vec.to_vec()];
+        while 1 << jumps.len() <= max_depth {
+            let last = jumps.last().unwrap();
+            let next = last.iter().map(|&city| last[city]).collect();
+            jumps.push(next);
+        }
+        Ok(Lca { jumps, depths })
+    }
+
+    /// Returns the number of roads between `city` and the capital.
+    pub fn depth(&self, city: usize) -> usize {
+        self.depths[city]
+    }
+
+    /// Returns the city `steps` roads closer to the capital than `city`, or the capital
+    /// if it is closer than that.
+    pub fn ancestor(&self, city: usize, steps: usize) -> usize {
+        let steps = steps.min(self.depths[city]);
+        self.jumps
+            .iter()
+            .enumerate()
+            .filter(|&(k, _)| steps & (1 << k) != 0)
+            .fold(city, |city, (_, jump)| jump[city])
+    }
+
+    /// Returns the lowest common ancestor of `a` and `b`.
+    pub fn lca(&self, a: usize, b: usize) -> usize {
+        let (deeper, other) = if self.depths[a] >= self.depths[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let mut a = self.ancestor(deeper, self.depths[deeper] - self.depths[other]);
+        let mut b = other;
+        if a == b {
+            return a;
+        }
+        for jump in self.jumps.iter().rev() {
+            if jump[a] != jump[b] {
+                a = jump[a];
+                b = jump[b];
+            }
+        }
+        self.jumps[0][a]
+    }
+
+    /// Returns the number of roads between `a` and `b`.
+    pub fn distance(&self, a: usize, b: usize) -> usize {
+        self.depths[a] + self.depths[b] - 2 * self.depths[self.lca(a, b)]
+    }
+}
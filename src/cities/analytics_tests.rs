@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod analytics_tests {
+    use crate::cities::analytics::*;
+    use crate::cities::*;
+    use crate::utils::prng::seeded_rng;
+    use crate::utils::uset::USet;
+
+    use spectral::prelude::*;
+
+    fn gen_map(size: usize, seed: u64) -> Vec<usize> {
+        gen_cities_with_rng(&CityGenConfig::new(size), &mut seeded_rng(seed))
+    }
+
+    fn all_distances(map: &CityMap, city: usize) -> Vec<usize> {
+        map.distances_from_sources(&USet::from_slice(&[city]))
+    }
+
+    #[test]
+    fn should_analyse_single_city() {
+        assert_that!(find_diameter(&[0])).is_equal_to(Ok(Diameter {
+            length: 0,
+            endpoints: (0, 0),
+        }));
+        assert_that!(find_centre(&[0])).is_equal_to(Ok(0));
+        assert_that!(find_centroid(&[0])).is_equal_to(Ok(0));
+        assert_that!(distance(&[0], 0, 0)).is_equal_to(Ok(0));
+        assert_that!(Lca::new(&[0]).unwrap().lca(0, 0)).is_equal_to(0);
+    }
+
+    #[test]
+    fn should_reject_invalid_maps() {
+        assert_that!(find_diameter(&[1, 0])).is_equal_to(Err(CityMapError::NoCapital));
+        assert_that!(distance(&[0, 0, 3, 2], 0, 1))
+            .is_equal_to(Err(CityMapError::Cycle(vec![2, 3])));
+        assert_that!(Lca::new(&[0, 5]).is_err()).is_true();
+    }
+
+    #[test]
+    fn should_find_both_centres_of_even_path() {
+        // 0 - 1 - 2 - 3, with 3 as the capital: both 1 and 2 are centres and centroids
+        let v = vec![1, 2, 3, 3];
+        assert_that!(find_centre(&v)).is_equal_to(Ok(1));
+        assert_that!(find_centroid(&v)).is_equal_to(Ok(1));
+    }
+
+    #[test]
+    fn should_answer_queries_on_long_chain() {
+        let size = 100_000;
+        let v: Vec<usize> = (0..size).map(|c| if c == 0 { 0 } else { c - 1 }).collect();
+        let lca = Lca::new(&v).unwrap();
+        assert_that!(lca.lca(size - 1, 12_345)).is_equal_to(12_345);
+        assert_that!(lca.distance(size - 1, 1)).is_equal_to(size - 2);
+        assert_that!(lca.ancestor(size - 1, size * 2)).is_equal_to(0);
+        assert_that!(find_diameter(&v).unwrap().length).is_equal_to(size - 1);
+        assert_that!(find_centre(&v)).is_equal_to(Ok(size / 2 - 1));
+    }
+
+    quickcheck! {
+        fn diameter_is_the_longest_distance(seed: u64) -> bool {
+            let v = gen_map(60, seed);
+            let map = CityMap::new(v.clone()).unwrap();
+            let diameter = find_diameter(&v).unwrap();
+            let longest = (0..v.len())
+                .map(|c| *all_distances(&map, c).iter().max().unwrap())
+                .max()
+                .unwrap();
+            let (a, b) = diameter.endpoints;
+            diameter.length == longest && all_distances(&map, a)[b] == longest
+        }
+
+        fn centre_and_centroid_are_optimal(seed: u64) -> bool {
+            let v = gen_map(60, seed);
+            let map = CityMap::new(v.clone()).unwrap();
+            let eccentricity = |c| *all_distances(&map, c).iter().max().unwrap();
+            let centre = find_centre(&v).unwrap();
+            let centroid = find_centroid(&v).unwrap();
+            let sum = |c| all_distances(&map, c).iter().sum::<usize>();
+            (0..v.len()).all(|c| eccentricity(centre) <= eccentricity(c))
+                // a centroid is also a median: it minimises the sum of distances
+                && (0..v.len()).all(|c| sum(centroid) <= sum(c))
+        }
+
+        fn lca_distances_match_bfs(seed: u64, a: usize, b: usize) -> bool {
+            let v = gen_map(200, seed);
+            let (a, b) = (a % v.len(), b % v.len());
+            let map = CityMap::new(v.clone()).unwrap();
+            let lca = Lca::new(&v).unwrap();
+            let expected = all_distances(&map, a)[b];
+            let common = lca.lca(a, b);
+            lca.distance(a, b) == expected
+                && distance(&v, a, b) == Ok(expected)
+                && lca.ancestor(a, map.depth(a) - map.depth(common)) == common
+                && lca.ancestor(b, map.depth(b) - map.depth(common)) == common
+        }
+    }
+}
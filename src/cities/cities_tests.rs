@@ -1,12 +1,28 @@
 #[cfg(test)]
 mod cities_tests {
     use crate::cities::*;
+    use crate::utils::prng::{seed_from_u64, seeded_rng, PseudoNormalRng};
+    use crate::utils::uset::USet;
+
+    use rand::Rng;
+    use std::cmp::min;
 
     use spectral::prelude::*;
 
+    fn small_config(size: usize) -> CityGenConfig {
+        CityGenConfig::new(size)
+            .with_branching(Branching::Uniform { max_roads: 3 })
+            .without_travel_centres()
+    }
+
     #[test]
     fn should_generate_unsorted_array() {
-        let v = gen_unshuffled(20, 3, 0.0, 3);
+        let v = gen_unshuffled(
+            &small_config(20),
+            &mut rand::thread_rng(),
+            &mut Monitor::new(),
+        )
+        .unwrap();
         assert_that!(v).has_length(20);
         assert_that!(v[0]).is_equal_to(0);
         assert_that(v.iter().max().unwrap()).is_less_than(20);
@@ -14,8 +30,282 @@ mod cities_tests {
 
     #[test]
     fn should_generate_city_array() {
-        let v = gen_cities(20, 3, 0.0, 3);
+        let v = gen_cities(&small_config(20));
         assert_that!(v).has_length(20);
         assert_that(v.iter().max().unwrap()).is_less_than(20);
     }
+
+    #[test]
+    fn should_generate_valid_small_maps() {
+        // moving the capital next to the old one used to leave two capitals
+        for seed in 0..200 {
+            let v = gen_cities_with_rng(&small_config(10), &mut seeded_rng(seed));
+            assert_that!(CityMap::validate(&v)).is_ok();
+        }
+    }
+
+    const ALL_BRANCHINGS: [Branching; 5] = [
+        Branching::Uniform { max_roads: 3 },
+        Branching::Uniform { max_roads: 0 },
+        Branching::Geometric { mean: 0.5 },
+        Branching::PseudoNormal {
+            max_roads: 6,
+            a: 2.0,
+        },
+        Branching::PreferentialAttachment,
+    ];
+
+    fn max_roads_to_one_city(city_vec: &[usize]) -> usize {
+        let map = CityMap::new(city_vec.to_vec()).unwrap();
+        (0..map.len()).map(|c| map.children(c).len()).max().unwrap()
+    }
+
+    #[test]
+    fn should_generate_exact_size_with_every_branching() {
+        for &branching in ALL_BRANCHINGS.iter() {
+            for &size in [1, 2, 3, 50, 1000].iter() {
+                let config = CityGenConfig::new(size).with_branching(branching);
+                let v = gen_cities_with_rng(&config, &mut seeded_rng(size as u64));
+                assert_that!(v).has_length(size);
+                assert_that!(CityMap::validate(&v)).is_ok();
+            }
+        }
+    }
+
+    #[test]
+    fn should_reach_target_depth_exactly() {
+        for &branching in ALL_BRANCHINGS.iter() {
+            for &depth in [1, 7, 100, 999].iter() {
+                let config = CityGenConfig::new(1000)
+                    .with_branching(branching)
+                    .with_target_depth(depth);
+                let v = gen_cities_with_rng(&config, &mut seeded_rng(depth as u64));
+                assert_that!(v).has_length(1000);
+                assert_that!(find_city_distances(&v).unwrap()).has_length(depth + 1);
+            }
+        }
+        let v = gen_cities(&CityGenConfig::new(1).with_target_depth(0));
+        assert_that!(v).is_equal_to(vec![0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_not_reach_depth_beyond_size() {
+        gen_cities(&CityGenConfig::new(10).with_target_depth(10));
+    }
+
+    #[test]
+    fn should_grow_travel_centres_with_preferential_attachment() {
+        let config = CityGenConfig::new(10_000);
+        let uniform =
+            gen_cities_with_rng(&config.clone().without_travel_centres(), &mut seeded_rng(1));
+        let preferential = gen_cities_with_rng(
+            &config.with_branching(Branching::PreferentialAttachment),
+            &mut seeded_rng(1),
+        );
+        assert_that!(max_roads_to_one_city(&uniform)).is_less_than(5);
+        assert_that!(max_roads_to_one_city(&preferential)).is_greater_than(50);
+    }
+
+    #[test]
+    fn should_sample_geometric_branching_with_given_mean() {
+        let branching = Branching::Geometric { mean: 2.0 };
+        let mut rng = seeded_rng(5);
+        let total: usize = (0..100_000).map(|_| branching.sample(&mut rng)).sum();
+        let mean = total as f64 / 100_000.0;
+        assert_that!(mean).is_greater_than(1.9);
+        assert_that!(mean).is_less_than(2.1);
+    }
+
+    #[test]
+    fn should_parse_branching() {
+        assert_that!("uniform:4".parse::<Branching>())
+            .is_equal_to(Ok(Branching::Uniform { max_roads: 4 }));
+        assert_that!("pseudo-normal:6:1.5".parse::<Branching>()).is_equal_to(Ok(
+            Branching::PseudoNormal {
+                max_roads: 6,
+                a: 1.5,
+            },
+        ));
+        assert_that!("preferential".parse::<Branching>())
+            .is_equal_to(Ok(Branching::PreferentialAttachment));
+        assert_that!("uniform:many".parse::<Branching>().is_err()).is_true();
+        assert_that!("normal:4".parse::<Branching>().is_err()).is_true();
+    }
+
+    #[test]
+    fn should_relabel_capital_too() {
+        let v = vec![1, 1, 1, 2];
+        let relabelled = relabel_cities(&v, &[3, 0, 1, 2]);
+        assert_that!(relabelled).is_equal_to(vec![0, 0, 1, 0]);
+        assert_that!(CityMap::validate(&relabelled)).is_equal_to(Ok(0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_require_a_label_for_every_city() {
+        relabel_cities(&[1, 1, 1, 2], &[3, 0, 1]);
+    }
+
+    #[test]
+    fn should_keep_histogram_when_shuffling() {
+        let v = gen_unshuffled(
+            &CityGenConfig::new(5000),
+            &mut seeded_rng(1),
+            &mut Monitor::new(),
+        )
+        .unwrap();
+        let expected = find_city_distances(&v).unwrap();
+
+        let mut shuffled = v.clone();
+        shuffle_cities(&mut shuffled, &mut seeded_rng(2));
+        assert_that!(shuffled).is_not_equal_to(v.clone());
+        assert_that!(find_city_distances(&shuffled)).is_equal_to(Ok(expected.clone()));
+
+        let mut swapped = v.clone();
+        shuffle_cities_by_swapping(&mut swapped, &mut seeded_rng(2));
+        assert_that!(find_city_distances(&swapped)).is_equal_to(Ok(expected));
+    }
+
+    #[test]
+    fn should_shuffle_tiny_maps() {
+        for size in 0..4usize {
+            let mut v: Vec<usize> = (0..size).map(|c| c.saturating_sub(1)).collect();
+            shuffle_cities(&mut v, &mut seeded_rng(size as u64));
+            shuffle_cities_by_swapping(&mut v, &mut seeded_rng(size as u64));
+            assert_that!(v).has_length(size);
+        }
+    }
+
+    #[test]
+    fn should_move_capital_to_every_city() {
+        let mut capitals = vec![false; 4];
+        for seed in 0..100 {
+            let mut v = vec![0, 0, 1, 2];
+            shuffle_cities(&mut v, &mut seeded_rng(seed));
+            capitals[CityMap::validate(&v).unwrap()] = true;
+        }
+        assert_that!(capitals).is_equal_to(vec![true; 4]);
+    }
+
+    #[test]
+    fn should_keep_histogram_for_weighted_distances() {
+        let v = gen_cities(&CityGenConfig::new(1000));
+        let weights = vec![1usize; v.len()];
+        let result = find_weighted_city_distances(&v, &weights).unwrap();
+        assert_that!(result.histogram()).is_equal_to(find_city_distances(&v).unwrap());
+        assert_that!(result.distances).is_equal_to(result.hops.clone());
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_require_a_weight_for_every_city() {
+        let _ = find_weighted_city_distances(&[1, 1, 1], &[1, 2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_refuse_extra_weights() {
+        let _ = find_weighted_city_distances(&[1, 1, 1], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn should_find_weighted_distances_and_predecessors() {
+        let v = vec![9, 3, 2, 2, 2, 2, 1, 5, 2, 1];
+        let result = find_weighted_city_distances_by(&v, |city| city * 10).unwrap();
+        assert_that!(result.hops).is_equal_to(vec![4, 2, 0, 1, 1, 1, 3, 2, 1, 3]);
+        assert_that!(result.distances).is_equal_to(vec![130, 40, 0, 30, 40, 50, 100, 120, 80, 130]);
+        assert_that!(result.predecessors[2]).is_equal_to(None);
+        assert_that!(result.predecessors[0]).is_equal_to(Some(9));
+        assert_that!(result.predecessors[6]).is_equal_to(Some(1));
+    }
+
+    #[test]
+    fn should_regenerate_the_same_maps_from_the_same_seed() {
+        assert_that!(gen_cities_with_rng(
+            &CityGenConfig::new(500),
+            &mut seeded_rng(7)
+        ))
+        .is_equal_to(gen_cities_with_rng(
+            &CityGenConfig::new(500),
+            &mut seeded_rng(7),
+        ));
+        assert_that!(gen_cities_uset_with_rng(500, 25, &mut seeded_rng(7)))
+            .is_equal_to(gen_cities_uset_with_rng(500, 25, &mut seeded_rng(7)));
+        assert_that!(gen_cities_hashset_with_rng(500, 25, &mut seeded_rng(7)))
+            .is_equal_to(gen_cities_hashset_with_rng(500, 25, &mut seeded_rng(7)));
+    }
+
+    #[test]
+    fn should_generate_different_maps_from_different_seeds() {
+        assert_that!(gen_cities_with_rng(
+            &CityGenConfig::new(500),
+            &mut seeded_rng(7)
+        ))
+        .is_not_equal_to(gen_cities_with_rng(
+            &CityGenConfig::new(500),
+            &mut seeded_rng(8),
+        ));
+    }
+
+    #[test]
+    fn should_generate_with_pseudo_normal_rng() {
+        let seed = seed_from_u64(7);
+        let v1 = gen_cities_uset_with_rng(500, 25, &mut PseudoNormalRng::with_seed(2.0, seed));
+        let v2 = gen_cities_uset_with_rng(500, 25, &mut PseudoNormalRng::with_seed(2.0, seed));
+        assert_that!(v1).is_equal_to(v2.clone());
+        assert_that!(find_city_distances(&v2)).is_ok();
+    }
+
+    // The generator before the free pool: rebuilds the set of free cities after every city
+    // with new roads.
+    fn gen_cities_uset_quadratic(size: usize, max_roads: usize, seed: u64) -> Vec<usize> {
+        let r = &mut seeded_rng(seed);
+        let all_cities = USet::from(0..size);
+        let capital = r.gen_range(0, size);
+        let mut city_vec = vec![(capital, capital)];
+        while city_vec.len() < size {
+            let (city, ..) = city_vec[city_vec.len() - 1];
+            let new_cities = r.gen_range(0, min(max_roads, size - city_vec.len())) + 1;
+            let used_cities: USet = city_vec.iter().map(|&(x, _)| x).collect();
+            let mut free_cities = &all_cities - &used_cities;
+            for _i in 0..new_cities {
+                let new_city = free_cities.pop(r.gen_range(0, free_cities.len())).unwrap();
+                city_vec.push((new_city, city));
+            }
+        }
+        let mut city_array = vec![0; size];
+        city_vec
+            .iter()
+            .for_each(|&(from, to)| city_array[from] = to);
+        city_array
+    }
+
+    #[test]
+    fn should_generate_the_same_maps_as_quadratic_uset_generator() {
+        for &(size, max_roads, seed) in &[(1, 3, 1), (2, 1, 2), (500, 25, 7), (1000, 3, 9)] {
+            assert_that!(gen_cities_uset_with_rng(
+                size,
+                max_roads,
+                &mut seeded_rng(seed)
+            ))
+            .is_equal_to(gen_cities_uset_quadratic(size, max_roads, seed));
+        }
+    }
+
+    #[test]
+    fn should_keep_snaky_shape() {
+        // every distance but the last has roads from 1 to `max_roads` cities
+        let max_roads = 10;
+        let maps = vec![
+            gen_cities_uset_with_rng(5000, max_roads, &mut seeded_rng(3)),
+            gen_cities_hashset_with_rng(5000, max_roads, &mut seeded_rng(3)),
+        ];
+        for city_vec in maps {
+            let histogram = find_city_distances(&city_vec).unwrap();
+            assert_that!(histogram[0]).is_equal_to(1);
+            assert_that!(histogram.iter().all(|&c| c >= 1 && c <= max_roads)).is_true();
+            assert_that!(histogram.len()).is_greater_than_or_equal_to(5000 / max_roads);
+        }
+    }
 }
@@ -0,0 +1,590 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+/// Describes what is wrong with a vector of roads which was supposed to be a city map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CityMapError {
+    /// No city has a road to itself, so there is no capital.
+    NoCapital,
+    /// More than one city has a road to itself. Contains all of them.
+    MultipleCapitals(Vec<usize>),
+    /// The road from `city` leads to `target`, which is not a city on the map.
+    DanglingRoad { city: usize, target: usize },
+    /// The cities form a cycle and none of them leads to the capital.
+    /// Contains the cities in the order in which the roads connect them.
+    Cycle(Vec<usize>),
+}
+
+impl fmt::Display for CityMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CityMapError::NoCapital => write!(f, "the map has no capital"),
+            CityMapError::MultipleCapitals(capitals) => {
+                write!(f, "the map has more than one capital: {:?}", capitals)
+            }
+            CityMapError::DanglingRoad { city, target } => write!(
+                f,
+                "the road from the city {} leads to a non-existing city {}",
+                city, target
+            ),
+            CityMapError::Cycle(cities) => write!(f, "the cities form a cycle: {:?}", cities),
+        }
+    }
+}
+
+impl Error for CityMapError {}
+
+/// Describes why a [`CityMap`](struct.CityMap.html) could not be changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MutationError {
+    /// The city is not on the map.
+    NoSuchCity(usize),
+    /// The road from the capital cannot be changed and the capital cannot be removed.
+    Capital,
+    /// Only cities with no roads leading to them can be removed.
+    NotALeaf(usize),
+    /// The new road would close a cycle. Contains the cities in the order in which the roads
+    /// would connect them.
+    Cycle(Vec<usize>),
+}
+
+impl fmt::Display for MutationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MutationError::NoSuchCity(city) => write!(f, "there is no city {} on the map", city),
+            MutationError::Capital => write!(f, "the capital cannot be moved or removed"),
+            MutationError::NotALeaf(city) => {
+                write!(
+                    f,
+                    "the city {} cannot be removed, other roads lead to it",
+                    city
+                )
+            }
+            MutationError::Cycle(cities) => {
+                write!(f, "the new road would form a cycle: {:?}", cities)
+            }
+        }
+    }
+}
+
+impl Error for MutationError {}
+
+/// A map of cities: `roads[x] == y` means that there is a road from the city `x` to the city `y`.
+/// The map is always a valid tree, ie. there is exactly one capital such that
+/// `roads[capital] == capital`, and every other city leads to it.
+///
+/// Alongside the roads, the map keeps the properties which are expensive to derive from
+/// the roads alone: the list of cities leading directly to every city, the distance of every city
+/// from the capital, the sizes of subtrees, and the distance histogram. All of them are computed
+/// once, in O(n), when the map is created, and then updated by every change of the map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CityMap {
+    roads: Vec<usize>,
+    capital: usize,
+    children: Vec<Vec<usize>>,
+    depths: Vec<usize>,
+    subtree_sizes: Vec<usize>,
+    histogram: Vec<usize>,
+}
+
+// Markers used by `validate` to find cycles.
+const NOT_VISITED: u8 = 0;
+const ON_PATH: u8 = 1;
+const LEADS_TO_CAPITAL: u8 = 2;
+
+impl CityMap {
+    /// Creates a city map from the vector of roads, if they form a valid map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let map = CityMap::new(vec![1, 1, 1, 2]).unwrap();
+    /// assert_eq!(map.capital(), 1);
+    ///
+    /// assert_eq!(CityMap::new(vec![1, 0]), Err(CityMapError::NoCapital));
+    /// ```
+    pub fn new(roads: Vec<usize>) -> Result<Self, CityMapError> {
+        let capital = CityMap::validate(&roads)?;
+
+        let mut children = vec![Vec::new(); roads.len()];
+        roads
+            .iter()
+            .enumerate()
+            .filter(|&(city, &parent)| city != parent)
+            .for_each(|(city, &parent)| children[parent].push(city));
+
+        let mut map = CityMap {
+            roads,
+            capital,
+            children,
+            depths: Vec::new(),
+            subtree_sizes: Vec::new(),
+            histogram: Vec::new(),
+        };
+
+        let order: Vec<usize> = map.bfs().collect();
+        let mut depths = vec![0; map.len()];
+        order
+            .iter()
+            .skip(1)
+            .for_each(|&city| depths[city] = depths[map.roads[city]] + 1);
+        let mut subtree_sizes = vec![1; map.len()];
+        order
+            .iter()
+            .skip(1)
+            .rev()
+            .for_each(|&city| subtree_sizes[map.roads[city]] += subtree_sizes[city]);
+
+        let max_depth = depths.iter().cloned().max().unwrap_or(0);
+        map.histogram = depths
+            .iter()
+            .fold(vec![0; max_depth + 1], |mut acc, &depth| {
+                acc[depth] += 1;
+                acc
+            });
+        map.depths = depths;
+        map.subtree_sizes = subtree_sizes;
+        Ok(map)
+    }
+
+    /// Checks if the vector of roads describes a valid city map and returns the capital.
+    /// Runs in O(n).
+    ///
+    /// # Errors
+    ///
+    /// The first problem found is reported: dangling roads are checked first, then the number of
+    /// capitals, and then cycles.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// assert_eq!(CityMap::validate(&[1, 1, 1, 2]), Ok(1));
+    /// assert_eq!(CityMap::validate(&[0, 1]), Err(CityMapError::MultipleCapitals(vec![0, 1])));
+    /// assert_eq!(CityMap::validate(&[0, 5]), Err(CityMapError::DanglingRoad { city: 1, target: 5 }));
+    /// assert_eq!(CityMap::validate(&[0, 2, 3, 1]), Err(CityMapError::Cycle(vec![1, 2, 3])));
+    /// ```
+    pub fn validate(roads: &[usize]) -> Result<usize, CityMapError> {
+        if let Some((city, &target)) = roads
+            .iter()
+            .enumerate()
+            .find(|&(_, &target)| target >= roads.len())
+        {
+            return Err(CityMapError::DanglingRoad { city, target });
+        }
+
+        let capitals: Vec<usize> = (0..roads.len()).filter(|&c| roads[c] == c).collect();
+        let capital = match capitals.len() {
+            0 => return Err(CityMapError::NoCapital),
+            1 => capitals[0],
+            _ => return Err(CityMapError::MultipleCapitals(capitals)),
+        };
+
+        let mut marks = vec![NOT_VISITED; roads.len()];
+        marks[capital] = LEADS_TO_CAPITAL;
+        let mut path = Vec::new();
+        for start in 0..roads.len() {
+            let mut city = start;
+            while marks[city] == NOT_VISITED {
+                marks[city] = ON_PATH;
+                path.push(city);
+                city = roads[city];
+            }
+
+            if marks[city] == ON_PATH {
+                let cycle_start = path.iter().position(|&c| c == city).unwrap();
+                return Err(CityMapError::Cycle(path.split_off(cycle_start)));
+            }
+
+            path.drain(..).for_each(|c| marks[c] = LEADS_TO_CAPITAL);
+        }
+
+        Ok(capital)
+    }
+
+    /// Returns the capital, ie. the only city with a road to itself.
+    pub fn capital(&self) -> usize {
+        self.capital
+    }
+
+    /// Returns the number of cities on the map.
+    pub fn len(&self) -> usize {
+        self.roads.len()
+    }
+
+    /// Returns `true` if there are no cities on the map. Never true for a valid map.
+    pub fn is_empty(&self) -> bool {
+        self.roads.is_empty()
+    }
+
+    /// Returns the underlying vector of roads.
+    pub fn roads(&self) -> &[usize] {
+        &self.roads
+    }
+
+    /// Returns the city to which the road from `city` leads, or `None` for the capital.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let map = CityMap::new(vec![1, 1, 1, 2]).unwrap();
+    /// assert_eq!(map.parent(3), Some(2));
+    /// assert_eq!(map.parent(1), None);
+    /// ```
+    pub fn parent(&self, city: usize) -> Option<usize> {
+        if city == self.capital {
+            None
+        } else {
+            Some(self.roads[city])
+        }
+    }
+
+    /// Returns the cities with roads leading directly to `city`, in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let map = CityMap::new(vec![1, 1, 1, 2]).unwrap();
+    /// assert_eq!(map.children(1), &[0, 2]);
+    /// assert!(map.children(3).is_empty());
+    /// ```
+    pub fn children(&self, city: usize) -> &[usize] {
+        &self.children[city]
+    }
+
+    /// Returns the number of roads between `city` and the capital.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let map = CityMap::new(vec![1, 1, 1, 2]).unwrap();
+    /// assert_eq!(map.depth(1), 0);
+    /// assert_eq!(map.depth(3), 2);
+    /// ```
+    pub fn depth(&self, city: usize) -> usize {
+        self.depths[city]
+    }
+
+    /// Returns the number of cities which lead to the capital through `city`, including `city`
+    /// itself. For the capital it is the number of all cities on the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let map = CityMap::new(vec![1, 1, 1, 2]).unwrap();
+    /// assert_eq!(map.subtree_size(1), 4);
+    /// assert_eq!(map.subtree_size(2), 2);
+    /// assert_eq!(map.subtree_size(3), 1);
+    /// ```
+    pub fn subtree_size(&self, city: usize) -> usize {
+        self.subtree_sizes[city]
+    }
+
+    /// Returns a vector where indexes are the distances from the capital and the values are
+    /// the number of cities with the given distance. The same as [`find_city_distances`].
+    ///
+    /// [`find_city_distances`]: ../fn.find_city_distances.html
+    pub fn distance_histogram(&self) -> Vec<usize> {
+        self.histogram.clone()
+    }
+
+    /// Returns an iterator visiting all cities in the breadth-first order, starting from
+    /// the capital. Cities with the same parent are visited in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let map = CityMap::new(vec![1, 1, 1, 2, 0]).unwrap();
+    /// assert_eq!(map.bfs().collect::<Vec<usize>>(), vec![1, 0, 2, 4, 3]);
+    /// ```
+    pub fn bfs(&self) -> Bfs<'_> {
+        let mut queue = VecDeque::with_capacity(self.len());
+        queue.push_back(self.capital);
+        Bfs { map: self, queue }
+    }
+
+    /// Returns an iterator visiting all cities in the depth-first (pre-)order, starting from
+    /// the capital. Cities with the same parent are visited in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let map = CityMap::new(vec![1, 1, 1, 2, 0]).unwrap();
+    /// assert_eq!(map.dfs().collect::<Vec<usize>>(), vec![1, 0, 4, 2, 3]);
+    /// ```
+    pub fn dfs(&self) -> Dfs<'_> {
+        Dfs {
+            map: self,
+            stack: vec![self.capital],
+        }
+    }
+}
+
+// Changes of the map. Each of them keeps all the derived properties up to date, visiting only
+// the cities on the way to the capital and, when reattaching, the moved subtree.
+impl CityMap {
+    fn check_city(&self, city: usize) -> Result<(), MutationError> {
+        if city < self.len() {
+            Ok(())
+        } else {
+            Err(MutationError::NoSuchCity(city))
+        }
+    }
+
+    // Adds `delta` cities to the subtree sizes of `city` and all cities on its way to the capital.
+    fn update_subtree_sizes(&mut self, city: usize, delta: isize) {
+        let mut city = city;
+        loop {
+            self.subtree_sizes[city] = (self.subtree_sizes[city] as isize + delta) as usize;
+            if city == self.capital {
+                break;
+            }
+            city = self.roads[city];
+        }
+    }
+
+    fn add_to_histogram(&mut self, depth: usize) {
+        if self.histogram.len() <= depth {
+            self.histogram.resize(depth + 1, 0);
+        }
+        self.histogram[depth] += 1;
+    }
+
+    fn remove_from_histogram(&mut self, depth: usize) {
+        self.histogram[depth] -= 1;
+        while self.histogram.len() > 1 && self.histogram[self.histogram.len() - 1] == 0 {
+            self.histogram.pop();
+        }
+    }
+
+    /// Adds a new city with a road to `to` and returns its number, which is always
+    /// the number of cities before the change. Runs in O(d), where `d` is the distance from
+    /// `to` to the capital.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let mut map = CityMap::new(vec![1, 1, 1]).unwrap();
+    /// assert_eq!(map.add_city(2), Ok(3));
+    /// assert_eq!(map.distance_histogram(), vec![1, 2, 1]);
+    /// ```
+    pub fn add_city(&mut self, to: usize) -> Result<usize, MutationError> {
+        self.check_city(to)?;
+        let city = self.len();
+        self.roads.push(to);
+        self.children.push(Vec::new());
+        self.children[to].push(city);
+        self.depths.push(self.depths[to] + 1);
+        self.subtree_sizes.push(1);
+        self.update_subtree_sizes(to, 1);
+        self.add_to_histogram(self.depths[city]);
+        Ok(city)
+    }
+
+    /// Removes a city with no roads leading to it. To keep the numbers of cities continuous,
+    /// the last city on the map takes the number of the removed one. Runs in O(d + k),
+    /// where `d` is the distance from the city to the capital and `k` is the number of roads
+    /// leading to the last city.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let mut map = CityMap::new(vec![1, 1, 1, 2]).unwrap();
+    /// assert_eq!(map.remove_leaf(2), Err(MutationError::NotALeaf(2)));
+    /// assert_eq!(map.remove_leaf(0), Ok(()));
+    /// // the city 3 is now the city 0
+    /// assert_eq!(map.roads(), &[2, 1, 1]);
+    /// assert_eq!(map.distance_histogram(), vec![1, 1, 1]);
+    /// ```
+    pub fn remove_leaf(&mut self, city: usize) -> Result<(), MutationError> {
+        self.check_city(city)?;
+        if city == self.capital {
+            return Err(MutationError::Capital);
+        } else if !self.children[city].is_empty() {
+            return Err(MutationError::NotALeaf(city));
+        }
+
+        let to = self.roads[city];
+        remove_sorted(&mut self.children[to], city);
+        self.update_subtree_sizes(to, -1);
+        self.remove_from_histogram(self.depths[city]);
+
+        let last = self.len() - 1;
+        if city != last {
+            self.renumber(last, city);
+        }
+        self.roads.pop();
+        self.children.pop();
+        self.depths.pop();
+        self.subtree_sizes.pop();
+        Ok(())
+    }
+
+    // Moves everything known about the city `from` to the unused number `to`.
+    fn renumber(&mut self, from: usize, to: usize) {
+        if self.roads[from] == from {
+            self.capital = to;
+            self.roads[to] = to;
+        } else {
+            let parent = self.roads[from];
+            self.roads[to] = parent;
+            remove_sorted(&mut self.children[parent], from);
+            insert_sorted(&mut self.children[parent], to);
+        }
+
+        let children = mem::take(&mut self.children[from]);
+        children.iter().for_each(|&child| self.roads[child] = to);
+        self.children[to] = children;
+        self.depths[to] = self.depths[from];
+        self.subtree_sizes[to] = self.subtree_sizes[from];
+    }
+
+    /// Replaces the road from `city` with a road to `to`. The cities leading to `city` move
+    /// together with it. Runs in O(d + s), where `d` is the longer of the distances from
+    /// the old and the new end of the road to the capital, and `s` is the number of cities which
+    /// move.
+    ///
+    /// # Errors
+    ///
+    /// The road cannot start in the capital, and cannot lead to a city which leads
+    /// to `city`, as that would close a cycle.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let mut map = CityMap::new(vec![1, 1, 1, 2]).unwrap();
+    /// assert_eq!(map.reattach(2, 0), Ok(()));
+    /// assert_eq!(map.distance_histogram(), vec![1, 1, 1, 1]);
+    /// assert_eq!(map.reattach(0, 3), Err(MutationError::Cycle(vec![0, 3, 2])));
+    /// ```
+    pub fn reattach(&mut self, city: usize, to: usize) -> Result<(), MutationError> {
+        self.check_city(city)?;
+        self.check_city(to)?;
+        if city == self.capital {
+            return Err(MutationError::Capital);
+        }
+
+        let mut path = vec![city];
+        let mut next = to;
+        while self.depths[next] > self.depths[city] {
+            path.push(next);
+            next = self.roads[next];
+        }
+        if next == city {
+            return Err(MutationError::Cycle(path));
+        }
+
+        let from = self.roads[city];
+        if from == to {
+            return Ok(());
+        }
+        let size = self.subtree_sizes[city] as isize;
+        remove_sorted(&mut self.children[from], city);
+        self.update_subtree_sizes(from, -size);
+        insert_sorted(&mut self.children[to], city);
+        self.update_subtree_sizes(to, size);
+        self.roads[city] = to;
+
+        let new_depth = self.depths[to] + 1;
+        if new_depth != self.depths[city] {
+            let mut stack = vec![city];
+            let mut moved = Vec::new();
+            while let Some(c) = stack.pop() {
+                stack.extend(&self.children[c]);
+                moved.push(c);
+            }
+            let delta = new_depth as isize - self.depths[city] as isize;
+            // add first, so that the histogram is not trimmed below the new depths
+            for &c in &moved {
+                self.add_to_histogram((self.depths[c] as isize + delta) as usize);
+            }
+            for &c in &moved {
+                self.remove_from_histogram(self.depths[c]);
+                self.depths[c] = (self.depths[c] as isize + delta) as usize;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn remove_sorted(cities: &mut Vec<usize>, city: usize) {
+    if let Ok(pos) = cities.binary_search(&city) {
+        cities.remove(pos);
+    }
+}
+
+fn insert_sorted(cities: &mut Vec<usize>, city: usize) {
+    if let Err(pos) = cities.binary_search(&city) {
+        cities.insert(pos, city);
+    }
+}
+
+/// A breadth-first iterator over the cities of a [`CityMap`](struct.CityMap.html).
+pub struct Bfs<'a> {
+    map: &'a CityMap,
+    queue: VecDeque<usize>,
+}
+
+impl Iterator for Bfs<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let city = self.queue.pop_front()?;
+        self.queue.extend(self.map.children(city));
+        Some(city)
+    }
+}
+
+/// A depth-first iterator over the cities of a [`CityMap`](struct.CityMap.html).
+pub struct Dfs<'a> {
+    map: &'a CityMap,
+    stack: Vec<usize>,
+}
+
+impl Iterator for Dfs<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let city = self.stack.pop()?;
+        self.stack.extend(self.map.children(city).iter().rev());
+        Some(city)
+    }
+}
+
+impl TryFrom<Vec<usize>> for CityMap {
+    type Error = CityMapError;
+
+    fn try_from(roads: Vec<usize>) -> Result<Self, Self::Error> {
+        CityMap::new(roads)
+    }
+}
+
+impl From<CityMap> for Vec<usize> {
+    fn from(map: CityMap) -> Self {
+        map.roads
+    }
+}
@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod city_map_tests {
+    use crate::cities::*;
+
+    use crate::utils::prng::seeded_rng;
+
+    use std::convert::TryFrom;
+
+    use spectral::prelude::*;
+
+    #[test]
+    fn should_accept_generated_maps() {
+        let v = gen_cities(&CityGenConfig::new(1000));
+        assert_that!(CityMap::validate(&v)).is_ok();
+        let v = gen_cities_uset(1000, 25);
+        assert_that!(CityMap::validate(&v)).is_ok();
+    }
+
+    #[test]
+    fn should_reject_empty_map() {
+        assert_that!(CityMap::validate(&[])).is_equal_to(Err(CityMapError::NoCapital));
+    }
+
+    #[test]
+    fn should_report_all_capitals() {
+        let v = vec![0, 0, 2, 2, 4];
+        assert_that!(CityMap::validate(&v))
+            .is_equal_to(Err(CityMapError::MultipleCapitals(vec![0, 2, 4])));
+    }
+
+    #[test]
+    fn should_report_dangling_road() {
+        let v = vec![0, 0, 7, 2];
+        assert_that!(CityMap::validate(&v))
+            .is_equal_to(Err(CityMapError::DanglingRoad { city: 2, target: 7 }));
+    }
+
+    #[test]
+    fn should_report_cycle_reached_from_outside() {
+        // 1 -> 2 -> 3 -> 4 -> 2, and 0 is the capital nobody leads to
+        let v = vec![0, 2, 3, 4, 2];
+        assert_that!(CityMap::validate(&v)).is_equal_to(Err(CityMapError::Cycle(vec![2, 3, 4])));
+        assert_that!(find_city_distances(&v)).is_err();
+    }
+
+    #[test]
+    fn should_not_overflow_on_long_cycles() {
+        let size = 1_000_000;
+        let mut v: Vec<usize> = (1..=size).collect();
+        v[size - 1] = 1;
+        v.push(size);
+        match CityMap::validate(&v) {
+            Err(CityMapError::Cycle(cycle)) => assert_that!(cycle).has_length(size - 1),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_describe_errors() {
+        let err = CityMapError::DanglingRoad { city: 2, target: 7 };
+        assert_that!(err.to_string())
+            .is_equal_to("the road from the city 2 leads to a non-existing city 7".to_string());
+    }
+
+    #[test]
+    fn should_derive_properties_of_generated_map() {
+        let v = gen_cities(&CityGenConfig::new(1000));
+        let map = CityMap::try_from(v.clone()).unwrap();
+        assert_that!(map.roads()).is_equal_to(&v[..]);
+        assert_that!(map.distance_histogram()).is_equal_to(find_city_distances(&v).unwrap());
+        assert_that!(map.subtree_size(map.capital())).is_equal_to(1000);
+
+        for city in 0..map.len() {
+            for &child in map.children(city) {
+                assert_that!(map.parent(child)).is_equal_to(Some(city));
+                assert_that!(map.depth(child)).is_equal_to(map.depth(city) + 1);
+            }
+            let children_sizes: usize = map
+                .children(city)
+                .iter()
+                .map(|&c| map.subtree_size(c))
+                .sum();
+            assert_that!(map.subtree_size(city)).is_equal_to(children_sizes + 1);
+        }
+    }
+
+    #[test]
+    fn should_visit_every_city_once() {
+        let map = CityMap::try_from(gen_cities_uset(500, 10)).unwrap();
+
+        let bfs: Vec<usize> = map.bfs().collect();
+        assert_that!(bfs).has_length(500);
+        assert_that!(bfs.windows(2).all(|w| map.depth(w[0]) <= map.depth(w[1]))).is_true();
+
+        let dfs: Vec<usize> = map.dfs().collect();
+        let mut sorted = dfs.clone();
+        sorted.sort();
+        assert_that!(sorted).is_equal_to((0..500).collect::<Vec<usize>>());
+        // in pre-order, the whole subtree of a city directly follows it
+        let position = |city: usize| dfs.iter().position(|&c| c == city).unwrap();
+        for city in 0..map.len() {
+            let start = position(city);
+            let end = start + map.subtree_size(city);
+            assert_that!(dfs[start..end].iter().all(|&c| {
+                let mut x = c;
+                while x != city && x != map.capital() {
+                    x = map.roads()[x];
+                }
+                x == city
+            }))
+            .is_true();
+        }
+    }
+
+    #[test]
+    fn should_fail_to_convert_invalid_roads() {
+        assert_that!(CityMap::try_from(vec![1, 0])).is_equal_to(Err(CityMapError::NoCapital));
+    }
+
+    #[test]
+    fn should_reject_invalid_mutations() {
+        let mut map = CityMap::new(vec![1, 1, 1, 2]).unwrap();
+        assert_that!(map.add_city(4)).is_equal_to(Err(MutationError::NoSuchCity(4)));
+        assert_that!(map.remove_leaf(1)).is_equal_to(Err(MutationError::Capital));
+        assert_that!(map.reattach(1, 0)).is_equal_to(Err(MutationError::Capital));
+        assert_that!(map.reattach(2, 2)).is_equal_to(Err(MutationError::Cycle(vec![2])));
+        assert_that!(map.reattach(0, 7)).is_equal_to(Err(MutationError::NoSuchCity(7)));
+        assert_that!(map.clone()).is_equal_to(CityMap::new(vec![1, 1, 1, 2]).unwrap());
+    }
+
+    #[test]
+    fn should_renumber_capital_when_removing() {
+        let mut map = CityMap::new(vec![2, 2, 2]).unwrap();
+        map.remove_leaf(0).unwrap();
+        assert_that!(map.roads()).is_equal_to(&[0, 0][..]);
+        assert_that!(map.capital()).is_equal_to(0);
+        assert_that!(map.children(0)).is_equal_to(&[1][..]);
+    }
+
+    #[test]
+    fn should_shrink_histogram_when_moving_closer() {
+        let mut map = CityMap::new(vec![0, 0, 1, 2, 3]).unwrap();
+        map.reattach(3, 0).unwrap();
+        assert_that!(map.distance_histogram()).is_equal_to(vec![1, 2, 2]);
+        map.remove_leaf(4).unwrap();
+        assert_that!(map.distance_histogram()).is_equal_to(vec![1, 2, 1]);
+    }
+
+    quickcheck! {
+        // After every change, the map has to be the same as if it was built from its roads.
+        fn mutations_keep_derived_properties(seed: u64, steps: Vec<(u8, usize, usize)>) -> bool {
+            let roads = gen_cities_with_rng(&CityGenConfig::new(30), &mut seeded_rng(seed));
+            let mut map = CityMap::new(roads).unwrap();
+            for &(op, a, b) in &steps {
+                let (a, b) = (a % map.len(), b % map.len());
+                // many random changes are rejected, and the map has to stay consistent either way
+                let _ = match op % 3 {
+                    0 => map.add_city(a).map(|_| ()),
+                    1 => map.remove_leaf(a),
+                    _ => map.reattach(a, b),
+                };
+                if map != CityMap::new(map.roads().to_vec()).unwrap() {
+                    return false;
+                }
+            }
+            true
+        }
+    }
+}
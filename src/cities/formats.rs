@@ -0,0 +1,369 @@
+//! Reading and writing city maps in text formats, so that they can be saved, compared,
+//! and visualised with external tools.
+//!
+//! Every reader checks if the result is a valid map (see [`CityMap::validate`]).
+//!
+//! [`CityMap::validate`]: ../city_map/struct.CityMap.html#method.validate
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use super::city_map::{CityMap, CityMapError};
+
+/// Supported file formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapFormat {
+    /// One number per line: the line `x` (counting from 0) holds the city to which the road
+    /// from the city `x` leads.
+    ParentArray,
+    /// Comma-separated values with the header `city,road_to` and one row per city.
+    /// The capital has a road to itself.
+    EdgeListCsv,
+    /// A Graphviz directed graph with an edge from every city to the next one on the way to
+    /// the capital. The capital is drawn as a double circle.
+    Dot,
+    /// A GraphML directed graph with the same edges as in `Dot` and a boolean `capital`
+    /// attribute on nodes.
+    GraphMl,
+}
+
+impl MapFormat {
+    /// Guesses the format from the file extension: `.txt`, `.csv`, `.dot`/`.gv`,
+    /// or `.graphml`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::formats::MapFormat;
+    ///
+    /// assert_eq!(MapFormat::from_path("maps/big.graphml"), Some(MapFormat::GraphMl));
+    /// assert_eq!(MapFormat::from_path("map.json"), None);
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<MapFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "txt" => Some(MapFormat::ParentArray),
+            "csv" => Some(MapFormat::EdgeListCsv),
+            "dot" | "gv" => Some(MapFormat::Dot),
+            "graphml" => Some(MapFormat::GraphMl),
+            _ => None,
+        }
+    }
+}
+
+/// An error which can occur while reading a city map.
+#[derive(Debug)]
+pub enum FormatError {
+    /// Reading from the underlying source failed.
+    Io(io::Error),
+    /// The text does not follow the format. Lines are counted from 1.
+    Syntax { line: usize, message: String },
+    /// The text follows the format, but describes an invalid city map.
+    InvalidMap(CityMapError),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Io(err) => write!(f, "i/o error: {}", err),
+            FormatError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            FormatError::InvalidMap(err) => write!(f, "invalid map: {}", err),
+        }
+    }
+}
+
+impl Error for FormatError {}
+
+impl From<io::Error> for FormatError {
+    fn from(err: io::Error) -> Self {
+        FormatError::Io(err)
+    }
+}
+
+impl From<CityMapError> for FormatError {
+    fn from(err: CityMapError) -> Self {
+        FormatError::InvalidMap(err)
+    }
+}
+
+fn syntax_error<T>(line: usize, message: String) -> Result<T, FormatError> {
+    Err(FormatError::Syntax { line, message })
+}
+
+fn parse_city(text: &str, line: usize) -> Result<usize, FormatError> {
+    text.trim()
+        .parse::<usize>()
+        .or_else(|_| syntax_error(line, format!("'{}' is not a city", text.trim())))
+}
+
+/// Writes the map in the given format.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::formats::*;
+///
+/// let mut out = Vec::new();
+/// write_map(&[1, 1, 1], MapFormat::EdgeListCsv, &mut out).unwrap();
+/// assert_eq!(String::from_utf8(out).unwrap(), "city,road_to\n0,1\n1,1\n2,1\n");
+/// ```
+pub fn write_map<W: Write>(
+    city_vec: &[usize],
+    format: MapFormat,
+    writer: &mut W,
+) -> io::Result<()> {
+    match format {
+        MapFormat::ParentArray => write_parent_array(city_vec, writer),
+        MapFormat::EdgeListCsv => write_csv(city_vec, writer),
+        MapFormat::Dot => write_dot(city_vec, writer),
+        MapFormat::GraphMl => write_graphml(city_vec, writer),
+    }
+}
+
+/// Reads a map in the given format and checks if it is valid.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::formats::*;
+///
+/// let dot = "digraph cities {\n  1 [shape=doublecircle];\n  0 -> 1;\n  2 -> 1;\n}\n";
+/// let map = read_map(MapFormat::Dot, dot.as_bytes()).unwrap();
+/// assert_eq!(map, vec![1, 1, 1]);
+/// ```
+pub fn read_map<R: BufRead>(format: MapFormat, reader: R) -> Result<Vec<usize>, FormatError> {
+    let city_vec = match format {
+        MapFormat::ParentArray => read_parent_array(reader)?,
+        MapFormat::EdgeListCsv => read_csv(reader)?,
+        MapFormat::Dot => read_dot(reader)?,
+        MapFormat::GraphMl => read_graphml(reader)?,
+    };
+    CityMap::validate(&city_vec)?;
+    Ok(city_vec)
+}
+
+fn write_parent_array<W: Write>(city_vec: &[usize], writer: &mut W) -> io::Result<()> {
+    for to in city_vec {
+        writeln!(writer, "{}", to)?;
+    }
+    Ok(())
+}
+
+// Empty lines and lines starting with '#' are skipped.
+fn read_parent_array<R: BufRead>(reader: R) -> Result<Vec<usize>, FormatError> {
+    let mut city_vec = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let text = line.trim();
+        if !text.is_empty() && !text.starts_with('#') {
+            city_vec.push(parse_city(text, index + 1)?);
+        }
+    }
+    Ok(city_vec)
+}
+
+fn write_csv<W: Write>(city_vec: &[usize], writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "city,road_to")?;
+    for (from, to) in city_vec.iter().enumerate() {
+        writeln!(writer, "{},{}", from, to)?;
+    }
+    Ok(())
+}
+
+// Rows may come in any order, but every city has to appear exactly once.
+fn read_csv<R: BufRead>(reader: R) -> Result<Vec<usize>, FormatError> {
+    let mut roads = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let text = line.trim();
+        if text.is_empty() || (index == 0 && text.starts_with("city")) {
+            continue;
+        }
+        let mut fields = text.split(',');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(from), Some(to), None) => roads.push((
+                parse_city(from, index + 1)?,
+                parse_city(to, index + 1)?,
+                index + 1,
+            )),
+            _ => {
+                return syntax_error(
+                    index + 1,
+                    format!("expected 'city,road_to', got '{}'", text),
+                )
+            }
+        }
+    }
+
+    let mut city_vec = vec![usize::MAX; roads.len()];
+    for (from, to, line) in roads {
+        if from >= city_vec.len() {
+            return syntax_error(line, format!("city {} is out of range", from));
+        } else if city_vec[from] != usize::MAX {
+            return syntax_error(line, format!("city {} has more than one road", from));
+        }
+        city_vec[from] = to;
+    }
+    Ok(city_vec)
+}
+
+fn write_dot<W: Write>(city_vec: &[usize], writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "digraph cities {{")?;
+    for (from, &to) in city_vec.iter().enumerate() {
+        if from == to {
+            writeln!(writer, "  {} [shape=doublecircle];", from)?;
+        }
+    }
+    for (from, &to) in city_vec.iter().enumerate() {
+        if from != to {
+            writeln!(writer, "  {} -> {};", from, to)?;
+        }
+    }
+    writeln!(writer, "}}")
+}
+
+// Builds the vector of roads from nodes and edges of a graph, given with their line numbers.
+// The cities are numbered from 0 to the highest node found, and a city without an outgoing edge
+// is a capital. Every city is a node or has an edge, so a node above their count is an error.
+fn roads_from_graph(
+    nodes: &[(usize, usize)],
+    edges: &[(usize, usize, usize)],
+) -> Result<Vec<usize>, FormatError> {
+    let count = nodes.len() + edges.len();
+    let ids = nodes.iter().cloned().chain(
+        edges
+            .iter()
+            .flat_map(|&(from, to, line)| vec![(from, line), (to, line)]),
+    );
+    let mut size = 0;
+    for (id, line) in ids {
+        if id >= count {
+            return syntax_error(line, format!("city {} is out of range", id));
+        }
+        size = size.max(id + 1);
+    }
+
+    let mut city_vec = vec![usize::MAX; size];
+    for &(from, to, line) in edges {
+        if city_vec[from] != usize::MAX {
+            return syntax_error(line, format!("city {} has more than one road", from));
+        }
+        city_vec[from] = to;
+    }
+    city_vec
+        .iter_mut()
+        .enumerate()
+        .filter(|(_, to)| **to == usize::MAX)
+        .for_each(|(city, to)| *to = city);
+    Ok(city_vec)
+}
+
+// Understands the subset of DOT written by `write_dot`: one statement per line, either a node
+// (`3;` or `3 [attributes];`) or an edge (`3 -> 4;`). Attributes are ignored.
+fn read_dot<R: BufRead>(reader: R) -> Result<Vec<usize>, FormatError> {
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let text = line.trim();
+        if text.is_empty()
+            || text.starts_with("//")
+            || text.starts_with('}')
+            || text.starts_with("digraph")
+        {
+            continue;
+        }
+        let statement = text.trim_end_matches(';');
+        let statement = match statement.find('[') {
+            Some(pos) => &statement[..pos],
+            None => statement,
+        };
+        match statement.find("->") {
+            Some(pos) => edges.push((
+                parse_city(&statement[..pos], index + 1)?,
+                parse_city(&statement[pos + 2..], index + 1)?,
+                index + 1,
+            )),
+            None => nodes.push((parse_city(statement, index + 1)?, index + 1)),
+        }
+    }
+    roads_from_graph(&nodes, &edges)
+}
+
+fn write_graphml<W: Write>(city_vec: &[usize], writer: &mut W) -> io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    writeln!(
+        writer,
+        r#"  <key id="capital" for="node" attr.name="capital" attr.type="boolean"><default>false</default></key>"#
+    )?;
+    writeln!(writer, r#"  <graph id="cities" edgedefault="directed">"#)?;
+    for (city, &to) in city_vec.iter().enumerate() {
+        if city == to {
+            writeln!(
+                writer,
+                r#"    <node id="n{}"><data key="capital">true</data></node>"#,
+                city
+            )?;
+        } else {
+            writeln!(writer, r#"    <node id="n{}"/>"#, city)?;
+        }
+    }
+    for (from, &to) in city_vec.iter().enumerate() {
+        if from != to {
+            writeln!(writer, r#"    <edge source="n{}" target="n{}"/>"#, from, to)?;
+        }
+    }
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")
+}
+
+// Returns the value of the XML attribute `name` in the tag.
+fn xml_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let end = tag[start..].find('"')? + start;
+    Some(&tag[start..end])
+}
+
+fn parse_node_id(id: &str, line: usize) -> Result<usize, FormatError> {
+    parse_city(id.trim_start_matches('n'), line)
+}
+
+// Understands GraphML with at most one `<node>` or `<edge>` tag per line, as written by
+// `write_graphml`. Node identifiers may be given as `n3` or `3`. Data attached to nodes
+// and edges is ignored.
+fn read_graphml<R: BufRead>(reader: R) -> Result<Vec<usize>, FormatError> {
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let text = line.trim();
+        if text.starts_with("<node") {
+            match xml_attribute(text, "id") {
+                Some(id) => nodes.push((parse_node_id(id, index + 1)?, index + 1)),
+                None => return syntax_error(index + 1, "a node without an id".to_string()),
+            }
+        } else if text.starts_with("<edge") {
+            match (xml_attribute(text, "source"), xml_attribute(text, "target")) {
+                (Some(from), Some(to)) => edges.push((
+                    parse_node_id(from, index + 1)?,
+                    parse_node_id(to, index + 1)?,
+                    index + 1,
+                )),
+                _ => {
+                    return syntax_error(
+                        index + 1,
+                        "an edge without a source or a target".to_string(),
+                    )
+                }
+            }
+        }
+    }
+    roads_from_graph(&nodes, &edges)
+}
@@ -0,0 +1,148 @@
+#[cfg(test)]
+mod formats_tests {
+    use crate::cities::formats::*;
+    use crate::cities::*;
+
+    use spectral::prelude::*;
+
+    const ALL_FORMATS: [MapFormat; 4] = [
+        MapFormat::ParentArray,
+        MapFormat::EdgeListCsv,
+        MapFormat::Dot,
+        MapFormat::GraphMl,
+    ];
+
+    fn write_to_string(city_vec: &[usize], format: MapFormat) -> String {
+        let mut out = Vec::new();
+        write_map(city_vec, format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn round_trip(city_vec: &[usize], format: MapFormat) -> Vec<usize> {
+        read_map(format, write_to_string(city_vec, format).as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn should_round_trip_generated_maps() {
+        let v = gen_cities(&CityGenConfig::new(1000));
+        for &format in ALL_FORMATS.iter() {
+            assert_that!(round_trip(&v, format)).is_equal_to(&v);
+        }
+    }
+
+    #[test]
+    fn should_round_trip_single_city() {
+        for &format in ALL_FORMATS.iter() {
+            assert_that!(round_trip(&[0], format)).is_equal_to(vec![0]);
+        }
+    }
+
+    quickcheck! {
+        fn round_trip_preserves_maps(size: usize, seed: u64) -> bool {
+            let mut rng = crate::utils::prng::seeded_rng(seed);
+            let v = gen_cities_with_rng(&CityGenConfig::new(size % 200 + 10), &mut rng);
+            CityMap::validate(&v).is_ok() && ALL_FORMATS.iter().all(|&format| round_trip(&v, format) == v)
+        }
+    }
+
+    #[test]
+    fn should_write_parent_array() {
+        assert_that!(write_to_string(&[1, 1, 1, 2], MapFormat::ParentArray))
+            .is_equal_to("1\n1\n1\n2\n".to_string());
+    }
+
+    #[test]
+    fn should_write_dot() {
+        assert_that!(write_to_string(&[1, 1, 1, 2], MapFormat::Dot)).is_equal_to(
+            "digraph cities {\n  1 [shape=doublecircle];\n  0 -> 1;\n  2 -> 1;\n  3 -> 2;\n}\n"
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn should_skip_comments_in_parent_array() {
+        let text = "# a small map\n1\n\n1\n  # the last one\n1\n";
+        assert_that!(read_map(MapFormat::ParentArray, text.as_bytes()).unwrap())
+            .is_equal_to(vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn should_read_csv_rows_in_any_order() {
+        let text = "city,road_to\n2,0\n0,0\n1,2\n";
+        assert_that!(read_map(MapFormat::EdgeListCsv, text.as_bytes()).unwrap())
+            .is_equal_to(vec![0, 2, 0]);
+    }
+
+    #[test]
+    fn should_read_graphml_with_plain_ids() {
+        let text =
+            "<graphml>\n<graph edgedefault=\"directed\">\n<node id=\"0\"/>\n<node id=\"1\"/>\n\
+                    <edge source=\"1\" target=\"0\"/>\n</graph>\n</graphml>\n";
+        assert_that!(read_map(MapFormat::GraphMl, text.as_bytes()).unwrap())
+            .is_equal_to(vec![0, 0]);
+    }
+
+    #[test]
+    fn should_report_syntax_errors_with_line() {
+        match read_map(MapFormat::ParentArray, "0\nzero\n".as_bytes()) {
+            Err(FormatError::Syntax { line, .. }) => assert_that!(line).is_equal_to(2),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match read_map(
+            MapFormat::EdgeListCsv,
+            "city,road_to\n0,0\n0,0\n".as_bytes(),
+        ) {
+            Err(FormatError::Syntax { line, .. }) => assert_that!(line).is_equal_to(3),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match read_map(
+            MapFormat::Dot,
+            "digraph cities {\n  1 -> 0;\n  1 -> 2;\n}\n".as_bytes(),
+        ) {
+            Err(FormatError::Syntax { line, .. }) => assert_that!(line).is_equal_to(3),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_reject_node_ids_out_of_range() {
+        for id in &["3000000000", "18446744073709551615"] {
+            let dot = format!("digraph cities {{\n  0;\n  1 -> 0;\n  {} -> 0;\n}}\n", id);
+            let graphml = format!(
+                "<graphml>\n<graph edgedefault=\"directed\">\n<node id=\"0\"/>\n\
+                 <node id=\"{}\"/>\n</graph>\n</graphml>\n",
+                id
+            );
+            for (format, text) in &[(MapFormat::Dot, dot), (MapFormat::GraphMl, graphml)] {
+                match read_map(*format, text.as_bytes()) {
+                    Err(FormatError::Syntax { line, message }) => {
+                        assert_that!(line).is_equal_to(4);
+                        assert_that!(message).contains("out of range");
+                    }
+                    other => panic!("unexpected result: {:?}", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_report_invalid_maps() {
+        match read_map(
+            MapFormat::Dot,
+            "digraph cities {\n  0 -> 1;\n  1 -> 0;\n}\n".as_bytes(),
+        ) {
+            Err(FormatError::InvalidMap(err)) => {
+                assert_that!(err).is_equal_to(CityMapError::NoCapital)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_guess_format_from_path() {
+        assert_that!(MapFormat::from_path("map.txt")).is_equal_to(Some(MapFormat::ParentArray));
+        assert_that!(MapFormat::from_path("map.CSV")).is_equal_to(Some(MapFormat::EdgeListCsv));
+        assert_that!(MapFormat::from_path("map.gv")).is_equal_to(Some(MapFormat::Dot));
+        assert_that!(MapFormat::from_path("map")).is_equal_to(None);
+    }
+}
@@ -0,0 +1,177 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+
+use crate::utils::prng::pseudo_normalize;
+
+/// Decides how many roads lead to a city from cities farther away from the capital.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Branching {
+    /// Every number from `0` to `max_roads - 1` is equally probable.
+    Uniform { max_roads: usize },
+    /// Every additional road is less probable than the previous one by the same ratio.
+    /// Most cities have few roads, but long tails are possible.
+    Geometric { mean: f64 },
+    /// Numbers from `0` to `max_roads - 1` drawn in the same way as by [`PseudoNormalRng`]:
+    /// the higher `a`, the more they concentrate around the middle of the range.
+    ///
+    /// [`PseudoNormalRng`]: ../utils/prng/struct.PseudoNormalRng.html
+    PseudoNormal { max_roads: usize, a: f64 },
+    /// Cities are added one by one, and each new city builds a road to an existing one with
+    /// the probability proportional to the number of roads already leading to it, plus one.
+    /// The number of roads follows a power law: a few huge travel centres and many small
+    /// cities. Travel centre settings of [`CityGenConfig`] are ignored.
+    ///
+    /// [`CityGenConfig`]: struct.CityGenConfig.html
+    PreferentialAttachment,
+}
+
+impl Branching {
+    // Draws the number of roads leading to the next city. Not used for `PreferentialAttachment`.
+    pub(crate) fn sample<R: Rng>(&self, r: &mut R) -> usize {
+        match *self {
+            Branching::Uniform { max_roads } => {
+                if max_roads > 1 {
+                    r.gen_range(0, max_roads)
+                } else {
+                    0
+                }
+            }
+            Branching::Geometric { mean } => {
+                // inverse transform of P(k) = p * (1 - p)^k, which has the mean (1 - p) / p
+                let p = 1.0 / (1.0 + mean.max(0.0));
+                let u: f64 = 1.0 - r.gen::<f64>();
+                (u.ln() / (1.0 - p).ln()).floor() as usize
+            }
+            Branching::PseudoNormal { max_roads, a } => {
+                (pseudo_normalize(r.gen::<f64>(), a) * max_roads as f64) as usize
+            }
+            Branching::PreferentialAttachment => 0,
+        }
+    }
+}
+
+/// An error returned when parsing a [`Branching`](enum.Branching.html) fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBranchingError(String);
+
+impl fmt::Display for ParseBranchingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid branching: {}", self.0)
+    }
+}
+
+impl Error for ParseBranchingError {}
+
+impl FromStr for Branching {
+    type Err = ParseBranchingError;
+
+    /// Parses `uniform:<max_roads>`, `geometric:<mean>`, `pseudo-normal:<max_roads>:<a>`,
+    /// or `preferential`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::Branching;
+    ///
+    /// assert_eq!("geometric:2.5".parse(), Ok(Branching::Geometric { mean: 2.5 }));
+    /// assert!("geometric".parse::<Branching>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseBranchingError(s.to_string());
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            ["uniform", max_roads] => Ok(Branching::Uniform {
+                max_roads: max_roads.parse().map_err(|_| error())?,
+            }),
+            ["geometric", mean] => Ok(Branching::Geometric {
+                mean: mean.parse().map_err(|_| error())?,
+            }),
+            ["pseudo-normal", max_roads, a] => Ok(Branching::PseudoNormal {
+                max_roads: max_roads.parse().map_err(|_| error())?,
+                a: a.parse().map_err(|_| error())?,
+            }),
+            ["preferential"] => Ok(Branching::PreferentialAttachment),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// Settings for [`gen_cities`](fn.gen_cities.html).
+///
+/// The fields can be set directly, or with the `with_` methods.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::*;
+///
+/// let config = CityGenConfig::new(1000)
+///     .with_branching(Branching::Geometric { mean: 1.5 })
+///     .with_target_depth(20);
+/// let city_vec = gen_cities(&config);
+/// assert_eq!(city_vec.len(), 1000);
+/// assert_eq!(find_city_distances(&city_vec).unwrap().len(), 21);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CityGenConfig {
+    /// The exact number of cities.
+    pub size: usize,
+    /// How many roads lead to a city.
+    pub branching: Branching,
+    /// The probability that a city becomes a travel centre and ignores `branching`.
+    pub centre_probability: f32,
+    /// The number of roads leading to a travel centre is drawn uniformly from
+    /// `0..centre_max_roads`.
+    pub centre_max_roads: usize,
+    /// If set, the farthest city is exactly this many roads away from the capital.
+    /// Has to be lower than `size`, and can be zero only if there is just one city.
+    pub target_depth: Option<usize>,
+}
+
+impl Default for CityGenConfig {
+    /// A "round map" of 25 000 cities with up to 4 roads leading to each, and one in ten cities
+    /// being a travel centre with up to 24 roads.
+    fn default() -> Self {
+        CityGenConfig {
+            size: 25_000,
+            branching: Branching::Uniform { max_roads: 5 },
+            centre_probability: 0.1,
+            centre_max_roads: 25,
+            target_depth: None,
+        }
+    }
+}
+
+impl CityGenConfig {
+    /// Creates the default config with the given number of cities.
+    pub fn new(size: usize) -> Self {
+        CityGenConfig {
+            size,
+            ..CityGenConfig::default()
+        }
+    }
+
+    pub fn with_branching(mut self, branching: Branching) -> Self {
+        self.branching = branching;
+        self
+    }
+
+    pub fn with_travel_centres(mut self, probability: f32, max_roads: usize) -> Self {
+        self.centre_probability = probability;
+        self.centre_max_roads = max_roads;
+        self
+    }
+
+    /// The same as `with_travel_centres(0.0, 0)`.
+    pub fn without_travel_centres(self) -> Self {
+        self.with_travel_centres(0.0, 0)
+    }
+
+    pub fn with_target_depth(mut self, depth: usize) -> Self {
+        self.target_depth = Some(depth);
+        self
+    }
+}
@@ -1,12 +1,56 @@
 use std;
 use std::cmp::min;
+use std::ops::Add;
 
 #[cfg(test)]
 mod cities_tests;
 
+pub mod analytics;
+#[cfg(test)]
+mod analytics_tests;
+
+pub mod city_map;
+#[cfg(test)]
+mod city_map_tests;
+
+pub mod formats;
+#[cfg(test)]
+mod formats_tests;
+
+pub mod network;
+#[cfg(test)]
+mod network_tests;
+
+#[cfg(feature = "parallel")]
+pub mod parallel;
+#[cfg(all(test, feature = "parallel"))]
+mod parallel_tests;
+
+pub mod progress;
+#[cfg(test)]
+mod progress_tests;
+
+pub mod relocation;
+#[cfg(test)]
+mod relocation_tests;
+
+pub mod stats;
+#[cfg(test)]
+mod stats_tests;
+
+pub mod uniform;
+#[cfg(test)]
+mod uniform_tests;
+
+pub use self::city_map::{CityMap, CityMapError, MutationError};
+
+mod gen_config;
+pub use self::gen_config::{Branching, CityGenConfig, ParseBranchingError};
+pub use self::progress::{Cancelled, Monitor, SolveError};
+
 extern crate rand;
 
-use self::rand::{Rng, ThreadRng};
+use self::rand::Rng;
 
 use crate::utils::uset::USet;
 use std::collections::HashSet;
@@ -18,24 +62,58 @@ use std::collections::HashSet;
 ///
 /// * `city_vec` - A vector describing the map of cities.
 ///   `city_vec[x] == y` means that there is a road from the city `x` to the city `y`.
-///   We assume there are no cycles (ie. the map is a tree) and there is exactly one "capital" such
+///   There must be no cycles (ie. the map is a tree) and exactly one "capital" such
 ///   that `city_vec[capital] == capital`.
 ///
+/// # Errors
+///
+/// Returns an error describing the offending cities if `city_vec` is not a valid map.
+/// See [`CityMap::validate`].
+///
 /// # Example
 ///
 /// ```
 /// use rust_experiments::cities::*;
 /// let city_vec = vec![9, 3, 2, 2, 2, 2, 1, 5, 2, 1];
 /// let distances = find_city_distances(&city_vec);
-/// assert_eq!(distances, vec![1, 4, 2, 2, 1]);
+/// assert_eq!(distances, Ok(vec![1, 4, 2, 2, 1]));
+///
+/// let city_vec = vec![1, 2, 0];
+/// assert_eq!(find_city_distances(&city_vec), Err(CityMapError::NoCapital));
 /// ```
-pub fn find_city_distances(city_vec: &[usize]) -> Vec<usize> {
-    let (capital, ..) = city_vec.iter().enumerate().find(|&(i, &c)| i == c).unwrap();
-    let mut distance_map = vec![std::usize::MAX; city_vec.len()];
+///
+/// [`CityMap::validate`]: city_map/struct.CityMap.html#method.validate
+pub fn find_city_distances(city_vec: &[usize]) -> Result<Vec<usize>, CityMapError> {
+    find_city_distances_monitored(city_vec, &mut Monitor::new()).map_err(|err| match err {
+        SolveError::InvalidMap(err) => err,
+        SolveError::Cancelled => unreachable!("a monitor without a token cannot cancel"),
+    })
+}
+
+/// Works like [`find_city_distances`], but reports the number of solved cities to the monitor,
+/// and stops if it is cancelled. The map is validated before any progress is reported.
+///
+/// # Errors
+///
+/// Returns [`SolveError::InvalidMap`] if `city_vec` is not a valid map, and
+/// [`SolveError::Cancelled`] if the monitor's token is cancelled.
+///
+/// [`find_city_distances`]: fn.find_city_distances.html
+/// [`SolveError::InvalidMap`]: progress/enum.SolveError.html#variant.InvalidMap
+/// [`SolveError::Cancelled`]: progress/enum.SolveError.html#variant.Cancelled
+pub fn find_city_distances_monitored(
+    city_vec: &[usize],
+    monitor: &mut Monitor,
+) -> Result<Vec<usize>, SolveError> {
+    monitor.restart();
+    let capital = CityMap::validate(city_vec)?;
+    let mut distance_map = vec![usize::MAX; city_vec.len()];
     distance_map[capital] = 0;
 
+    let mut path = Vec::new();
     for i in 0..city_vec.len() {
-        update_distance_map(i, city_vec, &mut distance_map);
+        update_distance_map(i, city_vec, &mut distance_map, &mut path);
+        monitor.step(i + 1, city_vec.len())?;
     }
 
     let &max_distance = distance_map.iter().max().unwrap();
@@ -47,78 +125,310 @@ pub fn find_city_distances(city_vec: &[usize]) -> Vec<usize> {
                 acc
             });
 
-    result
+    Ok(result)
 }
 
-fn update_distance_map(i: usize, city_vec: &[usize], distance_map: &mut [usize]) {
+/// Per-city results of solving a city map with weighted roads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CityDistances<W> {
+    /// `hops[x]` is the number of roads between the city `x` and the capital.
+    pub hops: Vec<usize>,
+    /// `distances[x]` is the sum of the weights of all roads between the city `x` and the capital.
+    pub distances: Vec<W>,
+    /// `predecessors[x]` is the city visited just before `x` on the way from the capital,
+    /// or `None` if `x` is the capital.
+    pub predecessors: Vec<Option<usize>>,
+}
+
+impl<W> CityDistances<W> {
+    /// Returns the same histogram as [`find_city_distances`]: indexes are the numbers of roads
+    /// from the capital and the values are the numbers of cities at the given distance.
+    ///
+    /// [`find_city_distances`]: fn.find_city_distances.html
+    pub fn histogram(&self) -> Vec<usize> {
+        let max_hops = self.hops.iter().cloned().max().unwrap_or(0);
+        self.hops
+            .iter()
+            .fold(vec![0; max_hops + 1], |mut acc, &hops| {
+                acc[hops] += 1;
+                acc
+            })
+    }
+}
+
+/// Calculates distances from the capital to every city, where every road has its own weight
+/// (e.g. the travel cost).
+///
+/// # Arguments
+///
+/// * `city_vec` - A vector describing the map of cities, as in [`find_city_distances`].
+/// * `weights` - A slice parallel to `city_vec`: `weights[x]` is the weight of the road from
+///   the city `x` to the city `city_vec[x]`. The weight at the capital's index is ignored.
+///
+/// # Errors
+///
+/// Returns an error if `city_vec` is not a valid map, as [`find_city_distances`] does.
+///
+/// # Panics
+///
+/// Panics if `weights` and `city_vec` have different lengths.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::*;
+/// let city_vec = vec![1, 1, 1, 2];
+/// let weights = vec![5, 0, 2, 3];
+/// let result = find_weighted_city_distances(&city_vec, &weights).unwrap();
+/// assert_eq!(result.distances, vec![5, 0, 2, 5]);
+/// assert_eq!(result.predecessors, vec![Some(1), None, Some(1), Some(2)]);
+/// assert_eq!(result.histogram(), vec![1, 2, 1]);
+/// ```
+///
+/// [`find_city_distances`]: fn.find_city_distances.html
+pub fn find_weighted_city_distances<W>(
+    city_vec: &[usize],
+    weights: &[W],
+) -> Result<CityDistances<W>, CityMapError>
+where
+    W: Copy + Default + Add<Output = W>,
+{
+    assert_eq!(
+        city_vec.len(),
+        weights.len(),
+        "there must be one weight for every city"
+    );
+    find_weighted_city_distances_by(city_vec, |city| weights[city])
+}
+
+/// Works like [`find_weighted_city_distances`], but the weight of the road from the city `x`
+/// to the city `city_vec[x]` is computed by calling `weight(x)`.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::*;
+/// let city_vec = vec![1, 1, 1, 2];
+/// let result = find_weighted_city_distances_by(&city_vec, |city| city as f64 * 0.5).unwrap();
+/// assert_eq!(result.distances, vec![0.0, 0.0, 1.0, 2.5]);
+/// ```
+///
+/// [`find_weighted_city_distances`]: fn.find_weighted_city_distances.html
+pub fn find_weighted_city_distances_by<W, F>(
+    city_vec: &[usize],
+    weight: F,
+) -> Result<CityDistances<W>, CityMapError>
+where
+    W: Copy + Default + Add<Output = W>,
+    F: Fn(usize) -> W,
+{
+    let capital = CityMap::validate(city_vec)?;
+    let mut hops = vec![usize::MAX; city_vec.len()];
+    let mut distances = vec![W::default(); city_vec.len()];
+    hops[capital] = 0;
+
     let mut path = Vec::new();
-    let found_distance = find_path(i, city_vec, distance_map, &mut path);
+    for i in 0..city_vec.len() {
+        let mut city = i;
+        while hops[city] == usize::MAX {
+            path.push(city);
+            city = city_vec[city];
+        }
+        while let Some(city) = path.pop() {
+            let next = city_vec[city];
+            hops[city] = hops[next] + 1;
+            distances[city] = distances[next] + weight(city);
+        }
+    }
+
+    let predecessors = city_vec
+        .iter()
+        .enumerate()
+        .map(|(city, &next)| if city == next { None } else { Some(next) })
+        .collect();
+
+    Ok(CityDistances {
+        hops,
+        distances,
+        predecessors,
+    })
+}
+
+// `path` is only a buffer, reused between calls to avoid allocating a new vector for every city.
+fn update_distance_map(
+    i: usize,
+    city_vec: &[usize],
+    distance_map: &mut [usize],
+    path: &mut Vec<usize>,
+) {
+    let found_distance = find_path(i, city_vec, distance_map, path);
     path.iter()
         .enumerate()
         .for_each(|(path_index, &city_index)| {
             distance_map[city_index] = found_distance + path.len() - path_index
-        })
+        });
+    path.clear();
 }
 
+// Follows the roads from the city `i` until it finds a city with an already known distance.
+// All cities visited on the way are pushed to `path`, so that their distances can be set
+// afterwards (path compression): no city is visited twice during the whole solving.
 fn find_path(
     i: usize,
     city_array: &[usize],
     distance_map: &[usize],
     path: &mut Vec<usize>,
 ) -> usize {
-    if distance_map[i] == std::usize::MAX {
-        path.push(i);
-        find_path(city_array[i], city_array, distance_map, path) // should be tailrec
-    } else {
-        distance_map[i]
+    let mut city = i;
+    while distance_map[city] == usize::MAX {
+        path.push(city);
+        city = city_array[city];
     }
+    distance_map[city]
 }
 
-fn gen_unshuffled(
-    size: usize,
-    max_broad: usize,
-    knots_occurence: f32,
-    knots_max_broad: usize,
-) -> Vec<usize> {
-    debug_assert!(size > 0);
-    debug_assert!(max_broad <= size);
-    debug_assert!(knots_occurence >= 0.0);
-    debug_assert!(knots_occurence <= 1.0);
-    debug_assert!(knots_max_broad >= max_broad);
+// Generates a map where the capital is 0 and every city has a lower number than the cities leading
+// to it.
+fn gen_unshuffled<R: Rng>(
+    config: &CityGenConfig,
+    r: &mut R,
+    monitor: &mut Monitor,
+) -> Result<Vec<usize>, Cancelled> {
+    let size = config.size;
+    assert!(size > 0, "a map needs at least one city");
+    if let Some(depth) = config.target_depth {
+        assert!(
+            depth < size && (depth > 0 || size == 1),
+            "cannot reach the depth {} with {} cities",
+            depth,
+            size
+        );
+    }
+    debug_assert!(config.centre_probability >= 0.0);
+    debug_assert!(config.centre_probability <= 1.0);
 
+    let max_depth = config.target_depth.unwrap_or(usize::MAX);
     let mut city_vec = Vec::with_capacity(size);
-    let mut r = rand::thread_rng();
-
+    let mut depths = Vec::with_capacity(size);
     city_vec.push(0);
+    depths.push(0);
+
+    // a single road from the capital to the target depth, so that it is reached for sure
+    for city in 1..=config.target_depth.unwrap_or(0) {
+        city_vec.push(city - 1);
+        depths.push(city);
+    }
 
+    match config.branching {
+        Branching::PreferentialAttachment => {
+            grow_preferentially(&mut city_vec, &mut depths, size, max_depth, r, monitor)?
+        }
+        branching => grow_by_branching(
+            &mut city_vec,
+            &mut depths,
+            config,
+            branching,
+            max_depth,
+            r,
+            monitor,
+        )?,
+    }
+    Ok(city_vec)
+}
+
+// Visits the cities in the order they were added and adds the roads leading to each of them.
+fn grow_by_branching<R: Rng>(
+    city_vec: &mut Vec<usize>,
+    depths: &mut Vec<usize>,
+    config: &CityGenConfig,
+    branching: Branching,
+    max_depth: usize,
+    r: &mut R,
+    monitor: &mut Monitor,
+) -> Result<(), Cancelled> {
     let mut index: usize = 0;
-    while city_vec.len() < size {
-        let min_broad = if index == city_vec.len() - 1 { 1 } else { 0 };
-        let b = if r.gen_range(0.0, 1.0) < knots_occurence {
-            knots_max_broad
+    while city_vec.len() < config.size {
+        if index == city_vec.len() {
+            // all remaining cities are at the target depth: extend any city closer to the capital,
+            // and visit the new one next
+            let to = loop {
+                let city = r.gen_range(0, city_vec.len());
+                if depths[city] < max_depth {
+                    break city;
+                }
+            };
+            city_vec.push(to);
+            depths.push(depths[to] + 1);
+            monitor.step(city_vec.len(), config.size)?;
+            continue;
+        }
+
+        let roads = if r.gen_range(0.0, 1.0) < config.centre_probability {
+            Branching::Uniform {
+                max_roads: config.centre_max_roads,
+            }
+            .sample(r)
         } else {
-            max_broad
+            branching.sample(r)
         };
 
-        let max_b = min(b, size - city_vec.len());
-        let s = if min_broad < max_b {
-            r.gen_range(min_broad, max_b)
+        let roads = if depths[index] >= max_depth {
+            0
+        } else if index == city_vec.len() - 1 {
+            // the last city has to keep the map growing
+            roads.max(1)
         } else {
-            min_broad
+            roads
         };
 
-        for _i in 0..s {
+        for _i in 0..min(roads, config.size - city_vec.len()) {
             city_vec.push(index);
+            depths.push(depths[index] + 1);
         }
+        monitor.step(city_vec.len(), config.size)?;
 
         index += 1;
     }
-    city_vec
+    Ok(())
 }
 
-fn city_swap(city_vec: &mut Vec<usize>, from: usize, to: usize) {
+// Every city may be chosen once for itself and once more for every road leading to it,
+// unless it is already at the maximum depth.
+fn grow_preferentially<R: Rng>(
+    city_vec: &mut Vec<usize>,
+    depths: &mut Vec<usize>,
+    size: usize,
+    max_depth: usize,
+    r: &mut R,
+    monitor: &mut Monitor,
+) -> Result<(), Cancelled> {
+    let mut candidates = Vec::with_capacity(2 * size);
+    for city in 0..city_vec.len() {
+        if depths[city] < max_depth {
+            candidates.push(city);
+        }
+        if city > 0 {
+            candidates.push(city_vec[city]);
+        }
+    }
+
+    while city_vec.len() < size {
+        let to = candidates[r.gen_range(0, candidates.len())];
+        let city = city_vec.len();
+        city_vec.push(to);
+        depths.push(depths[to] + 1);
+        candidates.push(to);
+        if depths[city] < max_depth {
+            candidates.push(city);
+        }
+        monitor.step(city_vec.len(), size)?;
+    }
+    Ok(())
+}
+
+fn city_swap(city_vec: &mut [usize], from: usize, to: usize) {
     city_vec.swap(from, to);
-    for v in city_vec.iter_mut().skip(1) {
+    for v in city_vec.iter_mut() {
         if *v == from {
             *v = to
         } else if *v == to {
@@ -127,58 +437,151 @@ fn city_swap(city_vec: &mut Vec<usize>, from: usize, to: usize) {
     }
 }
 
-fn shuffle_cities(mut city_vec: &mut Vec<usize>) {
-    debug_assert!(city_vec.len() > 1);
-    let mut r = rand::thread_rng();
+/// Gives every city a new number: the city `x` becomes `labels[x]`. The roads stay the same,
+/// so the distances histogram does not change.
+///
+/// # Panics
+///
+/// Panics if `labels` and `city_vec` have different lengths.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::*;
+/// let city_vec = vec![0, 0, 1];
+/// assert_eq!(relabel_cities(&city_vec, &[2, 0, 1]), vec![2, 0, 2]);
+/// ```
+pub fn relabel_cities(city_vec: &[usize], labels: &[usize]) -> Vec<usize> {
+    assert_eq!(
+        city_vec.len(),
+        labels.len(),
+        "there must be one label for every city"
+    );
+    let mut relabelled = vec![0; city_vec.len()];
+    for (city, &to) in city_vec.iter().enumerate() {
+        relabelled[labels[city]] = labels[to];
+    }
+    relabelled
+}
+
+/// Gives the cities new random numbers, including the capital, in O(n): draws a random
+/// permutation of all numbers with the Fisher-Yates shuffle and applies it with
+/// [`relabel_cities`].
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::*;
+/// let mut city_vec = vec![0, 0, 1, 2];
+/// shuffle_cities(&mut city_vec, &mut rand::thread_rng());
+/// assert_eq!(find_city_distances(&city_vec), Ok(vec![1, 1, 1, 1]));
+/// ```
+///
+/// [`relabel_cities`]: fn.relabel_cities.html
+pub fn shuffle_cities<R: Rng>(city_vec: &mut [usize], r: &mut R) {
+    let mut labels: Vec<usize> = (0..city_vec.len()).collect();
+    r.shuffle(&mut labels);
+    let relabelled = relabel_cities(city_vec, &labels);
+    city_vec.copy_from_slice(&relabelled);
+}
 
-    for _i in 1..(city_vec.len() / 2 - 1) {
+/// The previous version of [`shuffle_cities`], kept for comparison in benchmarks.
+/// Swaps random pairs of cities, rescanning all roads after every swap, which takes O(n²).
+/// The capital is moved away from the city 0 at the end.
+///
+/// [`shuffle_cities`]: fn.shuffle_cities.html
+pub fn shuffle_cities_by_swapping<R: Rng>(city_vec: &mut [usize], r: &mut R) {
+    if city_vec.len() < 2 {
+        return;
+    }
+
+    for _i in 1..(city_vec.len() / 2).saturating_sub(1) {
         let from = r.gen_range(1, city_vec.len());
         let to = r.gen_range(1, city_vec.len());
         if from != to {
-            city_swap(&mut city_vec, from, to);
+            city_swap(city_vec, from, to);
         }
     }
 
     let capital_switch = r.gen_range(1, city_vec.len());
-    city_swap(&mut city_vec, 0, capital_switch);
+    city_swap(city_vec, 0, capital_switch);
 }
 
-/// Generates a city map.
+/// Generates a city map as described by the config.
 ///
-/// By default creates a very "round map" with every city having roads to on average the same number
-/// of other cities. As the result, the computed distances vector is short and with values growing
-/// with each index until the penultimate. To make the map more interesting, the user may set
-/// `max_roads` to a very low number and instead allow for big "travel centres" with larger number
-/// of roads.
+/// The default config creates a very "round map" with every city having roads to on average
+/// the same number of other cities. As the result, the computed distances vector is short and with
+/// values growing with each index until the penultimate. To make the map more interesting,
+/// choose another [`Branching`], set few roads per city but allow for big "travel centres"
+/// with a larger number of roads, or set the target depth.
 ///
-/// # Arguments
-/// * `size` - the total number of cities
-/// * `max_roads` - the maximum number of roads - 1 from one city to others
-/// * `travel_centre_possibility` - the probability of the city having more than `max_roads`
-/// * `centre_max_roads` - the maximum number of roads - 1 for the travel centre
+/// # Panics
+///
+/// Panics if `config.size` is zero, or if the target depth cannot be reached with
+/// `config.size` cities.
 ///
 /// # Example
 ///
 /// ```
 /// use rust_experiments::cities::*;
-/// let city_vec = gen_cities(10, 2, 0.2, 4);
+/// let config = CityGenConfig::new(10)
+///     .with_branching(Branching::Uniform { max_roads: 2 })
+///     .with_travel_centres(0.2, 4);
+/// let city_vec = gen_cities(&config);
 /// assert_eq!(city_vec.len(), 10);
 /// ```
-pub fn gen_cities(
-    size: usize,
-    max_roads: usize,
-    travel_centre_possibility: f32,
-    centre_max_roads: usize,
-) -> Vec<usize> {
-    let mut city_vec = gen_unshuffled(size, max_roads, travel_centre_possibility, centre_max_roads);
-    shuffle_cities(&mut city_vec);
-    city_vec
+///
+/// [`Branching`]: enum.Branching.html
+pub fn gen_cities(config: &CityGenConfig) -> Vec<usize> {
+    gen_cities_with_rng(config, &mut rand::thread_rng())
+}
+
+/// Works like [`gen_cities`], but takes all random numbers from the given generator.
+/// Two calls with generators in the same state produce the same map.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::*;
+/// use rust_experiments::utils::prng::seeded_rng;
+///
+/// let config = CityGenConfig::new(100);
+/// let city_vec1 = gen_cities_with_rng(&config, &mut seeded_rng(42));
+/// let city_vec2 = gen_cities_with_rng(&config, &mut seeded_rng(42));
+/// assert_eq!(city_vec1, city_vec2);
+/// ```
+///
+/// [`gen_cities`]: fn.gen_cities.html
+pub fn gen_cities_with_rng<R: Rng>(config: &CityGenConfig, rng: &mut R) -> Vec<usize> {
+    gen_cities_monitored(config, rng, &mut Monitor::new())
+        .expect("a monitor without a token cannot cancel")
+}
+
+/// Works like [`gen_cities_with_rng`], but reports the number of generated cities to
+/// the monitor, and stops if it is cancelled.
+///
+/// # Errors
+///
+/// Returns [`Cancelled`] if the monitor's token is cancelled.
+///
+/// [`gen_cities_with_rng`]: fn.gen_cities_with_rng.html
+/// [`Cancelled`]: progress/struct.Cancelled.html
+pub fn gen_cities_monitored<R: Rng>(
+    config: &CityGenConfig,
+    rng: &mut R,
+    monitor: &mut Monitor,
+) -> Result<Vec<usize>, Cancelled> {
+    monitor.restart();
+    let mut city_vec = gen_unshuffled(config, rng, monitor)?;
+    shuffle_cities(&mut city_vec, rng);
+    Ok(city_vec)
 }
 
 /// Generates a city map.
 ///
 /// Creates more "snaky" maps than `gen_cities`. The computed distances vectors tend to be long and
-/// with low values at each index. Slower, and more complex. Uses `USet`.
+/// with low values at each index. Uses `USet` as the pool of cities not on the map yet, from which
+/// new cities are popped at random positions, which takes O(n log n) in total.
 ///
 /// # Arguments
 /// * `size` - the total number of cities
@@ -193,36 +596,65 @@ pub fn gen_cities(
 /// assert_eq!(city_vec.len(), 10);
 /// ```
 pub fn gen_cities_uset(size: usize, max_roads_per_distance: usize) -> Vec<usize> {
+    gen_cities_uset_with_rng(size, max_roads_per_distance, &mut rand::thread_rng())
+}
+
+/// Works like [`gen_cities_uset`], but takes all random numbers from the given generator.
+///
+/// [`gen_cities_uset`]: fn.gen_cities_uset.html
+pub fn gen_cities_uset_with_rng<R: Rng>(
+    size: usize,
+    max_roads_per_distance: usize,
+    r: &mut R,
+) -> Vec<usize> {
+    gen_cities_uset_monitored(size, max_roads_per_distance, r, &mut Monitor::new())
+        .expect("a monitor without a token cannot cancel")
+}
+
+/// Works like [`gen_cities_uset_with_rng`], but reports the number of generated cities to
+/// the monitor, and stops if it is cancelled.
+///
+/// # Errors
+///
+/// Returns [`Cancelled`] if the monitor's token is cancelled.
+///
+/// [`gen_cities_uset_with_rng`]: fn.gen_cities_uset_with_rng.html
+/// [`Cancelled`]: progress/struct.Cancelled.html
+pub fn gen_cities_uset_monitored<R: Rng>(
+    size: usize,
+    max_roads_per_distance: usize,
+    r: &mut R,
+    monitor: &mut Monitor,
+) -> Result<Vec<usize>, Cancelled> {
+    monitor.restart();
     let mut city_vec = Vec::with_capacity(size);
-    let mut r = rand::thread_rng();
 
-    let all_cities = USet::from(0..size);
+    let mut free_cities = USet::from(0..size);
     let capital = r.gen_range(0, size);
+    free_cities.remove(capital);
     city_vec.push((capital, capital));
 
     while city_vec.len() < size {
         let (city, ..) = city_vec[city_vec.len() - 1];
         let high = min(max_roads_per_distance, size - city_vec.len());
         let new_cities = r.gen_range(0, high) + 1;
-
-        let used_cities: USet = city_vec.iter().map(|&(x, _)| x).collect();
-        let mut free_cities = &all_cities - &used_cities;
         let max_cities = min(new_cities, free_cities.len());
 
         for _i in 0..max_cities {
-            let new_city = pop_random(&mut free_cities, &mut r).unwrap();
+            let new_city = pop_random(&mut free_cities, r).unwrap();
             city_vec.push((new_city, city));
         }
+        monitor.step(city_vec.len(), size)?;
     }
 
     let mut city_array = vec![0; size];
     city_vec
         .iter()
         .for_each(|&(from, to)| city_array[from] = to);
-    city_array
+    Ok(city_array)
 }
 
-fn pop_random(set: &mut USet, rnd: &mut ThreadRng) -> Option<usize> {
+fn pop_random<R: Rng>(set: &mut USet, rnd: &mut R) -> Option<usize> {
     if !set.is_empty() {
         let index = rnd.gen_range(0, set.len());
         set.pop(index)
@@ -234,36 +666,69 @@ fn pop_random(set: &mut USet, rnd: &mut ThreadRng) -> Option<usize> {
 /// Generates a city map.
 ///
 /// Same as `gen_cities_uset` but uses `std::collections::HashSet` instead of `USet`.
-/// Implemented for performance comparison.
+/// Implemented for performance comparison. A `HashSet` cannot be indexed, so random cities are
+/// drawn until a free one is found: about n / k draws when k cities are free, which adds up to
+/// O(n log n) on average.
 pub fn gen_cities_hashset(size: usize, max_roads_per_distance: usize) -> Vec<usize> {
-    let mut city_vec = Vec::with_capacity(size);
-    let mut r = rand::thread_rng();
+    gen_cities_hashset_with_rng(size, max_roads_per_distance, &mut rand::thread_rng())
+}
+
+/// Works like [`gen_cities_hashset`], but takes all random numbers from the given generator.
+///
+/// [`gen_cities_hashset`]: fn.gen_cities_hashset.html
+pub fn gen_cities_hashset_with_rng<R: Rng>(
+    size: usize,
+    max_roads_per_distance: usize,
+    r: &mut R,
+) -> Vec<usize> {
+    gen_cities_hashset_monitored(size, max_roads_per_distance, r, &mut Monitor::new())
+        .expect("a monitor without a token cannot cancel")
+}
 
-    let all_cities: HashSet<usize> = (0..size).collect();
+/// Works like [`gen_cities_hashset_with_rng`], but reports the number of generated cities to
+/// the monitor, and stops if it is cancelled.
+///
+/// # Errors
+///
+/// Returns [`Cancelled`] if the monitor's token is cancelled.
+///
+/// [`gen_cities_hashset_with_rng`]: fn.gen_cities_hashset_with_rng.html
+/// [`Cancelled`]: progress/struct.Cancelled.html
+pub fn gen_cities_hashset_monitored<R: Rng>(
+    size: usize,
+    max_roads_per_distance: usize,
+    r: &mut R,
+    monitor: &mut Monitor,
+) -> Result<Vec<usize>, Cancelled> {
+    monitor.restart();
+    let mut city_vec = Vec::with_capacity(size);
 
+    let mut free_cities: HashSet<usize> = (0..size).collect();
     let capital = r.gen_range(0, size);
+    free_cities.remove(&capital);
     city_vec.push((capital, capital));
 
     while city_vec.len() < size {
         let (city, ..) = city_vec[city_vec.len() - 1];
         let high = min(max_roads_per_distance, size - city_vec.len());
         let new_cities = r.gen_range(0, high) + 1;
-
-        let used_cities: HashSet<usize> = city_vec.iter().map(|&(x, _)| x).collect();
-        let mut free_cities = &all_cities - &used_cities;
         let max_cities = min(new_cities, free_cities.len());
 
         for _i in 0..max_cities {
-            let remove_index = r.gen_range(0, free_cities.len());
-            let &new_city = free_cities.iter().nth(remove_index).unwrap();
-            free_cities.remove(&new_city);
+            let new_city = loop {
+                let candidate = r.gen_range(0, size);
+                if free_cities.remove(&candidate) {
+                    break candidate;
+                }
+            };
             city_vec.push((new_city, city));
         }
+        monitor.step(city_vec.len(), size)?;
     }
 
     let mut city_array = vec![0; size];
     city_vec
         .iter()
         .for_each(|&(from, to)| city_array[from] = to);
-    city_array
+    Ok(city_array)
 }
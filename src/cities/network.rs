@@ -0,0 +1,335 @@
+//! Road networks: arbitrary graphs of cities, which may have cycles, many roads from one city,
+//! or no roads at all.
+//!
+//! A city map can be turned into a network with [`RoadNetwork::from_city_map`], and then
+//! extended with more roads, e.g. with [`gen_road_network`].
+//!
+//! [`RoadNetwork::from_city_map`]: struct.RoadNetwork.html#method.from_city_map
+//! [`gen_road_network`]: fn.gen_road_network.html
+
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use super::{gen_cities_with_rng, CityGenConfig};
+use crate::utils::umap::UMap;
+use crate::utils::uset::USet;
+
+/// A graph of cities connected with roads, either one-way (directed) or two-way (undirected).
+/// Cities are identified by numbers, which do not have to be continuous.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::network::RoadNetwork;
+///
+/// let mut network = RoadNetwork::undirected();
+/// network.add_road(1, 2);
+/// network.add_road(2, 3);
+/// network.add_road(3, 1);
+/// network.add_city(7);
+/// assert_eq!(network.city_count(), 4);
+/// assert_eq!(network.road_count(), 3);
+/// assert_eq!(network.distance_histogram(1), vec![1, 2]);
+/// assert_eq!(network.components().len(), 2);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoadNetwork {
+    directed: bool,
+    // both maps contain every city, even if no roads start or end in it
+    outgoing: UMap<Vec<usize>>,
+    incoming: UMap<Vec<usize>>,
+    road_count: usize,
+}
+
+fn roads_mut(roads: &mut UMap<Vec<usize>>, city: usize) -> &mut Vec<usize> {
+    if !roads.contains(city) {
+        roads.put(city, Vec::new());
+    }
+    roads.get_ref_mut(city).unwrap()
+}
+
+fn remove_road_from(roads: &mut UMap<Vec<usize>>, from: usize, to: usize) {
+    if let Some(list) = roads.get_ref_mut(from) {
+        if let Some(pos) = list.iter().position(|&c| c == to) {
+            list.remove(pos);
+        }
+    }
+}
+
+impl RoadNetwork {
+    /// Creates an empty network of one-way roads.
+    pub fn directed() -> Self {
+        RoadNetwork {
+            directed: true,
+            outgoing: UMap::new(),
+            incoming: UMap::new(),
+            road_count: 0,
+        }
+    }
+
+    /// Creates an empty network of two-way roads.
+    pub fn undirected() -> Self {
+        RoadNetwork {
+            directed: false,
+            ..RoadNetwork::directed()
+        }
+    }
+
+    /// Creates a network with the same roads as the city map. In a directed network the roads
+    /// lead towards the capital, as on the map. The vector does not have to be a valid map:
+    /// cycles and many capitals are allowed, but every road has to lead to a city on the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    /// use rust_experiments::cities::network::RoadNetwork;
+    ///
+    /// let city_vec = vec![9, 3, 2, 2, 2, 2, 1, 5, 2, 1];
+    /// let network = RoadNetwork::from_city_map(&city_vec, true);
+    /// assert_eq!(network.distance_histogram(2), find_city_distances(&city_vec).unwrap());
+    /// ```
+    pub fn from_city_map(city_vec: &[usize], directed: bool) -> Self {
+        let mut network = if directed {
+            RoadNetwork::directed()
+        } else {
+            RoadNetwork::undirected()
+        };
+        for (from, &to) in city_vec.iter().enumerate() {
+            network.add_city(from);
+            network.add_road(from, to);
+        }
+        network
+    }
+
+    /// Returns `true` if the roads are one-way.
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    /// Adds a city with no roads. Does nothing if the city is already in the network.
+    pub fn add_city(&mut self, city: usize) {
+        roads_mut(&mut self.outgoing, city);
+        roads_mut(&mut self.incoming, city);
+    }
+
+    /// Adds a road, and both cities if they are not in the network yet. Returns `false` if
+    /// the road already exists, or if it would lead from a city to itself.
+    pub fn add_road(&mut self, from: usize, to: usize) -> bool {
+        self.add_city(from);
+        self.add_city(to);
+        if from == to || self.has_road(from, to) {
+            return false;
+        }
+        roads_mut(&mut self.outgoing, from).push(to);
+        roads_mut(&mut self.incoming, to).push(from);
+        if !self.directed {
+            roads_mut(&mut self.outgoing, to).push(from);
+            roads_mut(&mut self.incoming, from).push(to);
+        }
+        self.road_count += 1;
+        true
+    }
+
+    /// Removes a road. Returns `false` if there was no such road. The cities stay in the network
+    /// and the other roads keep their order.
+    pub fn remove_road(&mut self, from: usize, to: usize) -> bool {
+        if !self.has_road(from, to) {
+            return false;
+        }
+        remove_road_from(&mut self.outgoing, from, to);
+        remove_road_from(&mut self.incoming, to, from);
+        if !self.directed {
+            remove_road_from(&mut self.outgoing, to, from);
+            remove_road_from(&mut self.incoming, from, to);
+        }
+        self.road_count -= 1;
+        true
+    }
+
+    /// Returns `true` if there is a road from `from` to `to`. In an undirected network,
+    /// the same as a road from `to` to `from`.
+    pub fn has_road(&self, from: usize, to: usize) -> bool {
+        self.roads_from(from).contains(&to)
+    }
+
+    /// Returns the cities to which roads lead from `city`, in the order the roads were added.
+    pub fn roads_from(&self, city: usize) -> &[usize] {
+        self.outgoing.get_ref(city).map_or(&[], |roads| roads)
+    }
+
+    /// Returns the cities from which roads lead to `city`, in the order the roads were added.
+    /// In an undirected network, the same cities as in `roads_from`.
+    pub fn roads_to(&self, city: usize) -> &[usize] {
+        self.incoming.get_ref(city).map_or(&[], |roads| roads)
+    }
+
+    /// Returns all cities in the network.
+    pub fn cities(&self) -> USet {
+        self.outgoing.keys()
+    }
+
+    pub fn city_count(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    /// Returns the number of roads. In an undirected network, every road is counted once.
+    pub fn road_count(&self) -> usize {
+        self.road_count
+    }
+
+    fn bfs(roads: &UMap<Vec<usize>>, source: usize) -> UMap<usize> {
+        let mut distances = UMap::new();
+        if !roads.contains(source) {
+            return distances;
+        }
+        distances.put(source, 0);
+        let mut queue = VecDeque::new();
+        queue.push_back((source, 0));
+        while let Some((city, distance)) = queue.pop_front() {
+            for &next in roads.get_ref(city).unwrap() {
+                if !distances.contains(next) {
+                    distances.put(next, distance + 1);
+                    queue.push_back((next, distance + 1));
+                }
+            }
+        }
+        distances
+    }
+
+    /// Returns the lowest number of roads from `source` to every city which can be reached
+    /// from it.
+    pub fn distances_from(&self, source: usize) -> UMap<usize> {
+        RoadNetwork::bfs(&self.outgoing, source)
+    }
+
+    /// Returns the lowest number of roads to `target` from every city from which it can
+    /// be reached.
+    pub fn distances_to(&self, target: usize) -> UMap<usize> {
+        RoadNetwork::bfs(&self.incoming, target)
+    }
+
+    /// Returns a vector where indexes are the distances to `target` and the values are
+    /// the number of cities with the given distance. Cities from which `target` cannot be reached
+    /// are not counted. For a network made of a valid city map, the result is the same as
+    /// the one of [`find_city_distances`] with the capital as `target`.
+    ///
+    /// [`find_city_distances`]: ../fn.find_city_distances.html
+    pub fn distance_histogram(&self, target: usize) -> Vec<usize> {
+        let distances = self.distances_to(target);
+        let max_distance = distances.iter().map(|(_, &d)| d).max();
+        max_distance.map_or_else(Vec::new, |max| {
+            distances
+                .iter()
+                .fold(vec![0; max + 1], |mut acc, (_, &distance)| {
+                    acc[distance] += 1;
+                    acc
+                })
+        })
+    }
+
+    /// Returns the connected components of the network, ordered by their lowest cities.
+    /// In a directed network, the direction of roads is ignored (the components are weakly
+    /// connected).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::network::RoadNetwork;
+    /// use rust_experiments::utils::uset::USet;
+    ///
+    /// let mut network = RoadNetwork::directed();
+    /// network.add_road(4, 1);
+    /// network.add_road(3, 1);
+    /// network.add_road(2, 5);
+    /// assert_eq!(
+    ///     network.components(),
+    ///     vec![USet::from_slice(&[1, 3, 4]), USet::from_slice(&[2, 5])]
+    /// );
+    /// ```
+    pub fn components(&self) -> Vec<USet> {
+        let mut visited = USet::new();
+        let mut components = Vec::new();
+        for start in self.cities().iter() {
+            if visited.contains(start) {
+                continue;
+            }
+            visited.push(start);
+            let mut component = vec![start];
+            let mut stack = vec![start];
+            while let Some(city) = stack.pop() {
+                let neighbours = self.roads_from(city).iter().chain(self.roads_to(city));
+                for &next in neighbours {
+                    if !visited.contains(next) {
+                        visited.push(next);
+                        component.push(next);
+                        stack.push(next);
+                    }
+                }
+            }
+            components.push(USet::from_slice(&component));
+        }
+        components
+    }
+}
+
+/// Generates a city map with [`gen_cities`] and turns it into a network with `extra_roads`
+/// additional roads between random cities. In a directed network, the roads of the map lead
+/// towards the capital, and the additional ones in random directions.
+///
+/// # Panics
+///
+/// Panics if there is not enough pairs of cities without roads for `extra_roads`.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::*;
+/// use rust_experiments::cities::network::*;
+///
+/// let network = gen_road_network(&CityGenConfig::new(1000), 50, false);
+/// assert_eq!(network.road_count(), 999 + 50);
+/// assert_eq!(network.components().len(), 1);
+/// ```
+///
+/// [`gen_cities`]: ../fn.gen_cities.html
+pub fn gen_road_network(config: &CityGenConfig, extra_roads: usize, directed: bool) -> RoadNetwork {
+    gen_road_network_with_rng(config, extra_roads, directed, &mut rand::thread_rng())
+}
+
+/// Works like [`gen_road_network`], but takes all random numbers from the given generator.
+///
+/// [`gen_road_network`]: fn.gen_road_network.html
+pub fn gen_road_network_with_rng<R: Rng>(
+    config: &CityGenConfig,
+    extra_roads: usize,
+    directed: bool,
+    r: &mut R,
+) -> RoadNetwork {
+    let city_vec = gen_cities_with_rng(config, r);
+    let mut network = RoadNetwork::from_city_map(&city_vec, directed);
+
+    let size = city_vec.len();
+    let all_pairs = if directed {
+        size * (size - 1)
+    } else {
+        size * (size - 1) / 2
+    };
+    assert!(
+        network.road_count() + extra_roads <= all_pairs,
+        "{} cities cannot have {} more roads",
+        size,
+        extra_roads
+    );
+
+    let mut added = 0;
+    while added < extra_roads {
+        let from = r.gen_range(0, size);
+        let to = r.gen_range(0, size);
+        if network.add_road(from, to) {
+            added += 1;
+        }
+    }
+    network
+}
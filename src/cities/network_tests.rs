@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod network_tests {
+    use crate::cities::network::*;
+    use crate::cities::*;
+    use crate::utils::prng::seeded_rng;
+    use crate::utils::uset::USet;
+
+    use spectral::prelude::*;
+
+    #[test]
+    fn should_match_solver_on_generated_maps() {
+        let v = gen_cities_with_rng(&CityGenConfig::new(2000), &mut seeded_rng(1));
+        let capital = CityMap::validate(&v).unwrap();
+        let expected = find_city_distances(&v).unwrap();
+        for &directed in [true, false].iter() {
+            let network = RoadNetwork::from_city_map(&v, directed);
+            assert_that!(network.city_count()).is_equal_to(2000);
+            assert_that!(network.road_count()).is_equal_to(1999);
+            assert_that!(network.distance_histogram(capital)).is_equal_to(expected.clone());
+        }
+    }
+
+    #[test]
+    fn should_follow_directions_of_roads() {
+        let network = RoadNetwork::from_city_map(&[1, 1, 1, 2], true);
+        assert_that!(network.distances_from(3).len()).is_equal_to(3);
+        assert_that!(network.distances_from(1).len()).is_equal_to(1);
+        assert_that!(network.distances_to(1).get(3)).is_equal_to(Some(2));
+        assert_that!(network.distance_histogram(3)).is_equal_to(vec![1]);
+
+        let network = RoadNetwork::from_city_map(&[1, 1, 1, 2], false);
+        assert_that!(network.distance_histogram(3)).is_equal_to(vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn should_add_and_remove_roads() {
+        let mut network = RoadNetwork::undirected();
+        assert_that!(network.add_road(10, 20)).is_true();
+        assert_that!(network.add_road(20, 10)).is_false();
+        assert_that!(network.add_road(10, 10)).is_false();
+        assert_that!(network.has_road(20, 10)).is_true();
+        assert_that!(network.remove_road(20, 10)).is_true();
+        assert_that!(network.remove_road(10, 20)).is_false();
+        assert_that!(network.road_count()).is_equal_to(0);
+        assert_that!(network.city_count()).is_equal_to(2);
+        assert_that!(network.roads_from(10).is_empty()).is_true();
+        assert_that!(network.roads_to(20).is_empty()).is_true();
+
+        let mut network = RoadNetwork::directed();
+        assert_that!(network.add_road(10, 20)).is_true();
+        assert_that!(network.add_road(20, 10)).is_true();
+        assert_that!(network.remove_road(10, 20)).is_true();
+        assert_that!(network.roads_from(20)).is_equal_to(&[10][..]);
+        assert_that!(network.roads_to(20).is_empty()).is_true();
+    }
+
+    #[test]
+    fn should_keep_order_of_roads_when_removing() {
+        for &directed in [true, false].iter() {
+            let mut network = if directed {
+                RoadNetwork::directed()
+            } else {
+                RoadNetwork::undirected()
+            };
+            for &to in [1, 2, 3, 4].iter() {
+                network.add_road(0, to);
+                network.add_road(to + 4, 1);
+            }
+            assert_that!(network.remove_road(0, 2)).is_true();
+            assert_that!(network.remove_road(6, 1)).is_true();
+            assert_that!(network.roads_from(0)).is_equal_to(&[1, 3, 4][..]);
+            assert_that!(network.roads_to(1)).is_equal_to(&[0, 5, 7, 8][..]);
+        }
+    }
+
+    #[test]
+    fn should_find_components_of_forest() {
+        // two capitals, 0 and 2, and a city with no roads at all
+        let mut network = RoadNetwork::from_city_map(&[0, 0, 2, 2, 3], false);
+        network.add_city(9);
+        assert_that!(network.components()).is_equal_to(vec![
+            USet::from_slice(&[0, 1]),
+            USet::from_slice(&[2, 3, 4]),
+            USet::from_slice(&[9]),
+        ]);
+        network.add_road(1, 4);
+        assert_that!(network.components()).has_length(2);
+    }
+
+    #[test]
+    fn should_generate_network_with_extra_roads() {
+        let config = CityGenConfig::new(500);
+        for &directed in [true, false].iter() {
+            let tree = gen_road_network_with_rng(&config, 0, directed, &mut seeded_rng(3));
+            let network = gen_road_network_with_rng(&config, 200, directed, &mut seeded_rng(3));
+            assert_that!(network.road_count()).is_equal_to(499 + 200);
+            assert_that!(network.components()).has_length(1);
+            assert_that!(network).is_equal_to(gen_road_network_with_rng(
+                &config,
+                200,
+                directed,
+                &mut seeded_rng(3),
+            ));
+            // extra roads can only make the way to the capital shorter
+            if directed {
+                let capital = (0..500).find(|&c| tree.roads_from(c).is_empty()).unwrap();
+                assert_that!(network.distance_histogram(capital).len())
+                    .is_less_than_or_equal_to(tree.distance_histogram(capital).len());
+            }
+        }
+    }
+
+    #[test]
+    fn should_fill_complete_graph() {
+        let network = gen_road_network(&CityGenConfig::new(10), 45 - 9, false);
+        assert_that!(network.road_count()).is_equal_to(45);
+        assert_that!(network.distance_histogram(0)).is_equal_to(vec![1, 9]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_not_add_more_roads_than_possible() {
+        gen_road_network(&CityGenConfig::new(10), 45 - 8, false);
+    }
+}
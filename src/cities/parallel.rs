@@ -0,0 +1,144 @@
+//! A multi-threaded solver for very large maps.
+//!
+//! [`find_city_distances`] follows the roads from every city one by one. Here instead every city
+//! keeps a pointer to a city closer to the capital and the number of roads to it, and in each
+//! round all cities in parallel replace their pointer with the pointer of the city it points to,
+//! adding up the distances ("pointer jumping"). After O(log d) rounds, where `d` is the longest
+//! distance, all pointers lead to the capital and the distances are known.
+//!
+//! The work is split between scoped threads from the standard library, each owning a contiguous
+//! chunk of the cities. Pointers and distances are kept as `u32`, which halves the memory
+//! needed for maps with 10^8 cities.
+//!
+//! The module is compiled only with the `parallel` feature, since scoped threads,
+//! `available_parallelism` and `usize::div_ceil` need Rust 1.73 or newer.
+//!
+//! [`find_city_distances`]: ../fn.find_city_distances.html
+
+use std::mem;
+use std::thread;
+
+use super::city_map::{CityMap, CityMapError};
+use super::find_city_distances;
+
+/// The same as [`find_city_distances`], but uses as many threads as there are cores.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::parallel::*;
+/// let city_vec = vec![9, 3, 2, 2, 2, 2, 1, 5, 2, 1];
+/// assert_eq!(find_city_distances_parallel(&city_vec), Ok(vec![1, 4, 2, 2, 1]));
+/// ```
+///
+/// [`find_city_distances`]: ../fn.find_city_distances.html
+pub fn find_city_distances_parallel(city_vec: &[usize]) -> Result<Vec<usize>, CityMapError> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    find_city_distances_with_threads(city_vec, threads)
+}
+
+/// The same as [`find_city_distances`], but splits the work between the given number of threads.
+/// Maps too large for `u32` are solved by `find_city_distances`.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::parallel::*;
+/// let city_vec = vec![9, 3, 2, 2, 2, 2, 1, 5, 2, 1];
+/// assert_eq!(find_city_distances_with_threads(&city_vec, 3), Ok(vec![1, 4, 2, 2, 1]));
+/// ```
+///
+/// [`find_city_distances`]: ../fn.find_city_distances.html
+pub fn find_city_distances_with_threads(
+    city_vec: &[usize],
+    threads: usize,
+) -> Result<Vec<usize>, CityMapError> {
+    let capital = CityMap::validate(city_vec)?;
+    if city_vec.len() > u32::MAX as usize {
+        return find_city_distances(city_vec);
+    }
+
+    let chunk_size = city_vec.len().div_ceil(threads.max(1));
+    let capital = capital as u32;
+
+    let mut next = vec![0u32; city_vec.len()];
+    let mut distances = vec![0u32; city_vec.len()];
+    in_parallel(
+        &mut next,
+        &mut distances,
+        chunk_size,
+        |start, next, distances| {
+            for (i, (n, d)) in next.iter_mut().zip(distances.iter_mut()).enumerate() {
+                *n = city_vec[start + i] as u32;
+                *d = if *n == (start + i) as u32 { 0 } else { 1 };
+            }
+        },
+    );
+
+    let mut next_after = vec![0u32; city_vec.len()];
+    let mut distances_after = vec![0u32; city_vec.len()];
+    loop {
+        let (current_next, current_distances) = (&next, &distances);
+        let unfinished = in_parallel(
+            &mut next_after,
+            &mut distances_after,
+            chunk_size,
+            |start, next_after, distances_after| {
+                let mut unfinished = false;
+                let chunk = next_after.iter_mut().zip(distances_after.iter_mut());
+                for (i, (n, d)) in chunk.enumerate() {
+                    let jump = current_next[start + i] as usize;
+                    *d = current_distances[start + i] + current_distances[jump];
+                    *n = current_next[jump];
+                    unfinished |= *n != capital;
+                }
+                unfinished
+            },
+        );
+        mem::swap(&mut next, &mut next_after);
+        mem::swap(&mut distances, &mut distances_after);
+        if !unfinished.into_iter().any(|u| u) {
+            break;
+        }
+    }
+
+    let histograms = in_parallel(&mut next, &mut distances, chunk_size, |_, _, distances| {
+        distances.iter().fold(Vec::new(), |mut acc, &d| {
+            let d = d as usize;
+            if acc.len() <= d {
+                acc.resize(d + 1, 0);
+            }
+            acc[d] += 1;
+            acc
+        })
+    });
+
+    Ok(histograms
+        .into_iter()
+        .fold(Vec::new(), |mut acc, histogram| {
+            if acc.len() < histogram.len() {
+                acc.resize(histogram.len(), 0);
+            }
+            acc.iter_mut().zip(histogram).for_each(|(a, h)| *a += h);
+            acc
+        }))
+}
+
+// Runs `f` on every chunk of the two vectors in a separate thread and collects the results.
+// `f` receives the index of the first city in the chunk.
+fn in_parallel<T, F>(next: &mut [u32], distances: &mut [u32], chunk_size: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize, &mut [u32], &mut [u32]) -> T + Sync,
+{
+    let f = &f;
+    thread::scope(|s| {
+        let handles: Vec<_> = next
+            .chunks_mut(chunk_size)
+            .zip(distances.chunks_mut(chunk_size))
+            .enumerate()
+            .map(|(i, (next, distances))| s.spawn(move || f(i * chunk_size, next, distances)))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod parallel_tests {
+    use crate::cities::parallel::*;
+    use crate::cities::*;
+    use crate::utils::prng::seeded_rng;
+
+    use spectral::prelude::*;
+
+    #[test]
+    fn should_solve_single_city() {
+        assert_that!(find_city_distances_with_threads(&[0], 4)).is_equal_to(Ok(vec![1]));
+    }
+
+    #[test]
+    fn should_reject_invalid_maps() {
+        assert_that!(find_city_distances_parallel(&[1, 0]))
+            .is_equal_to(Err(CityMapError::NoCapital));
+        assert_that!(find_city_distances_parallel(&[0, 2, 1]))
+            .is_equal_to(Err(CityMapError::Cycle(vec![1, 2])));
+    }
+
+    #[test]
+    fn should_accept_more_threads_than_cities() {
+        let v = vec![1, 1, 1, 2];
+        assert_that!(find_city_distances_with_threads(&v, 16)).is_equal_to(find_city_distances(&v));
+        assert_that!(find_city_distances_with_threads(&v, 0)).is_equal_to(find_city_distances(&v));
+    }
+
+    #[test]
+    fn should_solve_long_shuffled_chain() {
+        let size = 1_000_000;
+        let mut order: Vec<usize> = (0..size).collect();
+        rand::Rng::shuffle(&mut seeded_rng(3), &mut order);
+        let mut v = vec![order[0]; size];
+        for pair in order.windows(2) {
+            v[pair[1]] = pair[0];
+        }
+        assert_that!(find_city_distances_with_threads(&v, 4)).is_equal_to(Ok(vec![1; size]));
+    }
+
+    quickcheck! {
+        fn parallel_solver_matches_sequential(seed: u64, size: usize, threads: usize) -> bool {
+            let v = gen_cities_with_rng(&CityGenConfig::new(size % 500 + 10), &mut seeded_rng(seed));
+            find_city_distances_with_threads(&v, threads % 8 + 1) == find_city_distances(&v)
+        }
+    }
+}
@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod progress_tests {
+    use crate::cities::progress::*;
+    use crate::cities::uniform::*;
+    use crate::cities::*;
+    use crate::utils::prng::seeded_rng;
+
+    use spectral::prelude::*;
+
+    type Generator = fn(usize, &mut Monitor) -> Result<Vec<usize>, Cancelled>;
+
+    fn all_generators() -> Vec<(&'static str, Generator)> {
+        vec![
+            ("gen_cities", |size, m| {
+                gen_cities_monitored(&CityGenConfig::new(size), &mut seeded_rng(1), m)
+            }),
+            ("preferential", |size, m| {
+                let config =
+                    CityGenConfig::new(size).with_branching(Branching::PreferentialAttachment);
+                gen_cities_monitored(&config, &mut seeded_rng(1), m)
+            }),
+            ("gen_cities_uset", |size, m| {
+                gen_cities_uset_monitored(size, 20, &mut seeded_rng(1), m)
+            }),
+            ("gen_cities_hashset", |size, m| {
+                gen_cities_hashset_monitored(size, 20, &mut seeded_rng(1), m)
+            }),
+            ("prufer", |size, m| {
+                gen_uniform_cities_monitored(size, &mut seeded_rng(1), m)
+            }),
+            ("wilson", |size, m| {
+                gen_uniform_cities_wilson_monitored(size, &mut seeded_rng(1), m)
+            }),
+            ("aldous_broder", |size, m| {
+                gen_uniform_cities_aldous_broder_monitored(size, &mut seeded_rng(1), m)
+            }),
+        ]
+    }
+
+    #[test]
+    fn should_report_progress_of_every_generator() {
+        for (name, generate) in all_generators() {
+            let mut reports = Vec::new();
+            let result = {
+                let mut monitor = Monitor::new()
+                    .with_interval(100)
+                    .with_callback(|progress| reports.push(progress));
+                generate(1000, &mut monitor)
+            };
+            assert_that!(result.map(|v| v.len()))
+                .named(name)
+                .is_equal_to(Ok(1000));
+            assert_that!(reports.len()).named(name).is_greater_than(3);
+            assert_that!(reports.windows(2).all(|w| w[0].done < w[1].done))
+                .named(name)
+                .is_true();
+            let last = reports.last().unwrap();
+            assert_that!(last.done).named(name).is_equal_to(last.total);
+        }
+    }
+
+    #[test]
+    fn should_stop_every_generator_when_cancelled() {
+        for (name, generate) in all_generators() {
+            let token = CancellationToken::new();
+            let mut reports = 0;
+            let result = {
+                let cancel = token.clone();
+                let mut monitor = Monitor::new()
+                    .with_interval(100)
+                    .with_token(token.clone())
+                    .with_callback(|_| {
+                        reports += 1;
+                        cancel.cancel();
+                    });
+                generate(1000, &mut monitor)
+            };
+            assert_that!(result).named(name).is_equal_to(Err(Cancelled));
+            assert_that!(reports).named(name).is_equal_to(1);
+        }
+    }
+
+    #[test]
+    fn should_not_change_generated_maps() {
+        let config = CityGenConfig::new(500);
+        let mut monitor = Monitor::new().with_interval(7).with_callback(|_| ());
+        assert_that!(gen_cities_monitored(
+            &config,
+            &mut seeded_rng(3),
+            &mut monitor
+        ))
+        .is_equal_to(Ok(gen_cities_with_rng(&config, &mut seeded_rng(3))));
+        assert_that!(gen_uniform_cities_monitored(
+            500,
+            &mut seeded_rng(3),
+            &mut monitor
+        ))
+        .is_equal_to(Ok(gen_uniform_cities(500, &mut seeded_rng(3))));
+    }
+
+    #[test]
+    fn should_solve_with_monitor() {
+        let city_vec = gen_cities(&CityGenConfig::new(1000));
+        let mut count = 0;
+        let result = {
+            let mut monitor = Monitor::new()
+                .with_interval(300)
+                .with_callback(|_| count += 1);
+            find_city_distances_monitored(&city_vec, &mut monitor)
+        };
+        assert_that!(result).is_equal_to(Ok(find_city_distances(&city_vec).unwrap()));
+        // at 300, 600, 900 and 1000 cities
+        assert_that!(count).is_equal_to(4);
+    }
+
+    #[test]
+    fn should_reuse_monitor() {
+        let mut totals = Vec::new();
+        {
+            let mut monitor = Monitor::new().with_callback(|p| totals.push(p.total));
+            let city_vec =
+                gen_cities_monitored(&CityGenConfig::new(10), &mut seeded_rng(5), &mut monitor)
+                    .unwrap();
+            find_city_distances_monitored(&city_vec, &mut monitor).unwrap();
+        }
+        assert_that!(totals).is_equal_to(vec![10, 10]);
+    }
+
+    #[test]
+    fn should_report_invalid_map_before_cancelling() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let mut monitor = Monitor::new().with_token(token);
+        assert_that!(find_city_distances_monitored(&[1, 0], &mut monitor))
+            .is_equal_to(Err(SolveError::InvalidMap(CityMapError::NoCapital)));
+        assert_that!(SolveError::Cancelled.to_string()).is_equal_to("cancelled".to_string());
+    }
+
+    #[test]
+    fn should_compute_fraction() {
+        assert_that!(Progress { done: 1, total: 4 }.fraction()).is_equal_to(0.25);
+        assert_that!(Progress { done: 0, total: 0 }.fraction()).is_equal_to(1.0);
+    }
+}
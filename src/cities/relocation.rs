@@ -0,0 +1,273 @@
+//! Distances from many sources at once, and analysis of what would happen if the capital was
+//! moved to another city.
+//!
+//! Both treat the roads as two-way: the distance between two cities is the number of roads
+//! on the only path between them, no matter where the capital is.
+
+use std::collections::VecDeque;
+
+use super::city_map::{CityMap, CityMapError};
+use crate::utils::uset::USet;
+
+/// Calculates a vector where indexes are the distances from the nearest of `sources` and
+/// the values are the number of cities with the given distance. With the capital as the only
+/// source it is the same as [`find_city_distances`].
+///
+/// # Errors
+///
+/// Returns an error if `city_vec` is not a valid map. See [`CityMap::validate`].
+///
+/// # Panics
+///
+/// Panics if any of `sources` is not a city on the map.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::relocation::*;
+/// use rust_experiments::utils::uset::USet;
+///
+/// // 0 - 1 - 2 - 3 - 4, with 2 as the capital
+/// let city_vec = vec![1, 2, 2, 2, 3];
+/// let sources = USet::from_slice(&[0, 4]);
+/// assert_eq!(find_multi_source_distances(&city_vec, &sources), Ok(vec![2, 2, 1]));
+/// ```
+///
+/// [`find_city_distances`]: ../fn.find_city_distances.html
+/// [`CityMap::validate`]: ../city_map/struct.CityMap.html#method.validate
+pub fn find_multi_source_distances(
+    city_vec: &[usize],
+    sources: &USet,
+) -> Result<Vec<usize>, CityMapError> {
+    let map = CityMap::new(city_vec.to_vec())?;
+    Ok(histogram(&map.distances_from_sources(sources)))
+}
+
+// Counts the finite distances.
+fn histogram(distances: &[usize]) -> Vec<usize> {
+    let finite = distances.iter().filter(|&&d| d != usize::MAX);
+    let max_distance = match finite.clone().max() {
+        Some(&max) => max,
+        None => return Vec::new(),
+    };
+    finite.fold(vec![0; max_distance + 1], |mut acc, &distance| {
+        acc[distance] += 1;
+        acc
+    })
+}
+
+/// What the map would look like from a city if it was the capital.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapitalScore {
+    /// The sum of distances from the city to all other cities.
+    pub total_distance: usize,
+    /// The distance from the city to the farthest city, ie. the length of the distance histogram
+    /// minus one.
+    pub eccentricity: usize,
+}
+
+impl CityMap {
+    /// Returns the distance from every city to the nearest of `sources`, found with
+    /// a breadth-first search starting from all sources at once. Runs in O(n).
+    /// If `sources` is empty, all distances are `usize::MAX`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of `sources` is not a city on the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    /// use rust_experiments::utils::uset::USet;
+    ///
+    /// let map = CityMap::new(vec![1, 2, 2, 2, 3]).unwrap();
+    /// let distances = map.distances_from_sources(&USet::from_slice(&[0, 4]));
+    /// assert_eq!(distances, vec![0, 1, 2, 1, 0]);
+    /// ```
+    pub fn distances_from_sources(&self, sources: &USet) -> Vec<usize> {
+        let mut distances = vec![usize::MAX; self.len()];
+        let mut queue = VecDeque::with_capacity(self.len());
+        for source in sources.iter() {
+            distances[source] = 0;
+            queue.push_back(source);
+        }
+
+        while let Some(city) = queue.pop_front() {
+            let next = self
+                .parent(city)
+                .into_iter()
+                .chain(self.children(city).iter().cloned());
+            for neighbour in next {
+                if distances[neighbour] == usize::MAX {
+                    distances[neighbour] = distances[city] + 1;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Returns the distance histogram the map would have if `city` was the capital.
+    /// Runs in O(n) for a single city.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// let map = CityMap::new(vec![1, 2, 2, 2, 3]).unwrap();
+    /// assert_eq!(map.histogram_from(2), map.distance_histogram());
+    /// assert_eq!(map.histogram_from(0), vec![1, 1, 1, 1, 1]);
+    /// ```
+    pub fn histogram_from(&self, city: usize) -> Vec<usize> {
+        histogram(&self.distances_from_sources(&USet::from_slice(&[city])))
+    }
+
+    /// Returns, for every city, the [`CapitalScore`] it would have as the capital.
+    ///
+    /// The scores of all cities are found in O(n) total by re-rooting: they are computed
+    /// for the current capital first, and then moved along every road, adjusting only
+    /// for the cities which changed sides. Use [`capital_histograms`] when the full
+    /// histograms are needed.
+    ///
+    /// The city with the lowest `total_distance` is a median of the map and minimises
+    /// the average distance to the capital, the one with the lowest `eccentricity` is
+    /// a centre and minimises the length of the histogram.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    /// use rust_experiments::cities::relocation::CapitalScore;
+    ///
+    /// // 0 - 1 - 2 - 3 - 4, with 4 as the capital
+    /// let map = CityMap::new(vec![1, 2, 3, 4, 4]).unwrap();
+    /// let scores = map.capital_scores();
+    /// assert_eq!(scores[4], CapitalScore { total_distance: 10, eccentricity: 4 });
+    /// assert_eq!(scores[2], CapitalScore { total_distance: 6, eccentricity: 2 });
+    /// ```
+    ///
+    /// [`CapitalScore`]: ../relocation/struct.CapitalScore.html
+    /// [`capital_histograms`]: #method.capital_histograms
+    pub fn capital_scores(&self) -> Vec<CapitalScore> {
+        let n = self.len();
+        let order: Vec<usize> = self.bfs().collect();
+
+        // Pass up: the distances within the subtree of every city, and the two longest paths
+        // down from it through different children.
+        let mut down_total = vec![0; n];
+        let mut longest = vec![0; n];
+        let mut second_longest = vec![0; n];
+        let mut longest_via = vec![usize::MAX; n];
+        for &city in order.iter().rev() {
+            if let Some(parent) = self.parent(city) {
+                down_total[parent] += down_total[city] + self.subtree_size(city);
+                let length = longest[city] + 1;
+                if length > longest[parent] {
+                    second_longest[parent] = longest[parent];
+                    longest[parent] = length;
+                    longest_via[parent] = city;
+                } else if length > second_longest[parent] {
+                    second_longest[parent] = length;
+                }
+            }
+        }
+
+        // Pass down: moving the capital from the parent to the city brings the cities in
+        // the subtree one road closer and all others one road farther.
+        let mut total = vec![0; n];
+        let mut up = vec![0; n];
+        total[self.capital()] = down_total[self.capital()];
+        for &city in order.iter().skip(1) {
+            let parent = self.roads()[city];
+            let size = self.subtree_size(city);
+            total[city] = total[parent] + n - 2 * size;
+            let sideways = if longest_via[parent] == city {
+                second_longest[parent]
+            } else {
+                longest[parent]
+            };
+            up[city] = 1 + up[parent].max(sideways);
+        }
+
+        (0..n)
+            .map(|city| CapitalScore {
+                total_distance: total[city],
+                eccentricity: longest[city].max(up[city]),
+            })
+            .collect()
+    }
+
+    /// Returns, for every city, the distance histogram the map would have if it was
+    /// the capital. The histogram of the current capital is the same as
+    /// [`distance_histogram`].
+    ///
+    /// The histograms are found by re-rooting, like in [`capital_scores`]: the histogram of
+    /// every subtree is computed first, and the histogram of a city is then derived from
+    /// the one of its parent by moving its subtree one road closer and all other cities one
+    /// road farther. Every histogram is produced in time proportional to its length, so
+    /// apart from the O(n) passes the cost is just the size of the output. Note that
+    /// the output itself may have O(n²) entries in total, eg. on a long path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::*;
+    ///
+    /// // 0 - 1 - 2 - 3, with 3 as the capital
+    /// let map = CityMap::new(vec![1, 2, 3, 3]).unwrap();
+    /// let histograms = map.capital_histograms();
+    /// assert_eq!(histograms[3], vec![1, 1, 1, 1]);
+    /// assert_eq!(histograms[1], vec![1, 2, 1]);
+    /// ```
+    ///
+    /// [`distance_histogram`]: #method.distance_histogram
+    /// [`capital_scores`]: #method.capital_scores
+    pub fn capital_histograms(&self) -> Vec<Vec<usize>> {
+        let n = self.len();
+        let order: Vec<usize> = self.bfs().collect();
+
+        // Pass up: the histogram of every subtree, with distances measured from its root.
+        let mut down = vec![vec![1]; n];
+        for &city in order.iter().rev() {
+            if let Some(parent) = self.parent(city) {
+                let subtree = std::mem::take(&mut down[city]);
+                add_shifted(&mut down[parent], &subtree);
+                down[city] = subtree;
+            }
+        }
+
+        // Pass down: the histogram of the parent without the subtree of the city is one road
+        // farther from the city, the subtree stays as it is.
+        let mut histograms = vec![Vec::new(); n];
+        histograms[self.capital()] = down[self.capital()].clone();
+        for &city in order.iter().skip(1) {
+            let parent = &histograms[self.roads()[city]];
+            let subtree = &down[city];
+            let at = |v: &[usize], d: usize| v.get(d).cloned().unwrap_or(0);
+            let mut histogram: Vec<usize> = (0..subtree.len().max(parent.len() + 1))
+                .map(|d| match d {
+                    0 => 1,
+                    1 => at(subtree, 1) + parent[0],
+                    _ => at(subtree, d) + at(parent, d - 1) - at(subtree, d - 2),
+                })
+                .collect();
+            while histogram.last() == Some(&0) {
+                histogram.pop();
+            }
+            histograms[city] = histogram;
+        }
+        histograms
+    }
+}
+
+// Adds the histogram of a subtree to the histogram of its parent, one road farther.
+fn add_shifted(histogram: &mut Vec<usize>, subtree: &[usize]) {
+    if histogram.len() < subtree.len() + 1 {
+        histogram.resize(subtree.len() + 1, 0);
+    }
+    for (distance, &count) in subtree.iter().enumerate() {
+        histogram[distance + 1] += count;
+    }
+}
@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod relocation_tests {
+    use crate::cities::relocation::*;
+    use crate::cities::*;
+    use crate::utils::prng::seeded_rng;
+    use crate::utils::uset::USet;
+
+    use spectral::prelude::*;
+
+    fn gen_map(size: usize, seed: u64) -> CityMap {
+        CityMap::new(gen_cities_with_rng(
+            &CityGenConfig::new(size),
+            &mut seeded_rng(seed),
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn should_match_single_source_solver_for_capital() {
+        let v = gen_cities(&CityGenConfig::new(1000));
+        let capital = CityMap::validate(&v).unwrap();
+        assert_that!(find_multi_source_distances(
+            &v,
+            &USet::from_slice(&[capital])
+        ))
+        .is_equal_to(find_city_distances(&v));
+    }
+
+    #[test]
+    fn should_reject_invalid_map() {
+        assert_that!(find_multi_source_distances(
+            &[1, 0],
+            &USet::from_slice(&[0])
+        ))
+        .is_equal_to(Err(CityMapError::NoCapital));
+    }
+
+    #[test]
+    fn should_handle_no_sources() {
+        let map = gen_map(20, 1);
+        assert_that!(map.distances_from_sources(&USet::new())).is_equal_to(vec![usize::MAX; 20]);
+        assert_that!(find_multi_source_distances(map.roads(), &USet::new()))
+            .is_equal_to(Ok(vec![]));
+    }
+
+    #[test]
+    fn should_score_star_centre_best() {
+        // 1, 2 and 3 lead to 0, which leads to the capital 4
+        let map = CityMap::new(vec![4, 0, 0, 0, 4]).unwrap();
+        let scores = map.capital_scores();
+        assert_that!(scores[0]).is_equal_to(CapitalScore {
+            total_distance: 4,
+            eccentricity: 1,
+        });
+        assert_that!(scores[1]).is_equal_to(CapitalScore {
+            total_distance: 7,
+            eccentricity: 2,
+        });
+        assert_that!(scores[4]).is_equal_to(CapitalScore {
+            total_distance: 7,
+            eccentricity: 2,
+        });
+    }
+
+    #[test]
+    fn should_find_histograms_for_every_capital() {
+        // 1, 2 and 3 lead to 0, which leads to the capital 4
+        let map = CityMap::new(vec![4, 0, 0, 0, 4]).unwrap();
+        let histograms = map.capital_histograms();
+        assert_that!(histograms[4]).is_equal_to(map.distance_histogram());
+        assert_that!(histograms[0]).is_equal_to(vec![1, 4]);
+        assert_that!(histograms[1]).is_equal_to(vec![1, 1, 3]);
+        assert_that!(CityMap::new(vec![0]).unwrap().capital_histograms())
+            .is_equal_to(vec![vec![1]]);
+    }
+
+    quickcheck! {
+        fn multi_source_distances_are_minimum_of_single_sources(seed: u64, raw: Vec<usize>) -> bool {
+            let map = gen_map(60, seed);
+            let sources: Vec<usize> = raw.iter().map(|s| s % map.len()).take(5).collect();
+            let distances = map.distances_from_sources(&USet::from_slice(&sources));
+            let singles: Vec<Vec<usize>> = sources
+                .iter()
+                .map(|&s| map.distances_from_sources(&USet::from_slice(&[s])))
+                .collect();
+            (0..map.len()).all(|city| {
+                distances[city] == singles.iter().map(|d| d[city]).min().unwrap_or(usize::MAX)
+            })
+        }
+
+        fn scores_agree_with_histograms(seed: u64) -> bool {
+            let map = gen_map(80, seed);
+            map.capital_scores().iter().enumerate().all(|(city, score)| {
+                let histogram = map.histogram_from(city);
+                let total: usize = histogram.iter().enumerate().map(|(d, count)| d * count).sum();
+                score.total_distance == total && score.eccentricity == histogram.len() - 1
+            })
+        }
+
+        fn rerooted_histograms_agree_with_single_sources(seed: u64) -> bool {
+            let map = gen_map(80, seed);
+            map.capital_histograms()
+                .iter()
+                .enumerate()
+                .all(|(city, histogram)| *histogram == map.histogram_from(city))
+        }
+    }
+}
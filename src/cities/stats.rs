@@ -0,0 +1,283 @@
+//! Statistics of distance histograms, and reports comparing them.
+
+use std::fmt;
+
+use super::city_map::{CityMap, CityMapError};
+
+/// Percentiles shown in reports, next to the median.
+pub const REPORTED_PERCENTILES: [u8; 4] = [25, 75, 90, 99];
+
+/// Describes the shape of a city map by the distances from its cities to the capital.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::stats::DistanceStats;
+///
+/// let stats = DistanceStats::new(&[1, 1, 1, 2]).unwrap();
+/// assert_eq!(stats.histogram, vec![1, 2, 1]);
+/// assert_eq!(stats.mean, 1.0);
+/// assert_eq!(stats.variance, 0.5);
+/// assert_eq!(stats.median, 1);
+/// assert_eq!(stats.leaves, 2);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceStats {
+    /// The number of cities, including the capital.
+    pub cities: usize,
+    /// The number of cities with every distance, as from [`find_city_distances`].
+    ///
+    /// [`find_city_distances`]: ../fn.find_city_distances.html
+    pub histogram: Vec<usize>,
+    pub mean: f64,
+    /// The population variance.
+    pub variance: f64,
+    pub median: usize,
+    /// The distance to the farthest city.
+    pub max_depth: usize,
+    /// The number of cities with no roads leading to them.
+    pub leaves: usize,
+}
+
+impl DistanceStats {
+    /// Computes the statistics of a map.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `city_vec` is not a valid map. See [`CityMap::validate`].
+    ///
+    /// [`CityMap::validate`]: ../city_map/struct.CityMap.html#method.validate
+    pub fn new(city_vec: &[usize]) -> Result<Self, CityMapError> {
+        let map = CityMap::new(city_vec.to_vec())?;
+        Ok(DistanceStats::from_map(&map))
+    }
+
+    /// Computes the statistics of a map which is already validated.
+    pub fn from_map(map: &CityMap) -> Self {
+        let leaves = (0..map.len())
+            .filter(|&city| map.children(city).is_empty())
+            .count();
+        DistanceStats::from_histogram(&map.distance_histogram(), leaves)
+    }
+
+    /// Computes the statistics from a distance histogram. The number of leaves cannot be derived
+    /// from the histogram, so it has to be given.
+    pub fn from_histogram(histogram: &[usize], leaves: usize) -> Self {
+        let cities: usize = histogram.iter().sum();
+        let (mean, variance) = if cities == 0 {
+            (0.0, 0.0)
+        } else {
+            let weighted = |f: &dyn Fn(f64) -> f64| {
+                histogram
+                    .iter()
+                    .enumerate()
+                    .map(|(d, &count)| f(d as f64) * count as f64)
+                    .sum::<f64>()
+                    / cities as f64
+            };
+            let mean = weighted(&|d| d);
+            (mean, weighted(&|d| (d - mean).powi(2)))
+        };
+
+        let mut stats = DistanceStats {
+            cities,
+            histogram: histogram.to_vec(),
+            mean,
+            variance,
+            median: 0,
+            max_depth: histogram.len().saturating_sub(1),
+            leaves,
+        };
+        stats.median = stats.percentile(50.0);
+        stats
+    }
+
+    /// Returns the lowest distance such that at least `p` percent of cities are at most that far
+    /// from the capital (the nearest-rank method).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::stats::DistanceStats;
+    ///
+    /// let stats = DistanceStats::from_histogram(&[1, 5, 3, 1], 4);
+    /// assert_eq!(stats.percentile(10.0), 0);
+    /// assert_eq!(stats.percentile(60.0), 1);
+    /// assert_eq!(stats.percentile(90.0), 2);
+    /// assert_eq!(stats.percentile(100.0), 3);
+    /// ```
+    pub fn percentile(&self, p: f64) -> usize {
+        let rank = ((p / 100.0) * self.cities as f64).ceil().max(1.0) as usize;
+        let mut seen = 0;
+        for (distance, &count) in self.histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return distance;
+            }
+        }
+        self.max_depth
+    }
+
+    // Labels and values of all rows in reports.
+    fn rows(&self) -> Vec<(String, String)> {
+        let mut rows = vec![
+            ("cities".to_string(), self.cities.to_string()),
+            ("max depth".to_string(), self.max_depth.to_string()),
+            ("leaves".to_string(), self.leaves.to_string()),
+            ("mean".to_string(), format!("{:.3}", self.mean)),
+            ("variance".to_string(), format!("{:.3}", self.variance)),
+            ("median".to_string(), self.median.to_string()),
+        ];
+        for &p in REPORTED_PERCENTILES.iter() {
+            rows.push((
+                format!("{}th percentile", p),
+                self.percentile(f64::from(p)).to_string(),
+            ));
+        }
+        rows
+    }
+
+    /// Returns the statistics as a JSON object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::cities::stats::DistanceStats;
+    ///
+    /// let stats = DistanceStats::from_histogram(&[1, 2, 1], 2);
+    /// assert_eq!(
+    ///     stats.to_json(),
+    ///     "{\"cities\":4,\"max_depth\":2,\"leaves\":2,\"mean\":1,\"variance\":0.5,\"median\":1,\
+    ///      \"percentiles\":{\"25\":0,\"75\":1,\"90\":2,\"99\":2},\"histogram\":[1,2,1]}"
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let percentiles: Vec<String> = REPORTED_PERCENTILES
+            .iter()
+            .map(|&p| format!("\"{}\":{}", p, self.percentile(f64::from(p))))
+            .collect();
+        let histogram: Vec<String> = self.histogram.iter().map(|c| c.to_string()).collect();
+        format!(
+            "{{\"cities\":{},\"max_depth\":{},\"leaves\":{},\"mean\":{},\"variance\":{},\
+             \"median\":{},\"percentiles\":{{{}}},\"histogram\":[{}]}}",
+            self.cities,
+            self.max_depth,
+            self.leaves,
+            self.mean,
+            self.variance,
+            self.median,
+            percentiles.join(","),
+            histogram.join(",")
+        )
+    }
+}
+
+impl fmt::Display for DistanceStats {
+    /// Writes the statistics as a table with one row per value.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = self.rows();
+        let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        for (label, value) in rows {
+            writeln!(f, "{:<width$}  {}", label, value, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the number of cities with no roads leading to them, without validating the map.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::stats::count_leaves;
+///
+/// assert_eq!(count_leaves(&[1, 1, 1, 2]), 2);
+/// assert_eq!(count_leaves(&[0]), 1);
+/// ```
+pub fn count_leaves(city_vec: &[usize]) -> usize {
+    let mut has_roads_to = vec![false; city_vec.len()];
+    for (city, &to) in city_vec.iter().enumerate() {
+        if city != to {
+            has_roads_to[to] = true;
+        }
+    }
+    has_roads_to.iter().filter(|&&r| !r).count()
+}
+
+/// Writes the statistics of many maps side by side, with the given names as the column headers.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::stats::*;
+///
+/// let round = DistanceStats::from_histogram(&[1, 4, 4], 8);
+/// let snaky = DistanceStats::from_histogram(&[1, 1, 1, 1, 1, 1, 1, 1, 1], 1);
+/// let table = comparison_table(&[("round", &round), ("snaky", &snaky)]);
+/// assert!(table.starts_with("                 round  snaky\n"));
+/// assert!(table.contains("max depth            2      8\n"));
+/// ```
+pub fn comparison_table(columns: &[(&str, &DistanceStats)]) -> String {
+    let all_rows: Vec<Vec<(String, String)>> =
+        columns.iter().map(|(_, stats)| stats.rows()).collect();
+    let label_width = all_rows.first().map_or(0, |rows| {
+        rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0)
+    });
+    let widths: Vec<usize> = columns
+        .iter()
+        .zip(&all_rows)
+        .map(|((name, _), rows)| {
+            rows.iter()
+                .map(|(_, value)| value.len())
+                .chain(Some(name.len()))
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    let mut table = format!("{:<width$}", "", width = label_width);
+    for ((name, _), width) in columns.iter().zip(&widths) {
+        table += &format!("  {:>width$}", name, width = width);
+    }
+    table.push('\n');
+
+    let row_count = all_rows.first().map_or(0, |rows| rows.len());
+    for row in 0..row_count {
+        table += &format!("{:<width$}", all_rows[0][row].0, width = label_width);
+        for (rows, width) in all_rows.iter().zip(&widths) {
+            table += &format!("  {:>width$}", rows[row].1, width = width);
+        }
+        table.push('\n');
+    }
+    table
+}
+
+/// Writes the statistics of many maps as a JSON object, with the given names as the keys.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::cities::stats::*;
+///
+/// let stats = DistanceStats::from_histogram(&[1], 1);
+/// let json = comparison_json(&[("single", &stats)]);
+/// assert!(json.starts_with("{\"single\":{\"cities\":1,"));
+/// ```
+pub fn comparison_json(columns: &[(&str, &DistanceStats)]) -> String {
+    let entries: Vec<String> = columns
+        .iter()
+        .map(|(name, stats)| format!("\"{}\":{}", escape_json(name), stats.to_json()))
+        .collect();
+    format!("{{{}}}", entries.join(","))
+}
+
+fn escape_json(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '"' => "\\\"".chars().collect::<Vec<char>>(),
+            '\\' => "\\\\".chars().collect(),
+            c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32).chars().collect(),
+            c => vec![c],
+        })
+        .collect()
+}
@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod stats_tests {
+    use crate::cities::stats::*;
+    use crate::cities::*;
+    use crate::utils::prng::seeded_rng;
+
+    use spectral::prelude::*;
+
+    #[test]
+    fn should_describe_single_city() {
+        let stats = DistanceStats::new(&[0]).unwrap();
+        assert_that!(stats.cities).is_equal_to(1);
+        assert_that!(stats.max_depth).is_equal_to(0);
+        assert_that!(stats.leaves).is_equal_to(1);
+        assert_that!(stats.mean).is_equal_to(0.0);
+        assert_that!(stats.variance).is_equal_to(0.0);
+        assert_that!(stats.percentile(99.0)).is_equal_to(0);
+    }
+
+    #[test]
+    fn should_reject_invalid_maps() {
+        assert_that!(DistanceStats::new(&[1, 0])).is_equal_to(Err(CityMapError::NoCapital));
+    }
+
+    #[test]
+    fn should_describe_chain() {
+        // 0 <- 1 <- 2 <- ... <- 9
+        let city_vec: Vec<usize> = (0..10).map(|c: usize| c.saturating_sub(1)).collect();
+        let stats = DistanceStats::new(&city_vec).unwrap();
+        assert_that!(stats.max_depth).is_equal_to(9);
+        assert_that!(stats.leaves).is_equal_to(1);
+        assert_that!(stats.mean).is_equal_to(4.5);
+        assert_that!(stats.variance).is_equal_to(8.25);
+        assert_that!(stats.median).is_equal_to(4);
+        assert_that!(stats.percentile(25.0)).is_equal_to(2);
+        assert_that!(stats.percentile(0.0)).is_equal_to(0);
+    }
+
+    #[test]
+    fn should_agree_with_solver() {
+        let city_vec = gen_cities_with_rng(&CityGenConfig::new(2000), &mut seeded_rng(7));
+        let stats = DistanceStats::new(&city_vec).unwrap();
+        let histogram = find_city_distances(&city_vec).unwrap();
+        assert_that!(stats.histogram).is_equal_to(&histogram);
+        assert_that!(stats.cities).is_equal_to(2000);
+        assert_that!(stats.max_depth).is_equal_to(histogram.len() - 1);
+        assert_that!(stats.leaves).is_equal_to(count_leaves(&city_vec));
+    }
+
+    #[test]
+    fn should_align_table_columns() {
+        let stats = DistanceStats::from_histogram(&[1, 2, 1], 2);
+        let table = stats.to_string();
+        assert_that!(table.lines().count()).is_equal_to(6 + REPORTED_PERCENTILES.len());
+        assert_that!(table).contains("mean             1.000\n");
+        assert_that!(table).contains("99th percentile  2\n");
+
+        let table = comparison_table(&[("a", &stats), ("a much longer name", &stats)]);
+        let widths: Vec<usize> = table.lines().map(str::len).collect();
+        assert_that!(widths.iter().all(|&w| w == widths[0])).is_true();
+    }
+
+    #[test]
+    fn should_escape_names_in_json() {
+        let stats = DistanceStats::from_histogram(&[1], 1);
+        let json = comparison_json(&[("a \"quoted\"\\name", &stats)]);
+        assert_that!(json).starts_with("{\"a \\\"quoted\\\"\\\\name\":{");
+    }
+
+    quickcheck! {
+        fn percentiles_are_monotonic(histogram: Vec<u8>) -> bool {
+            let histogram: Vec<usize> = histogram.into_iter().map(usize::from).collect();
+            let stats = DistanceStats::from_histogram(&histogram, 0);
+            let percentiles: Vec<usize> = (0..=100).map(|p| stats.percentile(f64::from(p))).collect();
+            percentiles.windows(2).all(|w| w[0] <= w[1])
+                && percentiles.iter().all(|&d| d <= stats.max_depth)
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Generators of uniformly random city maps, and Prüfer sequences.
+//!
+//! There are n^(n-1) different maps of `n` cities: n^(n-2) labelled trees, as given by Cayley's
+//! formula, times `n` possible capitals. Every generator here gives each of them the same
+//! probability, which is not the case for [`gen_cities`] or [`gen_cities_uset`].
+//!
+//! [`gen_cities`]: ../fn.gen_cities.html
+//! [`gen_cities_uset`]: ../fn.gen_cities_uset.html
+
+use rand::Rng;
+
+use super::city_map::{CityMap, CityMapError};
+use super::progress::{Cancelled, Monitor};
+
+// Turns the road from `city` into the road to the capital: reverses all roads between `city`
+// and the current capital.
+fn move_capital(city_vec: &mut [usize], city: usize) {
+    let mut prev = city;
+    let mut current = city_vec[city];
+    city_vec[city] = city;
+    while current != prev {
+        let next = city_vec[current];
+        city_vec[current] = prev;
+        if next == current {
+            break;
+        }
+        prev = current;
+        current = next;
+    }
+}
+
+/// Encodes the map as a Prüfer sequence in O(n). The sequence describes which cities are
+/// connected, but not which one is the capital, so both are needed by [`from_prufer`].
+///
+/// # Errors
+///
+/// Returns an error if `city_vec` is not a valid map. See [`CityMap::validate`].
+///
+/// # Panics
+///
+/// Panics if the map has only one city: there are no Prüfer sequences of maps so small.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::uniform::*;
+///
+/// // 0 - 4 - 1, 2 - 4, and 3 - 2, with 0 as the capital
+/// let city_vec = vec![0, 4, 4, 2, 0];
+/// assert_eq!(to_prufer(&city_vec), Ok(vec![4, 4, 2]));
+/// assert_eq!(from_prufer(&[4, 4, 2], 0), city_vec);
+/// ```
+///
+/// [`from_prufer`]: fn.from_prufer.html
+/// [`CityMap::validate`]: ../city_map/struct.CityMap.html#method.validate
+pub fn to_prufer(city_vec: &[usize]) -> Result<Vec<usize>, CityMapError> {
+    CityMap::validate(city_vec)?;
+    let n = city_vec.len();
+    assert!(n > 1, "a map with one city has no Prüfer sequence");
+
+    // the algorithm needs the last city to be the capital
+    let mut parents = city_vec.to_vec();
+    move_capital(&mut parents, n - 1);
+
+    let mut degrees = vec![0; n];
+    for city in 0..n - 1 {
+        degrees[city] += 1;
+        degrees[parents[city]] += 1;
+    }
+
+    let mut pointer = degrees.iter().position(|&d| d == 1).unwrap();
+    let mut leaf = pointer;
+    let mut sequence = Vec::with_capacity(n - 2);
+    for _ in 0..n - 2 {
+        let next = parents[leaf];
+        sequence.push(next);
+        degrees[next] -= 1;
+        if degrees[next] == 1 && next < pointer {
+            leaf = next;
+        } else {
+            pointer += 1;
+            while degrees[pointer] != 1 {
+                pointer += 1;
+            }
+            leaf = pointer;
+        }
+    }
+    Ok(sequence)
+}
+
+/// Decodes a Prüfer sequence into a map of `sequence.len() + 2` cities, in O(n).
+///
+/// # Panics
+///
+/// Panics if the sequence or the capital contain a number which is not a city on such a map.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::uniform::*;
+///
+/// assert_eq!(from_prufer(&[], 1), vec![1, 1]);
+/// assert_eq!(from_prufer(&[3, 3], 3), vec![3, 3, 3, 3]);
+/// ```
+pub fn from_prufer(sequence: &[usize], capital: usize) -> Vec<usize> {
+    let n = sequence.len() + 2;
+    assert!(
+        capital < n && sequence.iter().all(|&city| city < n),
+        "not a Prüfer sequence of a map with {} cities",
+        n
+    );
+
+    let mut degrees = vec![1; n];
+    sequence.iter().for_each(|&city| degrees[city] += 1);
+
+    let mut city_vec = vec![0; n];
+    let mut pointer = degrees.iter().position(|&d| d == 1).unwrap();
+    let mut leaf = pointer;
+    for &next in sequence {
+        city_vec[leaf] = next;
+        degrees[next] -= 1;
+        if degrees[next] == 1 && next < pointer {
+            leaf = next;
+        } else {
+            pointer += 1;
+            while degrees[pointer] != 1 {
+                pointer += 1;
+            }
+            leaf = pointer;
+        }
+    }
+    city_vec[leaf] = n - 1;
+    city_vec[n - 1] = n - 1;
+
+    move_capital(&mut city_vec, capital);
+    city_vec
+}
+
+/// Generates a uniformly random map by decoding a random Prüfer sequence. Runs in O(n).
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::uniform::*;
+/// use rust_experiments::cities::find_city_distances;
+///
+/// let city_vec = gen_uniform_cities(1000, &mut rand::thread_rng());
+/// assert_eq!(city_vec.len(), 1000);
+/// assert!(find_city_distances(&city_vec).is_ok());
+/// ```
+pub fn gen_uniform_cities<R: Rng>(size: usize, r: &mut R) -> Vec<usize> {
+    gen_uniform_cities_monitored(size, r, &mut Monitor::new())
+        .expect("a monitor without a token cannot cancel")
+}
+
+/// Works like [`gen_uniform_cities`], but reports the progress to the monitor, and stops if
+/// it is cancelled. The progress counts the drawn numbers of the Prüfer sequence.
+///
+/// # Errors
+///
+/// Returns [`Cancelled`] if the monitor's token is cancelled.
+///
+/// [`gen_uniform_cities`]: fn.gen_uniform_cities.html
+/// [`Cancelled`]: ../progress/struct.Cancelled.html
+pub fn gen_uniform_cities_monitored<R: Rng>(
+    size: usize,
+    r: &mut R,
+    monitor: &mut Monitor,
+) -> Result<Vec<usize>, Cancelled> {
+    assert!(size > 0, "a map needs at least one city");
+    monitor.restart();
+    if size == 1 {
+        return Ok(vec![0]);
+    }
+    let mut sequence = Vec::with_capacity(size - 2);
+    while sequence.len() < size - 2 {
+        sequence.push(r.gen_range(0, size));
+        monitor.step(sequence.len(), size - 2)?;
+    }
+    Ok(from_prufer(&sequence, r.gen_range(0, size)))
+}
+
+// Makes a step of a random walk on the complete graph, where every road leads to every other city.
+fn random_neighbour<R: Rng>(city: usize, size: usize, r: &mut R) -> usize {
+    let next = r.gen_range(0, size - 1);
+    if next >= city {
+        next + 1
+    } else {
+        next
+    }
+}
+
+/// Generates a uniformly random map with Wilson's algorithm: starting from a random capital,
+/// adds loop-erased random walks from every city which is not on the map yet, until they reach
+/// the map. Runs in O(n log n) on average.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::uniform::*;
+///
+/// let city_vec = gen_uniform_cities_wilson(1000, &mut rand::thread_rng());
+/// assert_eq!(city_vec.len(), 1000);
+/// ```
+pub fn gen_uniform_cities_wilson<R: Rng>(size: usize, r: &mut R) -> Vec<usize> {
+    gen_uniform_cities_wilson_monitored(size, r, &mut Monitor::new())
+        .expect("a monitor without a token cannot cancel")
+}
+
+/// Works like [`gen_uniform_cities_wilson`], but reports the number of cities on the map to
+/// the monitor, and stops if it is cancelled.
+///
+/// # Errors
+///
+/// Returns [`Cancelled`] if the monitor's token is cancelled.
+///
+/// [`gen_uniform_cities_wilson`]: fn.gen_uniform_cities_wilson.html
+/// [`Cancelled`]: ../progress/struct.Cancelled.html
+pub fn gen_uniform_cities_wilson_monitored<R: Rng>(
+    size: usize,
+    r: &mut R,
+    monitor: &mut Monitor,
+) -> Result<Vec<usize>, Cancelled> {
+    assert!(size > 0, "a map needs at least one city");
+    monitor.restart();
+    let capital = r.gen_range(0, size);
+    let mut city_vec = vec![0; size];
+    let mut on_map = vec![false; size];
+    city_vec[capital] = capital;
+    on_map[capital] = true;
+    let mut on_map_count = 1;
+
+    for start in 0..size {
+        // only the last exit from every city is remembered, which erases the loops
+        let mut city = start;
+        while !on_map[city] {
+            city_vec[city] = random_neighbour(city, size, r);
+            city = city_vec[city];
+        }
+        let mut city = start;
+        while !on_map[city] {
+            on_map[city] = true;
+            on_map_count += 1;
+            city = city_vec[city];
+        }
+        monitor.step(on_map_count, size)?;
+    }
+    Ok(city_vec)
+}
+
+/// Generates a uniformly random map with the Aldous-Broder algorithm: walks randomly from
+/// a random capital, and every city is joined to the map with a road to the city from which
+/// it was entered for the first time. Runs in O(n log n) on average.
+///
+/// # Example
+///
+/// ```
+/// use rust_experiments::cities::uniform::*;
+///
+/// let city_vec = gen_uniform_cities_aldous_broder(1000, &mut rand::thread_rng());
+/// assert_eq!(city_vec.len(), 1000);
+/// ```
+pub fn gen_uniform_cities_aldous_broder<R: Rng>(size: usize, r: &mut R) -> Vec<usize> {
+    gen_uniform_cities_aldous_broder_monitored(size, r, &mut Monitor::new())
+        .expect("a monitor without a token cannot cancel")
+}
+
+/// Works like [`gen_uniform_cities_aldous_broder`], but reports the number of visited cities
+/// to the monitor, and stops if it is cancelled.
+///
+/// # Errors
+///
+/// Returns [`Cancelled`] if the monitor's token is cancelled.
+///
+/// [`gen_uniform_cities_aldous_broder`]: fn.gen_uniform_cities_aldous_broder.html
+/// [`Cancelled`]: ../progress/struct.Cancelled.html
+pub fn gen_uniform_cities_aldous_broder_monitored<R: Rng>(
+    size: usize,
+    r: &mut R,
+    monitor: &mut Monitor,
+) -> Result<Vec<usize>, Cancelled> {
+    assert!(size > 0, "a map needs at least one city");
+    monitor.restart();
+    let capital = r.gen_range(0, size);
+    let mut city_vec = vec![usize::MAX; size];
+    city_vec[capital] = capital;
+
+    let mut visited = 1;
+    let mut city = capital;
+    while visited < size {
+        let next = random_neighbour(city, size, r);
+        if city_vec[next] == usize::MAX {
+            city_vec[next] = city;
+            visited += 1;
+            monitor.step(visited, size)?;
+        }
+        city = next;
+    }
+    Ok(city_vec)
+}
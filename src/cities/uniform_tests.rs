@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod uniform_tests {
+    use crate::cities::uniform::*;
+    use crate::cities::*;
+    use crate::utils::prng::seeded_rng;
+
+    use rand::XorShiftRng;
+    use std::collections::HashMap;
+
+    use spectral::prelude::*;
+
+    // All sequences of `len` numbers lower than `base`.
+    fn all_sequences(len: usize, base: usize) -> Vec<Vec<usize>> {
+        (0..len).fold(vec![vec![]], |acc, _| {
+            acc.iter()
+                .flat_map(|s| {
+                    (0..base).map(move |x| {
+                        let mut s = s.clone();
+                        s.push(x);
+                        s
+                    })
+                })
+                .collect()
+        })
+    }
+
+    // Pearson's chi-squared statistic of the counts of generated maps, assuming every map
+    // of `size` cities is equally probable.
+    fn chi_squared<F>(size: usize, samples: usize, generate: F) -> f64
+    where
+        F: Fn(&mut XorShiftRng) -> Vec<usize>,
+    {
+        let mut rng = seeded_rng(size as u64);
+        let mut counts: HashMap<Vec<usize>, usize> = HashMap::new();
+        for _ in 0..samples {
+            *counts.entry(generate(&mut rng)).or_insert(0) += 1;
+        }
+        let maps = size.pow(size as u32 - 1);
+        assert_that!(counts.len()).is_equal_to(maps);
+        let expected = samples as f64 / maps as f64;
+        counts
+            .values()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    // With 63 degrees of freedom, the statistic exceeds this value with the probability 0.001.
+    const CHI_SQUARED_63_DF: f64 = 103.4;
+
+    #[test]
+    fn should_decode_every_sequence_to_a_different_map() {
+        let mut maps = Vec::new();
+        for sequence in all_sequences(3, 5) {
+            for capital in 0..5 {
+                let v = from_prufer(&sequence, capital);
+                assert_that!(CityMap::validate(&v)).is_equal_to(Ok(capital));
+                assert_that!(to_prufer(&v)).is_equal_to(Ok(sequence.clone()));
+                maps.push(v);
+            }
+        }
+        maps.sort();
+        maps.dedup();
+        assert_that!(maps).has_length(5usize.pow(4));
+    }
+
+    #[test]
+    fn should_reject_invalid_map() {
+        assert_that!(to_prufer(&[1, 0])).is_equal_to(Err(CityMapError::NoCapital));
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_not_decode_numbers_outside_map() {
+        from_prufer(&[0, 4], 0);
+    }
+
+    #[test]
+    fn should_generate_single_city() {
+        let mut rng = seeded_rng(1);
+        assert_that!(gen_uniform_cities(1, &mut rng)).is_equal_to(vec![0]);
+        assert_that!(gen_uniform_cities_wilson(1, &mut rng)).is_equal_to(vec![0]);
+        assert_that!(gen_uniform_cities_aldous_broder(1, &mut rng)).is_equal_to(vec![0]);
+    }
+
+    #[test]
+    fn should_generate_valid_large_maps() {
+        let mut rng = seeded_rng(2);
+        for v in &[
+            gen_uniform_cities(100_000, &mut rng),
+            gen_uniform_cities_wilson(100_000, &mut rng),
+            gen_uniform_cities_aldous_broder(100_000, &mut rng),
+        ] {
+            assert_that!(v.len()).is_equal_to(100_000);
+            assert_that!(CityMap::validate(v)).is_ok();
+        }
+    }
+
+    #[test]
+    fn should_generate_all_maps_of_four_cities_uniformly() {
+        let samples = 64_000;
+        assert_that!(chi_squared(4, samples, |r| gen_uniform_cities(4, r)))
+            .is_less_than(CHI_SQUARED_63_DF);
+        assert_that!(chi_squared(4, samples, |r| gen_uniform_cities_wilson(4, r)))
+            .is_less_than(CHI_SQUARED_63_DF);
+        assert_that!(chi_squared(4, samples, |r| {
+            gen_uniform_cities_aldous_broder(4, r)
+        }))
+        .is_less_than(CHI_SQUARED_63_DF);
+    }
+
+    #[test]
+    fn should_not_generate_all_maps_uniformly_with_gen_cities() {
+        // the statistic is far beyond any reasonable threshold, which shows the test can fail
+        let config = CityGenConfig::new(4);
+        let statistic = chi_squared(4, 64_000, |r| gen_cities_with_rng(&config, r));
+        assert_that!(statistic).is_greater_than(10.0 * CHI_SQUARED_63_DF);
+    }
+
+    quickcheck! {
+        fn prufer_round_trip(seed: u64, size: usize) -> bool {
+            let v = gen_cities_with_rng(&CityGenConfig::new(size % 300 + 2), &mut seeded_rng(seed));
+            let capital = CityMap::validate(&v).unwrap();
+            from_prufer(&to_prufer(&v).unwrap(), capital) == v
+        }
+    }
+}
@@ -1,6 +1,10 @@
 #![allow(unknown_lints)]
 
 use std::env;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::thread;
+use std::time::Duration;
 
 #[cfg(test)]
 #[macro_use]
@@ -28,6 +32,7 @@ pub mod redis;
 
 extern crate rand;
 
+use cities::progress::{CancellationToken, Cancelled, SolveError};
 use flexi_logger::*;
 
 fn main() {
@@ -47,36 +52,267 @@ fn main() {
     //strings::strings();
 }
 
+// Removes `<name> <value>` from the arguments and returns the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let pos = args.iter().position(|arg| arg == name)?;
+    if pos + 1 >= args.len() {
+        panic!("{} requires a value", name);
+    }
+    let value = args.remove(pos + 1);
+    args.remove(pos);
+    Some(value)
+}
+
+// Removes `<name>` from the arguments and returns whether it was there.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(pos) => {
+            args.remove(pos);
+            true
+        }
+        None => false,
+    }
+}
+
+// Creates a monitor which logs the progress of the task about every 5%.
+fn progress_monitor<'a>(
+    task: &'a str,
+    total: usize,
+    token: &CancellationToken,
+) -> cities::Monitor<'a> {
+    cities::Monitor::new()
+        .with_interval((total / 20).max(1))
+        .with_token(token.clone())
+        .with_callback(move |progress| {
+            info!(
+                "{}: {}/{} cities ({:.0}%)",
+                task,
+                progress.done,
+                progress.total,
+                progress.fraction() * 100.0
+            )
+        })
+}
+
+fn map_format(path: &str) -> cities::formats::MapFormat {
+    cities::formats::MapFormat::from_path(path)
+        .unwrap_or_else(|| panic!("Unknown map format of {}", path))
+}
+
+// Generator settings which can be given only as options.
+struct GenOptions {
+    seed: u64,
+    branching: Option<String>,
+    depth: Option<String>,
+}
+
 fn cities() {
-    let size = match env::args().nth(1) {
-        Some(s) => s.parse::<usize>().unwrap(),
-        None => 25_000,
-    };
+    let mut args: Vec<String> = env::args().skip(1).collect();
 
-    let max_broad = match env::args().nth(2) {
-        Some(max) => max.parse::<usize>().unwrap(),
-        None => 5,
+    // options may appear anywhere; the rest of the arguments are positional
+    let seed = match take_option(&mut args, "--seed") {
+        Some(seed) => seed.parse::<u64>().unwrap(),
+        None => rand::random(),
     };
-
-    let knots_occurence = match env::args().nth(3) {
-        Some(occ) => occ.parse::<f32>().unwrap(),
-        None => 0.1,
+    let json = take_flag(&mut args, "--json");
+    let compare = take_flag(&mut args, "--compare");
+    let load = take_option(&mut args, "--load");
+    let save = take_option(&mut args, "--save");
+    let options = GenOptions {
+        seed,
+        branching: take_option(&mut args, "--branching"),
+        depth: take_option(&mut args, "--depth"),
     };
 
-    let knots_max_broad = match env::args().nth(4) {
-        Some(max) => max.parse::<usize>().unwrap(),
-        None => 25,
+    // long runs are cancelled after the timeout, and stop at the next progress report
+    let token = CancellationToken::new();
+    if let Some(timeout) = take_option(&mut args, "--timeout") {
+        let timeout = Duration::from_secs(timeout.parse::<u64>().unwrap());
+        let token = token.clone();
+        thread::spawn(move || {
+            thread::sleep(timeout);
+            warn!("Timeout of {:?} reached, cancelling", timeout);
+            token.cancel();
+        });
+    }
+
+    if compare {
+        compare_generators(&gen_config(&args, &options), options.seed, json, &token);
+        return;
+    }
+
+    let city_array = match load {
+        Some(path) => {
+            let file = File::open(&path).unwrap_or_else(|e| panic!("Cannot open {}: {}", path, e));
+            match cities::formats::read_map(map_format(&path), BufReader::new(file)) {
+                Ok(city_array) => {
+                    info!("Map loaded from {}", path);
+                    city_array
+                }
+                Err(err) => {
+                    error!("Cannot load {}: {}", path, err);
+                    return;
+                }
+            }
+        }
+        None => match generate_cities(&gen_config(&args, &options), options.seed, &token) {
+            Some(city_array) => city_array,
+            None => return,
+        },
     };
 
-    info!("Cities standard generator chosen with options: size={}, max_broad={}, knots_occurence={}, knots_max_broad={}",
-          size, max_broad, knots_occurence, knots_max_broad
+    if let Some(path) = save {
+        let file = File::create(&path).unwrap_or_else(|e| panic!("Cannot create {}: {}", path, e));
+        let mut writer = BufWriter::new(file);
+        match cities::formats::write_map(&city_array, map_format(&path), &mut writer)
+            .and_then(|_| writer.flush())
+        {
+            Ok(()) => info!("Map saved to {}", path),
+            Err(err) => error!("Cannot save {}: {}", path, err),
+        }
+    }
+
+    let mut monitor = progress_monitor("Solving", city_array.len(), &token);
+    match cities::find_city_distances_monitored(&city_array, &mut monitor) {
+        Ok(histogram) => {
+            info!("Map solved: {:?}", histogram);
+            let leaves = cities::stats::count_leaves(&city_array);
+            let stats = cities::stats::DistanceStats::from_histogram(&histogram, leaves);
+            if json {
+                println!("{}", stats.to_json());
+            } else {
+                print!("{}", stats);
+            }
+        }
+        Err(SolveError::Cancelled) => warn!("Solving cancelled"),
+        Err(SolveError::InvalidMap(err)) => error!("Invalid map: {}", err),
+    }
+}
+
+fn gen_config(args: &[String], options: &GenOptions) -> cities::CityGenConfig {
+    let mut config = cities::CityGenConfig::default();
+
+    if let Some(size) = args.first() {
+        config.size = size.parse::<usize>().unwrap();
+    }
+
+    if let Some(max) = args.get(1) {
+        config.branching = cities::Branching::Uniform {
+            max_roads: max.parse::<usize>().unwrap(),
+        };
+    }
+
+    if let Some(occ) = args.get(2) {
+        config.centre_probability = occ.parse::<f32>().unwrap();
+    }
+
+    if let Some(max) = args.get(3) {
+        config.centre_max_roads = max.parse::<usize>().unwrap();
+    }
+
+    if let Some(branching) = &options.branching {
+        config.branching = branching.parse().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    if let Some(depth) = &options.depth {
+        config.target_depth = Some(depth.parse::<usize>().unwrap());
+    }
+
+    config
+}
+
+fn generate_cities(
+    config: &cities::CityGenConfig,
+    seed: u64,
+    token: &CancellationToken,
+) -> Option<Vec<usize>> {
+    info!(
+        "Cities standard generator chosen with options: {:?}, seed={}",
+        config, seed
     );
 
-    let city_array = cities::gen_cities(size, max_broad, knots_occurence, knots_max_broad);
+    let mut rng = utils::prng::seeded_rng(seed);
+    let mut monitor = progress_monitor("Generating", config.size, token);
+    match cities::gen_cities_monitored(config, &mut rng, &mut monitor) {
+        Ok(city_array) => {
+            info!("Map generated");
+            Some(city_array)
+        }
+        Err(Cancelled) => {
+            warn!("Generation cancelled");
+            None
+        }
+    }
+}
 
-    info!("Map generated");
+// A generator of city maps, which can be cancelled while it runs.
+type Generator<'a> = Box<dyn Fn() -> Result<Vec<usize>, Cancelled> + 'a>;
+
+// Generates maps of the same size with every generator, and reports their shapes side by side.
+fn compare_generators(
+    config: &cities::CityGenConfig,
+    seed: u64,
+    json: bool,
+    token: &CancellationToken,
+) {
+    // the older generators only support uniform branching, so other kinds fall back to
+    // the default limit of roads
+    let max_roads = match config.branching {
+        cities::Branching::Uniform { max_roads }
+        | cities::Branching::PseudoNormal { max_roads, .. } => max_roads,
+        _ => 5,
+    };
+    info!(
+        "Comparing generators with options: {:?}, seed={}",
+        config, seed
+    );
 
-    let result = cities::find_city_distances(&city_array);
+    let rng = || utils::prng::seeded_rng(seed);
+    let monitor = |name| progress_monitor(name, config.size, token);
+    let generators: Vec<(&str, Generator)> = vec![
+        (
+            "gen_cities",
+            Box::new(|| {
+                cities::gen_cities_monitored(config, &mut rng(), &mut monitor("gen_cities"))
+            }),
+        ),
+        (
+            "gen_cities_uset",
+            Box::new(|| {
+                let monitor = &mut monitor("gen_cities_uset");
+                cities::gen_cities_uset_monitored(config.size, max_roads, &mut rng(), monitor)
+            }),
+        ),
+        (
+            "gen_cities_hashset",
+            Box::new(|| {
+                let monitor = &mut monitor("gen_cities_hashset");
+                cities::gen_cities_hashset_monitored(config.size, max_roads, &mut rng(), monitor)
+            }),
+        ),
+    ];
 
-    info!("Map solved: {:?}", result);
+    let mut all_stats = Vec::new();
+    for (name, generate) in &generators {
+        let city_array = match generate() {
+            Ok(city_array) => city_array,
+            Err(Cancelled) => {
+                warn!("Comparison cancelled while running {}", name);
+                return;
+            }
+        };
+        match cities::stats::DistanceStats::new(&city_array) {
+            Ok(stats) => all_stats.push((*name, stats)),
+            Err(err) => error!("{} generated an invalid map: {}", name, err),
+        }
+    }
+    let columns: Vec<(&str, &cities::stats::DistanceStats)> = all_stats
+        .iter()
+        .map(|(name, stats)| (*name, stats))
+        .collect();
+    if json {
+        println!("{}", cities::stats::comparison_json(&columns));
+    } else {
+        print!("{}", cities::stats::comparison_table(&columns));
+    }
 }
@@ -1,69 +1,617 @@
-use std::collections::VecDeque;
+use std::fmt;
+use std::io::{Read, Write};
 use std::iter::Iterator;
-use std::net::TcpStream;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use super::streams;
+use super::pubsub::{Broker, Kind, Mailbox, Push, SubscriberId, MAX_PATTERN_LENGTH};
+use super::resp::{Parser, Value};
+use super::store::{End, Store, Ticket};
+use super::streams::{Connection, Writer};
 
-enum Behaviour {
+/// The protocol spoken with clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    /// RESP2, as spoken by `redis-cli` and client libraries.
+    #[default]
+    Resp,
+    /// The old plain text protocol: one command per line, e.g. `ENQUEUE q,a,b`, with replies
+    /// as lines.
+    Line,
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "resp" => Ok(Protocol::Resp),
+            "line" => Ok(Protocol::Line),
+            _ => Err(format!("Unknown protocol {}, expected resp or line", s)),
+        }
+    }
+}
+
+/// A command with its arguments, independent of the protocol it came in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    /// The name in upper case.
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl Command {
+    /// Parses a line of the plain text protocol: the name, and arguments separated with commas.
+    pub fn from_line(line: &str) -> Command {
+        let line = line.trim();
+        let (name, rest) = match line.find(char::is_whitespace) {
+            Some(pos) => (&line[..pos], line[pos..].trim()),
+            None => (line, ""),
+        };
+        let args = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',')
+                .map(|word| word.trim().to_string())
+                .collect()
+        };
+        Command {
+            name: name.to_uppercase(),
+            args,
+        }
+    }
+
+    /// Parses a RESP request: an array of bulk strings, the first one being the name.
+    pub fn from_value(value: Value) -> Result<Command, String> {
+        let values = match value {
+            Value::Array(values) if !values.is_empty() => values,
+            _ => return Err("Protocol error: expected a non-empty array".into()),
+        };
+        let mut words = values.into_iter().map(|value| match value {
+            Value::Bulk(data) => String::from_utf8(data)
+                .map_err(|_| "Protocol error: invalid UTF-8 in a bulk string".to_string()),
+            _ => Err("Protocol error: expected bulk strings".into()),
+        });
+        let name = words.next().unwrap()?.to_uppercase();
+        let args = words.collect::<Result<Vec<String>, String>>()?;
+        Ok(Command { name, args })
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.args.is_empty() {
+            write!(f, " {}", self.args.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) enum Behaviour {
     Continue,
     Result(String),
+    Integer(i64),
+    List(Vec<String>),
+    /// Nothing to return, e.g. after a timeout.
+    Nil,
+    /// The client waits in line for a word, until the deadline if there is one.
+    Wait(Ticket, Option<Instant>),
+    /// The replies are in the mailbox of the client.
+    Pushed,
     Stop,
 }
 
-fn perform(command: &str, queue: &mut VecDeque<String>) -> Result<Behaviour, String> {
-    if command.starts_with("PUBLISH") {
-        if command.trim() == "PUBLISH" {
-            Err("Error: Empty PUBLISH".into())
-        } else {
-            (&command[8..])
-                .split(',')
-                .for_each(|word| queue.push_back(word.trim().into()));
+impl Behaviour {
+    // The outcome of waiting, with the word if it came before the deadline.
+    pub(crate) fn waited(word: Option<String>) -> Behaviour {
+        word.map_or(Behaviour::Nil, Behaviour::Result)
+    }
+}
+
+// Parses the timeout of BRETRIEVE in seconds, where 0 means no timeout, as in BLPOP.
+fn parse_timeout(arg: &str) -> Result<Option<Duration>, String> {
+    match arg.parse::<f64>() {
+        Ok(0.0) => Ok(None),
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Duration::try_from_secs_f64(secs)
+            .map(Some)
+            .map_err(|_| "timeout is out of range".into()),
+        Ok(secs) if secs < 0.0 => Err("timeout is negative".into()),
+        _ => Err("timeout is not a float or out of range".into()),
+    }
+}
+
+fn parse_integer(arg: &str) -> Result<i64, String> {
+    arg.parse::<i64>()
+        .map_err(|_| "value is not an integer or out of range".into())
+}
+
+// Checks the number of arguments, which is exact, or a minimum if `at_least` is set.
+fn check_arity(command: &Command, count: usize, at_least: bool) -> Result<(), String> {
+    let len = command.args.len();
+    if len == count || (at_least && len > count) {
+        Ok(())
+    } else {
+        Err(format!(
+            "wrong number of arguments for '{}' command",
+            command.name.to_lowercase()
+        ))
+    }
+}
+
+/// What the server keeps about a client between its commands.
+pub(crate) struct Session {
+    pub(crate) mailbox: Arc<Mailbox>,
+    // registered with the broker on the first subscription command
+    subscriber: Option<SubscriberId>,
+}
+
+impl Session {
+    pub(crate) fn new(mailbox: Arc<Mailbox>) -> Self {
+        Session {
+            mailbox,
+            subscriber: None,
+        }
+    }
+
+    fn subscriber(&mut self, broker: &Broker) -> SubscriberId {
+        let mailbox = &self.mailbox;
+        *self
+            .subscriber
+            .get_or_insert_with(|| broker.register(mailbox.clone()))
+    }
+
+    // A client subscribed to anything may only change its subscriptions, as in Redis.
+    fn subscribed(&self, broker: &Broker) -> bool {
+        self.subscriber
+            .is_some_and(|id| broker.subscriptions(id) > 0)
+    }
+
+    /// Drops the subscriptions of a client which is gone.
+    pub(crate) fn close(&mut self, broker: &Broker) {
+        if let Some(id) = self.subscriber.take() {
+            broker.unregister(id);
+        }
+    }
+}
+
+// Returns the list the command takes a word from, if it is one of those which do. The word
+// can be given back if it cannot be sent.
+pub(crate) fn taken_from(command: &Command) -> Option<&str> {
+    match command.name.as_str() {
+        "RETRIEVE" | "BRETRIEVE" | "LPOP" => command.args.first().map(String::as_str),
+        _ => None,
+    }
+}
+
+fn push(command: &Command, store: &Store, end: End) -> Result<Behaviour, String> {
+    check_arity(command, 2, true)?;
+    let len = store
+        .push(&command.args[0], command.args[1..].iter().cloned(), end)
+        .map_err(|err| err.to_string())?;
+    Ok(Behaviour::Integer(len as i64))
+}
+
+fn pop(command: &Command, store: &Store, end: End) -> Result<Behaviour, String> {
+    check_arity(command, 1, false)?;
+    let word = store
+        .pop(&command.args[0], end)
+        .map_err(|err| err.to_string())?;
+    Ok(word.map_or(Behaviour::Nil, Behaviour::Result))
+}
+
+fn subscribe(
+    command: &Command,
+    broker: &Broker,
+    session: &mut Session,
+    kind: Kind,
+) -> Result<Behaviour, String> {
+    check_arity(command, 1, true)?;
+    let too_long = |pattern: &String| pattern.chars().count() > MAX_PATTERN_LENGTH;
+    if kind == Kind::Pattern && command.args.iter().any(too_long) {
+        return Err(format!(
+            "pattern is longer than {} characters",
+            MAX_PATTERN_LENGTH
+        ));
+    }
+    broker.subscribe(session.subscriber(broker), &command.args, kind);
+    Ok(Behaviour::Pushed)
+}
+
+fn unsubscribe(
+    command: &Command,
+    broker: &Broker,
+    session: &mut Session,
+    kind: Kind,
+) -> Result<Behaviour, String> {
+    broker.unsubscribe(session.subscriber(broker), &command.args, kind);
+    Ok(Behaviour::Pushed)
+}
+
+// Performs the command. A command which has to wait is not finished here: the server waits
+// for the word in its own way, and sends the reply when it comes.
+pub(crate) fn perform(
+    command: &Command,
+    store: &Store,
+    broker: &Broker,
+    session: &mut Session,
+) -> Result<Behaviour, String> {
+    let args = &command.args;
+    let name = command.name.as_str();
+    let allowed = [
+        "SUBSCRIBE",
+        "PSUBSCRIBE",
+        "UNSUBSCRIBE",
+        "PUNSUBSCRIBE",
+        "PING",
+        "STOP",
+    ];
+    if !allowed.contains(&name) && session.subscribed(broker) {
+        return Err(format!(
+            "Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / STOP are allowed \
+             in this context",
+            name.to_lowercase()
+        ));
+    }
+    match name {
+        "ENQUEUE" => {
+            if args.len() < 2 {
+                return Err("Empty ENQUEUE".into());
+            }
+            store
+                .push(&args[0], args[1..].iter().cloned(), End::Back)
+                .map_err(|err| err.to_string())?;
             Ok(Behaviour::Continue)
         }
-    } else if command.starts_with("RETRIEVE") {
-        if let Some(word) = queue.pop_front() {
-            Ok(Behaviour::Result(word))
-        } else {
-            Err("Error: Empty RETRIEVE".into())
+        "RETRIEVE" => {
+            check_arity(command, 1, false)?;
+            match store
+                .pop(&args[0], End::Front)
+                .map_err(|err| err.to_string())?
+            {
+                Some(word) => Ok(Behaviour::Result(word)),
+                None => Err("Empty RETRIEVE".into()),
+            }
         }
-    } else if command.starts_with("STOP") {
-        Ok(Behaviour::Stop)
-    } else {
-        Err(format!("Error: Unrecognized command {}", command))
+        "BRETRIEVE" => {
+            check_arity(command, 2, false)?;
+            // the deadline is checked before joining the line, so that an error leaves no
+            // ticket behind
+            let deadline = match parse_timeout(&args[1])? {
+                Some(timeout) => Some(
+                    Instant::now()
+                        .checked_add(timeout)
+                        .ok_or("timeout is out of range")?,
+                ),
+                None => None,
+            };
+            match store.join(&args[0]).map_err(|err| err.to_string())? {
+                Ok(word) => Ok(Behaviour::Result(word)),
+                Err(ticket) => Ok(Behaviour::Wait(ticket, deadline)),
+            }
+        }
+        "GET" => {
+            check_arity(command, 1, false)?;
+            let value = store.get(&args[0]).map_err(|err| err.to_string())?;
+            Ok(value.map_or(Behaviour::Nil, Behaviour::Result))
+        }
+        "SET" => {
+            check_arity(command, 2, false)?;
+            store.set(&args[0], args[1].clone());
+            Ok(Behaviour::Continue)
+        }
+        "DEL" => {
+            check_arity(command, 1, true)?;
+            let count = store.del(args.iter().map(String::as_str));
+            Ok(Behaviour::Integer(count as i64))
+        }
+        "EXISTS" => {
+            check_arity(command, 1, true)?;
+            let count = store.exists(args.iter().map(String::as_str));
+            Ok(Behaviour::Integer(count as i64))
+        }
+        "EXPIRE" => {
+            check_arity(command, 2, false)?;
+            let secs = parse_integer(&args[1])?;
+            // as in Redis, a key set to expire in the past is removed at once
+            let done = if secs <= 0 {
+                store.del(Some(args[0].as_str())) > 0
+            } else {
+                let at = Instant::now()
+                    .checked_add(Duration::from_secs(secs as u64))
+                    .ok_or("invalid expire time")?;
+                store.expire_at(&args[0], at)
+            };
+            Ok(Behaviour::Integer(done as i64))
+        }
+        "TTL" => {
+            check_arity(command, 1, false)?;
+            let ttl = match store.ttl(&args[0]) {
+                None => -2,
+                Some(None) => -1,
+                Some(Some(ttl)) => ((ttl.as_millis() + 500) / 1000) as i64,
+            };
+            Ok(Behaviour::Integer(ttl))
+        }
+        "LPUSH" => push(command, store, End::Front),
+        "RPUSH" => push(command, store, End::Back),
+        "LPOP" => pop(command, store, End::Front),
+        "RPOP" => pop(command, store, End::Back),
+        "LLEN" => {
+            check_arity(command, 1, false)?;
+            let len = store.len(&args[0]).map_err(|err| err.to_string())?;
+            Ok(Behaviour::Integer(len as i64))
+        }
+        "LRANGE" => {
+            check_arity(command, 3, false)?;
+            let (start, stop) = (parse_integer(&args[1])?, parse_integer(&args[2])?);
+            let words = store
+                .range(&args[0], start, stop)
+                .map_err(|err| err.to_string())?;
+            Ok(Behaviour::List(words))
+        }
+        "PUBLISH" => {
+            check_arity(command, 2, false)?;
+            let count = broker.publish(&args[0], &args[1]);
+            Ok(Behaviour::Integer(count as i64))
+        }
+        "SUBSCRIBE" => subscribe(command, broker, session, Kind::Channel),
+        "PSUBSCRIBE" => subscribe(command, broker, session, Kind::Pattern),
+        "UNSUBSCRIBE" => unsubscribe(command, broker, session, Kind::Channel),
+        "PUNSUBSCRIBE" => unsubscribe(command, broker, session, Kind::Pattern),
+        "PING" => Ok(Behaviour::Result("PONG".into())),
+        "STOP" => Ok(Behaviour::Stop),
+        _ => Err(format!("Unrecognized command {}", command)),
     }
 }
 
-fn error(stream: &mut TcpStream, err_str: &str) -> Result<bool, String> {
-    let s = format!("Error: {}", err_str);
-    streams::write(stream, &s).unwrap();
-    Err(s)
+// Takes the next complete command from the parser, or `None` if more bytes are needed.
+pub(crate) fn next_command(
+    parser: &mut Parser,
+    protocol: Protocol,
+) -> Result<Option<Command>, String> {
+    let protocol_error = |err| format!("Protocol error: {}", err);
+    match protocol {
+        Protocol::Line => Ok(parser
+            .next_line()
+            .map_err(protocol_error)?
+            .map(|line| Command::from_line(&line))),
+        Protocol::Resp => match parser.next_value().map_err(protocol_error)? {
+            Some(value) => Command::from_value(value).map(Some),
+            None => Ok(None),
+        },
+    }
+}
+
+// Encodes the outcome of a command in the given protocol. In the plain text protocol,
+// commands which succeed with nothing to return get no reply.
+pub(crate) fn encode_reply(protocol: Protocol, outcome: &Result<Behaviour, String>) -> Vec<u8> {
+    match (protocol, outcome) {
+        (Protocol::Line, Ok(Behaviour::Result(word))) => format!("{}\n", word).into_bytes(),
+        (Protocol::Line, Ok(Behaviour::Integer(n))) => format!("{}\n", n).into_bytes(),
+        (Protocol::Line, Ok(Behaviour::List(words))) => {
+            format!("{}\n", words.join(",")).into_bytes()
+        }
+        (Protocol::Line, Ok(Behaviour::Nil)) => b"(nil)\n".to_vec(),
+        (_, Ok(Behaviour::Wait(_, _))) => unreachable!("the server waits before replying"),
+        (_, Ok(Behaviour::Pushed)) => Vec::new(),
+        (Protocol::Line, Ok(_)) => Vec::new(),
+        (Protocol::Line, Err(err)) => format!("Error: {}\n", err).into_bytes(),
+        (Protocol::Resp, Ok(Behaviour::Result(word))) => Value::bulk(word).encode(),
+        (Protocol::Resp, Ok(Behaviour::Integer(n))) => Value::Integer(*n).encode(),
+        (Protocol::Resp, Ok(Behaviour::List(words))) => {
+            Value::Array(words.iter().map(|word| Value::bulk(word)).collect()).encode()
+        }
+        (Protocol::Resp, Ok(Behaviour::Nil)) => Value::Null.encode(),
+        (Protocol::Resp, Ok(_)) => Value::Simple("OK".into()).encode(),
+        (Protocol::Resp, Err(err)) => Value::Error(format!("ERR {}", err)).encode(),
+    }
+}
+
+// Encodes a push in the given protocol: an array in RESP, as in Redis, and its words separated
+// with commas in the plain text protocol.
+pub(crate) fn encode_push(protocol: Protocol, push: &Push) -> Vec<u8> {
+    let words: Vec<Option<String>> = match push {
+        Push::Subscription {
+            command,
+            name,
+            count,
+        } => vec![
+            Some(command.to_string()),
+            name.clone(),
+            Some(count.to_string()),
+        ],
+        Push::Message { channel, payload } => vec![
+            Some("message".into()),
+            Some(channel.clone()),
+            Some(payload.clone()),
+        ],
+        Push::PatternMessage {
+            pattern,
+            channel,
+            payload,
+        } => vec![
+            Some("pmessage".into()),
+            Some(pattern.clone()),
+            Some(channel.clone()),
+            Some(payload.clone()),
+        ],
+    };
+    match (protocol, push) {
+        (Protocol::Line, _) => {
+            let words: Vec<String> = words.into_iter().map(Option::unwrap_or_default).collect();
+            format!("{}\n", words.join(",")).into_bytes()
+        }
+        (Protocol::Resp, Push::Subscription { count, .. }) => {
+            let mut values: Vec<Value> = words[..2]
+                .iter()
+                .map(|word| word.as_ref().map_or(Value::Null, |word| Value::bulk(word)))
+                .collect();
+            values.push(Value::Integer(*count as i64));
+            Value::Array(values).encode()
+        }
+        (Protocol::Resp, _) => Value::Array(
+            words
+                .iter()
+                .flatten()
+                .map(|word| Value::bulk(word))
+                .collect(),
+        )
+        .encode(),
+    }
+}
+
+// Takes the pushes waiting in the mailbox, encoded in the protocol.
+pub(crate) fn encode_pushes(protocol: Protocol, mailbox: &Mailbox) -> Vec<u8> {
+    mailbox
+        .take()
+        .iter()
+        .flat_map(|push| encode_push(protocol, push))
+        .collect()
 }
 
-fn flatten(res: Result<Result<Behaviour, String>, String>) -> Result<Behaviour, String> {
-    match res {
-        Ok(Ok(behaviour)) => Ok(behaviour),
-        Ok(err) => err,
-        Err(err) => Err(err),
+// Writes the pushes waiting in the mailbox, then the bytes. Whoever writes to the client goes
+// through here, so that nothing is interleaved and the pushes keep their order with the replies.
+fn send<W: Write>(
+    writer: &Mutex<W>,
+    mailbox: &Mailbox,
+    protocol: Protocol,
+    bytes: &[u8],
+) -> Result<(), String> {
+    let mut writer = writer.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut output = encode_pushes(protocol, mailbox);
+    output.extend(bytes);
+    if output.is_empty() {
+        return Ok(());
     }
+    writer.write_all(&output).map_err(|err| err.to_string())
 }
 
-pub fn handle(stream: &mut TcpStream, queue: &mut VecDeque<String>) -> Result<bool, String> {
-    let mut res = (Ok(false), false);
-    while !res.1 {
-        match flatten(streams::read(stream).map(|command| perform(&command, queue))) {
-            Ok(Behaviour::Result(word)) => {
-                streams::write(stream, &word)
-                    .map_err(|err| {
-                        res = (error(stream, &err.to_string()), true);
-                    })
-                    .unwrap();
+// Starts the thread sending the client the messages published to it, whenever it is told to,
+// so that publishers never wait for the client. The thread ends with the mailbox.
+fn start_pusher<W: Write + Send + 'static>(
+    writer: Arc<Mutex<W>>,
+    mailbox: Weak<Mailbox>,
+    notified: Receiver<()>,
+    protocol: Protocol,
+) -> Result<(), String> {
+    thread::Builder::new()
+        .name("redis pusher".into())
+        .spawn(move || {
+            for () in notified {
+                let sent = match mailbox.upgrade() {
+                    Some(mailbox) => send(&writer, &mailbox, protocol, &[]),
+                    None => return,
+                };
+                // the thread serving the client notices the failure as well
+                if sent.is_err() {
+                    return;
+                }
             }
-            Ok(Behaviour::Stop) => {
-                res = (Ok(true), true);
+        })
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// Serves one client until it sends `STOP` or disconnects. Returns `true` if the client sent
+/// `STOP`. A malformed RESP request is answered with an error, and ends the connection.
+///
+/// Many clients can be served at once from different threads. `BRETRIEVE` blocks the thread
+/// until a word comes or the timeout passes, so a client which disconnects in the meantime is
+/// noticed only when the reply is sent. The word is given back if sending fails, but a socket
+/// closed by the peer may still accept it; the event loop server does not have this problem.
+///
+/// Requests are read from `reader`, and replies written to `writer`. Once the client subscribes,
+/// the messages published to it are sent from another thread; a subscriber which lets more than
+/// [`MAX_PENDING_PUSHES`] of them pile up is disconnected.
+///
+/// [`MAX_PENDING_PUSHES`]: pubsub/constant.MAX_PENDING_PUSHES.html
+pub fn handle<R: Read, W: Writer>(
+    reader: R,
+    writer: W,
+    store: &Store,
+    broker: &Broker,
+    protocol: Protocol,
+) -> Result<bool, String> {
+    let close = writer.closer().map_err(|err| err.to_string())?;
+    let writer = Arc::new(Mutex::new(writer));
+    // one pending notification is enough for the pusher to send everything in the mailbox
+    let (notify, notified) = mpsc::sync_channel(1);
+    let mailbox = Mailbox::new(move |mailbox| {
+        if mailbox.overflowed() {
+            close();
+        } else {
+            let _ = notify.try_send(());
+        }
+    });
+    let mut session = Session::new(Arc::new(mailbox));
+    let served = serve_client(
+        Connection::new(reader),
+        &writer,
+        &mut Some(notified),
+        store,
+        broker,
+        &mut session,
+        protocol,
+    );
+    session.close(broker);
+    if session.mailbox.overflowed() {
+        return Err("the client did not keep up with its messages".into());
+    }
+    served
+}
+
+fn serve_client<R: Read, W: Write + Send + 'static>(
+    mut connection: Connection<R>,
+    writer: &Arc<Mutex<W>>,
+    pusher: &mut Option<Receiver<()>>,
+    store: &Store,
+    broker: &Broker,
+    session: &mut Session,
+    protocol: Protocol,
+) -> Result<bool, String> {
+    loop {
+        let command = match connection.read_command(protocol) {
+            Ok(Some(command)) => command,
+            Ok(None) => return Ok(false),
+            Err(err) => {
+                // the error is already the one to report; failing to send it changes nothing
+                let _ = send(
+                    writer,
+                    &session.mailbox,
+                    protocol,
+                    &encode_reply(protocol, &Err(err.clone())),
+                );
+                return Err(err);
             }
-            Err(err) => res = (error(stream, &err.to_string()), false),
-            _ => {}
         };
+        let outcome = match perform(&command, store, broker, session) {
+            Ok(Behaviour::Wait(ticket, deadline)) => {
+                Ok(Behaviour::waited(store.wait(ticket, deadline)))
+            }
+            outcome => outcome,
+        };
+        if let (Ok(Behaviour::Pushed), Some(notified)) = (&outcome, pusher.take()) {
+            let mailbox = Arc::downgrade(&session.mailbox);
+            start_pusher(writer.clone(), mailbox, notified, protocol)?;
+        }
+        if let Err(err) = send(
+            writer,
+            &session.mailbox,
+            protocol,
+            &encode_reply(protocol, &outcome),
+        ) {
+            // the word is not lost with the client
+            if let (Some(key), Ok(Behaviour::Result(word))) = (taken_from(&command), outcome) {
+                store.give_back(key, word);
+            }
+            return Err(err);
+        }
+        if let Ok(Behaviour::Stop) = outcome {
+            return Ok(true);
+        }
     }
-    res.0
 }
@@ -0,0 +1,412 @@
+//! A single threaded server driven by `mio`: all the clients are served from one event loop, so
+//! an idle connection costs only its buffers, not a thread.
+//!
+//! Commands are dispatched the same way as in the threaded server. A client may pipeline them,
+//! i.e. send many requests without waiting for the replies, which are then written in order.
+//! A client blocked in `BRETRIEVE` is parked, and its later commands wait until it gets the
+//! word or times out. A command which panics gets an error reply, and its client is
+//! disconnected, while the others are still served.
+//!
+//! Messages published to a subscriber are added to its output when the loop wakes up. A
+//! subscriber which lets more than [`MAX_PENDING_OUTPUT`] of them pile up, in its output or in
+//! its mailbox, is disconnected, as Redis does with slow subscribers. So is a client which
+//! sends a request longer than [`MAX_PENDING_INPUT`].
+//!
+//! [`MAX_PENDING_OUTPUT`]: constant.MAX_PENDING_OUTPUT.html
+//! [`MAX_PENDING_INPUT`]: constant.MAX_PENDING_INPUT.html
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Registry, Token, Waker};
+
+use super::core::{
+    encode_pushes, encode_reply, next_command, perform, Behaviour, Protocol, Session,
+};
+use super::pubsub::{Broker, Mailbox};
+use super::resp::Parser;
+use super::store::{Store, Ticket};
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+
+/// A client with more replies than this waiting to be sent is not read from until it takes
+/// some of them, so that it cannot make the server buffer without bounds. The same goes for
+/// the requests of a parked client.
+pub const MAX_PENDING_OUTPUT: usize = 1024 * 1024;
+
+/// The longest request accepted. A client which sends more than this without completing
+/// a command gets an error and is disconnected, instead of the server buffering it all.
+pub const MAX_PENDING_INPUT: usize = 16 * 1024 * 1024;
+
+struct Client {
+    stream: TcpStream,
+    parser: Parser,
+    output: Vec<u8>,
+    // set after STOP, a protocol error or the end of input; the client is dropped once
+    // everything in `output` is sent
+    closing: bool,
+    waiting: Option<(Ticket, Option<Instant>)>,
+    session: Session,
+    interest: Interest,
+}
+
+impl Client {
+    fn new(stream: TcpStream, mailbox: Mailbox) -> Self {
+        Client {
+            stream,
+            parser: Parser::new(),
+            output: Vec::new(),
+            closing: false,
+            waiting: None,
+            session: Session::new(Arc::new(mailbox)),
+            interest: Interest::READABLE,
+        }
+    }
+
+    fn wants_input(&self) -> bool {
+        !self.closing
+            && self.output.len() < MAX_PENDING_OUTPUT
+            && self.parser.len() < MAX_PENDING_INPUT
+            && (self.waiting.is_none() || self.parser.len() < MAX_PENDING_OUTPUT)
+    }
+
+    // Reads what is available and performs every complete command. Returns `true` if it read
+    // until the socket would block, and `false` if it stopped because the client wants no more
+    // input for now.
+    fn read(&mut self, store: &Store, broker: &Broker, protocol: Protocol) -> io::Result<bool> {
+        let mut buf = [0; 4096];
+        while self.wants_input() {
+            match self.stream.read(&mut buf) {
+                Ok(0) => self.closing = true,
+                Ok(n) => self.parser.feed(&buf[..n]),
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+            self.perform(store, broker, protocol);
+        }
+        Ok(false)
+    }
+
+    fn perform(&mut self, store: &Store, broker: &Broker, protocol: Protocol) {
+        while !self.closing && self.waiting.is_none() && self.output.len() < MAX_PENDING_OUTPUT {
+            let outcome = match next_command(&mut self.parser, protocol) {
+                Ok(Some(command)) => {
+                    let session = &mut self.session;
+                    // a bug in a command costs the client which sent it, not the whole server;
+                    // the store and the broker recover their locks if poisoned
+                    let performed = panic::catch_unwind(AssertUnwindSafe(|| {
+                        perform(&command, store, broker, session)
+                    }));
+                    performed.unwrap_or_else(|_| {
+                        self.closing = true;
+                        Err(format!("internal error in {}", command.name))
+                    })
+                }
+                Ok(None) if self.parser.len() < MAX_PENDING_INPUT => return,
+                Ok(None) => {
+                    self.closing = true;
+                    Err("Protocol error: request too long".into())
+                }
+                Err(err) => {
+                    self.closing = true;
+                    Err(err)
+                }
+            };
+            // the confirmations of subscriptions, and messages which came before them
+            self.output
+                .extend(encode_pushes(protocol, &self.session.mailbox));
+            match outcome {
+                Ok(Behaviour::Wait(ticket, deadline)) => self.waiting = Some((ticket, deadline)),
+                Ok(Behaviour::Stop) => {
+                    self.closing = true;
+                    self.output.extend(encode_reply(protocol, &outcome));
+                }
+                _ => self.output.extend(encode_reply(protocol, &outcome)),
+            }
+        }
+    }
+
+    // Checks on the word the client waits for. Returns `true` if it is not waiting anymore:
+    // it got the word, timed out, or disconnected.
+    fn check_waiting(
+        &mut self,
+        store: &Store,
+        broker: &Broker,
+        protocol: Protocol,
+        now: Instant,
+    ) -> bool {
+        let (ticket, deadline) = match self.waiting {
+            Some(waiting) => waiting,
+            None => return true,
+        };
+        if self.closing {
+            // the word is not lost with the client
+            store.abandon(ticket);
+            self.waiting = None;
+            return true;
+        }
+        let word = match store.collect(ticket) {
+            Some(word) => Some(word),
+            None if deadline.is_some_and(|deadline| deadline <= now) => store.cancel(ticket),
+            None => return false,
+        };
+        self.waiting = None;
+        self.output
+            .extend(encode_reply(protocol, &Ok(Behaviour::waited(word))));
+        self.perform(store, broker, protocol);
+        true
+    }
+
+    // Adds the messages published to the client to its output, unless there are too many.
+    fn take_pushes(&mut self, protocol: Protocol) {
+        self.output
+            .extend(encode_pushes(protocol, &self.session.mailbox));
+        if self.session.mailbox.overflowed() || self.output.len() > MAX_PENDING_OUTPUT {
+            println!("error: dropping a subscriber which does not keep up with its messages");
+            self.output.clear();
+            self.closing = true;
+        }
+    }
+
+    // Writes as much of the output as the socket takes.
+    fn write(&mut self) -> io::Result<()> {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.output.drain(..n);
+                }
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    // Serves the client until it waits for the socket or for a word. The events are edge
+    // triggered, so stopping earlier could miss the last wake-up.
+    fn serve(&mut self, store: &Store, broker: &Broker, protocol: Protocol) -> io::Result<()> {
+        loop {
+            let blocked = self.read(store, broker, protocol)?;
+            self.write()?;
+            // unless reading stopped only because of the output, which is now sent, there will
+            // be another event when there is something to do
+            if blocked || !self.output.is_empty() || !self.wants_input() {
+                return Ok(());
+            }
+        }
+    }
+
+    // Returns `false` if the client is done with and should be dropped.
+    fn update_interest(&mut self, registry: &Registry, token: Token) -> io::Result<bool> {
+        let interest = match (self.wants_input(), self.output.is_empty()) {
+            (true, true) => Interest::READABLE,
+            (true, false) => Interest::READABLE | Interest::WRITABLE,
+            (false, false) => Interest::WRITABLE,
+            (false, true) if self.closing && self.waiting.is_none() => return Ok(false),
+            // a parked client with its requests piling up; it is read from again once it gets
+            // the word
+            (false, true) => Interest::READABLE,
+        };
+        if interest != self.interest {
+            registry.reregister(&mut self.stream, token, interest)?;
+            self.interest = interest;
+        }
+        Ok(true)
+    }
+}
+
+struct Server {
+    poll: Poll,
+    waker: Arc<Waker>,
+    store: Arc<Store>,
+    broker: Arc<Broker>,
+    protocol: Protocol,
+    clients: HashMap<Token, Client>,
+    // the clients waiting in BRETRIEVE
+    parked: HashSet<Token>,
+    // the clients with messages published to them, by any thread
+    pushed: Arc<Mutex<HashSet<Token>>>,
+    next_token: usize,
+}
+
+impl Server {
+    fn accept(&mut self, listener: &TcpListener) {
+        loop {
+            match listener.accept() {
+                Ok((mut stream, addr)) => {
+                    println!("accepted stream; addr={:?}", addr);
+                    let token = Token(self.next_token);
+                    self.next_token += 1;
+                    match self
+                        .poll
+                        .registry()
+                        .register(&mut stream, token, Interest::READABLE)
+                    {
+                        Ok(()) => {
+                            let (pushed, waker) = (self.pushed.clone(), self.waker.clone());
+                            let mailbox = Mailbox::new(move |_| {
+                                pushed
+                                    .lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .insert(token);
+                                let _ = waker.wake();
+                            });
+                            self.clients.insert(token, Client::new(stream, mailbox));
+                        }
+                        Err(err) => println!("error: {:?}", err),
+                    }
+                }
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+                // e.g. too many open files; the other clients are still served
+                Err(err) => {
+                    println!("error: {:?}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    // Serves the client after an event, or after it stopped waiting.
+    fn serve(&mut self, token: Token) {
+        let (store, broker) = (&self.store, &self.broker);
+        let (protocol, registry) = (self.protocol, self.poll.registry());
+        let keep = match self.clients.get_mut(&token) {
+            Some(client) => {
+                let result = client
+                    .serve(store, broker, protocol)
+                    .and_then(|_| client.update_interest(registry, token));
+                match result {
+                    Ok(keep) => keep,
+                    Err(err) => {
+                        println!("error: {:?}", err);
+                        client.closing = true;
+                        false
+                    }
+                }
+            }
+            None => return,
+        };
+        let client = &self.clients[&token];
+        if client.waiting.is_some() {
+            self.parked.insert(token);
+        }
+        if !keep {
+            let mut client = self.clients.remove(&token).unwrap();
+            client.check_waiting(store, broker, protocol, Instant::now());
+            client.session.close(broker);
+            self.parked.remove(&token);
+            // the socket is closed when dropped anyway
+            let _ = registry.deregister(&mut client.stream);
+        }
+    }
+
+    // Gives the parked clients their words, or times them out.
+    fn check_parked(&mut self) {
+        let now = Instant::now();
+        let parked: Vec<Token> = self.parked.iter().cloned().collect();
+        for token in parked {
+            let done = match self.clients.get_mut(&token) {
+                Some(client) => client.check_waiting(&self.store, &self.broker, self.protocol, now),
+                None => true,
+            };
+            if done {
+                self.parked.remove(&token);
+                self.serve(token);
+            }
+        }
+    }
+
+    // Sends the subscribers the messages published to them.
+    fn check_pushed(&mut self) {
+        let pushed: Vec<Token> = self
+            .pushed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .drain()
+            .collect();
+        for token in pushed {
+            if let Some(client) = self.clients.get_mut(&token) {
+                client.take_pushes(self.protocol);
+                self.serve(token);
+            }
+        }
+    }
+
+    // The earliest deadline of the parked clients, as a timeout for polling.
+    fn next_timeout(&self) -> Option<std::time::Duration> {
+        let now = Instant::now();
+        self.parked
+            .iter()
+            .filter_map(|token| self.clients.get(token)?.waiting?.1)
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+}
+
+/// Accepts clients on the listener and serves all of them from one thread, with the store and
+/// the broker shared with anything else holding them. Returns only if polling fails.
+pub fn serve(
+    listener: net::TcpListener,
+    store: Arc<Store>,
+    broker: Arc<Broker>,
+    protocol: Protocol,
+) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let mut listener = TcpListener::from_std(listener);
+    let poll = Poll::new()?;
+    poll.registry()
+        .register(&mut listener, LISTENER, Interest::READABLE)?;
+    // words may be handed to the parked clients, and messages published to the subscribers,
+    // by other threads sharing the store and the broker
+    let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+    {
+        let waker = waker.clone();
+        store.on_delivery(move || {
+            let _ = waker.wake();
+        });
+    }
+
+    let mut server = Server {
+        poll,
+        waker,
+        store,
+        broker,
+        protocol,
+        clients: HashMap::new(),
+        parked: HashSet::new(),
+        pushed: Arc::new(Mutex::new(HashSet::new())),
+        next_token: WAKER.0 + 1,
+    };
+    let mut events = Events::with_capacity(1024);
+
+    loop {
+        let timeout = server.next_timeout();
+        if let Err(err) = server.poll.poll(&mut events, timeout) {
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        for event in events.iter() {
+            match event.token() {
+                LISTENER => server.accept(&listener),
+                // the parked clients and the subscribers are checked below anyway
+                WAKER => {}
+                token => server.serve(token),
+            }
+        }
+        server.check_parked();
+        server.check_pushed();
+    }
+}
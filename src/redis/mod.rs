@@ -3,22 +3,59 @@ use std::net::TcpListener;
 use std::env;
 use std::net::SocketAddr;
 
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::thread;
 
 mod core;
+#[cfg(feature = "event-loop")]
+pub mod event_loop;
+pub mod pubsub;
+#[cfg(test)]
+mod pubsub_tests;
+pub mod resp;
+#[cfg(test)]
+mod resp_tests;
+mod store;
+#[cfg(test)]
+mod store_tests;
 mod streams;
-pub use self::core::handle;
+pub use self::core::{handle, Command, Protocol};
+pub use self::pubsub::Broker;
+pub use self::store::{End, Store, Ticket, WrongType};
+pub use self::streams::Writer;
 
-pub fn main() {
-    let addr = env::args()
-        .nth(1)
+// Takes the protocol from `--protocol <resp|line>`, whether to run the event loop from
+// `--event-loop`, and the address from the first other argument.
+fn parse_args() -> (SocketAddr, Protocol, bool) {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let event_loop = match args.iter().position(|arg| arg == "--event-loop") {
+        Some(pos) => {
+            args.remove(pos);
+            true
+        }
+        None => false,
+    };
+    let protocol = match args.iter().position(|arg| arg == "--protocol") {
+        Some(pos) if pos + 1 < args.len() => {
+            let protocol = args.remove(pos + 1);
+            args.remove(pos);
+            protocol
+                .parse::<Protocol>()
+                .unwrap_or_else(|err| panic!("{}", err))
+        }
+        Some(_) => panic!("--protocol requires a value"),
+        None => Protocol::default(),
+    };
+    let addr = args
+        .first()
+        .cloned()
         .unwrap_or_else(|| "127.0.0.1:4141".to_string());
-    let addr = addr.parse::<SocketAddr>().unwrap();
-    let listener = TcpListener::bind(&addr).unwrap();
-
-    let queue = Arc::new(Mutex::new(VecDeque::new()));
+    (addr.parse::<SocketAddr>().unwrap(), protocol, event_loop)
+}
 
+/// Accepts clients on the listener and serves each of them on its own thread, with the store
+/// and the broker shared between them. Runs until the listener fails.
+pub fn serve(listener: TcpListener, store: Arc<Store>, broker: Arc<Broker>, protocol: Protocol) {
     listener
         .incoming()
         .filter_map(|res| match res {
@@ -28,12 +65,55 @@ pub fn main() {
             }
             Ok(r) => Some(r),
         })
-        .for_each(|mut stream| {
-            println!("accepted stream; addr={:?}", stream.peer_addr().unwrap());
-            let q = queue.clone();
-            let mut q_locked = q.lock().unwrap();
-            if let Err(err) = core::handle(&mut stream, &mut q_locked) {
+        .for_each(|stream| {
+            let peer = stream.peer_addr();
+            println!("accepted stream; addr={:?}", peer);
+            let reader = match stream.try_clone() {
+                Ok(reader) => reader,
+                Err(err) => {
+                    println!("error: {:?}", err);
+                    return;
+                }
+            };
+            let (store, broker) = (store.clone(), broker.clone());
+            let spawned = thread::Builder::new()
+                .name(format!("redis client {:?}", peer))
+                .spawn(move || {
+                    if let Err(err) = core::handle(reader, stream, &store, &broker, protocol) {
+                        println!("error: {:?}", err);
+                    }
+                });
+            // the streams are dropped with the closure, which closes the connection
+            if let Err(err) = spawned {
                 println!("error: {:?}", err);
             }
         });
 }
+
+pub fn main() {
+    let (addr, protocol, event_loop) = parse_args();
+    let listener = TcpListener::bind(addr).unwrap();
+    let (store, broker) = (Arc::new(Store::new()), Arc::new(Broker::new()));
+    if event_loop {
+        serve_event_loop(listener, store, broker, protocol);
+    } else {
+        serve(listener, store, broker, protocol);
+    }
+}
+
+#[cfg(feature = "event-loop")]
+fn serve_event_loop(
+    listener: TcpListener,
+    store: Arc<Store>,
+    broker: Arc<Broker>,
+    protocol: Protocol,
+) {
+    if let Err(err) = event_loop::serve(listener, store, broker, protocol) {
+        println!("error: {:?}", err);
+    }
+}
+
+#[cfg(not(feature = "event-loop"))]
+fn serve_event_loop(_: TcpListener, _: Arc<Store>, _: Arc<Broker>, _: Protocol) {
+    panic!("--event-loop requires building with the event-loop feature");
+}
@@ -0,0 +1,459 @@
+//! Publish/subscribe: messages published to a channel are pushed to every connection
+//! subscribed to it, either by its name or by a glob pattern matching it.
+//!
+//! Nothing is stored: a message published to a channel without subscribers is gone. Every
+//! connection has a [`Mailbox`] where its pushes wait until the server sends them, in the order
+//! they came, together with the replies to its own commands.
+//!
+//! [`Mailbox`]: struct.Mailbox.html
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The longest pattern accepted by `PSUBSCRIBE`, which bounds the work of matching every
+/// published channel against it.
+pub const MAX_PATTERN_LENGTH: usize = 1024;
+
+/// The identifier of a subscribed connection.
+pub type SubscriberId = u64;
+
+/// Whether a subscription is to a channel by its name, or to the channels matching a pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Channel,
+    Pattern,
+}
+
+/// What a subscribed connection is sent without asking.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Push {
+    /// The confirmation of `SUBSCRIBE`, `UNSUBSCRIBE`, `PSUBSCRIBE` or `PUNSUBSCRIBE` for one
+    /// channel or pattern, with the number of subscriptions left. The name is missing when
+    /// unsubscribing from everything while there was nothing to unsubscribe from.
+    Subscription {
+        command: &'static str,
+        name: Option<String>,
+        count: usize,
+    },
+    /// A message published to a subscribed channel.
+    Message { channel: String, payload: String },
+    /// A message published to a channel matching a subscribed pattern.
+    PatternMessage {
+        pattern: String,
+        channel: String,
+        payload: String,
+    },
+}
+
+impl Push {
+    // The bytes of text in the push, about what it takes to send it.
+    fn size(&self) -> usize {
+        match self {
+            Push::Subscription { name, .. } => name.as_ref().map_or(0, String::len),
+            Push::Message { channel, payload } => channel.len() + payload.len(),
+            Push::PatternMessage {
+                pattern,
+                channel,
+                payload,
+            } => pattern.len() + channel.len() + payload.len(),
+        }
+    }
+}
+
+/// A connection with more than this many bytes of messages waiting in its mailbox does not
+/// keep up with them: the messages are dropped, and the server is to disconnect it.
+pub const MAX_PENDING_PUSHES: usize = 1024 * 1024;
+
+#[derive(Default)]
+struct Pending {
+    pushes: VecDeque<Push>,
+    size: usize,
+    overflowed: bool,
+}
+
+/// The pushes waiting to be sent to a connection.
+pub struct Mailbox {
+    pending: Mutex<Pending>,
+    on_push: Box<dyn Fn(&Mailbox) + Send + Sync>,
+}
+
+impl Mailbox {
+    /// Creates a mailbox which calls `on_push` when messages are published to it, or when it
+    /// overflows, so that the server can send them or disconnect the client. Confirmations of
+    /// subscriptions are left for the connection which asked for them to send.
+    ///
+    /// `on_push` is called from the publishing thread, and should not block.
+    pub fn new<F: Fn(&Mailbox) + Send + Sync + 'static>(on_push: F) -> Self {
+        Mailbox {
+            pending: Mutex::new(Pending::default()),
+            on_push: Box::new(on_push),
+        }
+    }
+
+    fn pending(&self) -> MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes all the waiting pushes, oldest first.
+    pub fn take(&self) -> Vec<Push> {
+        let mut pending = self.pending();
+        pending.size = 0;
+        pending.pushes.drain(..).collect()
+    }
+
+    /// Returns `true` once more than [`MAX_PENDING_PUSHES`] bytes of messages were waiting.
+    /// Nothing is pushed anymore after that.
+    ///
+    /// [`MAX_PENDING_PUSHES`]: constant.MAX_PENDING_PUSHES.html
+    pub fn overflowed(&self) -> bool {
+        self.pending().overflowed
+    }
+
+    fn push(&self, push: Push) {
+        let mut pending = self.pending();
+        if pending.overflowed {
+            return;
+        }
+        let size = push.size();
+        let message = !matches!(push, Push::Subscription { .. });
+        if message && pending.size + size > MAX_PENDING_PUSHES {
+            *pending = Pending {
+                overflowed: true,
+                ..Pending::default()
+            };
+            return;
+        }
+        pending.size += size;
+        pending.pushes.push_back(push);
+    }
+}
+
+struct Subscriber {
+    mailbox: Arc<Mailbox>,
+    // in the order of subscribing, which is the order of unsubscribing from all of them
+    channels: Vec<String>,
+    patterns: Vec<String>,
+}
+
+impl Subscriber {
+    fn names(&mut self, kind: Kind) -> &mut Vec<String> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    channels: HashMap<String, HashSet<SubscriberId>>,
+    patterns: HashMap<String, HashSet<SubscriberId>>,
+    subscribers: HashMap<SubscriberId, Subscriber>,
+    next_id: SubscriberId,
+}
+
+impl State {
+    fn index(&mut self, kind: Kind) -> &mut HashMap<String, HashSet<SubscriberId>> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+        }
+    }
+
+    // Removes the subscription, if there is one. Returns `true` if there was.
+    fn remove(&mut self, id: SubscriberId, name: &str, kind: Kind) -> bool {
+        let index = self.index(kind);
+        let removed = match index.get_mut(name) {
+            Some(ids) => ids.remove(&id),
+            None => false,
+        };
+        if index.get(name).is_some_and(HashSet::is_empty) {
+            index.remove(name);
+        }
+        if let Some(subscriber) = self.subscribers.get_mut(&id) {
+            subscriber
+                .names(kind)
+                .retain(|subscribed| subscribed != name);
+        }
+        removed
+    }
+}
+
+/// The subscriptions of all the connections.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::redis::pubsub::*;
+/// use std::sync::Arc;
+///
+/// let broker = Broker::new();
+/// let mailbox = Arc::new(Mailbox::new(|_| {}));
+/// let id = broker.register(mailbox.clone());
+/// broker.subscribe(id, &["news.*".to_string()], Kind::Pattern);
+/// assert_eq!(broker.publish("news.tech", "hello"), 1);
+/// assert_eq!(broker.publish("weather", "sunny"), 0);
+/// assert_eq!(mailbox.take().len(), 2);
+/// ```
+#[derive(Default)]
+pub struct Broker {
+    state: Mutex<State>,
+}
+
+impl Broker {
+    pub fn new() -> Self {
+        Broker::default()
+    }
+
+    // Nothing panics while holding the lock, so the state is consistent even if poisoned.
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Registers a connection which is going to subscribe, with the mailbox for its pushes.
+    pub fn register(&self, mailbox: Arc<Mailbox>) -> SubscriberId {
+        let mut state = self.state();
+        let id = state.next_id;
+        state.next_id += 1;
+        let subscriber = Subscriber {
+            mailbox,
+            channels: Vec::new(),
+            patterns: Vec::new(),
+        };
+        state.subscribers.insert(id, subscriber);
+        id
+    }
+
+    /// Removes the connection with all its subscriptions, e.g. when it disconnects.
+    pub fn unregister(&self, id: SubscriberId) {
+        let mut state = self.state();
+        for &kind in &[Kind::Channel, Kind::Pattern] {
+            let names = match state.subscribers.get_mut(&id) {
+                Some(subscriber) => subscriber.names(kind).clone(),
+                None => return,
+            };
+            names.iter().for_each(|name| {
+                state.remove(id, name, kind);
+            });
+        }
+        state.subscribers.remove(&id);
+    }
+
+    /// Returns the number of channels and patterns the connection is subscribed to.
+    pub fn subscriptions(&self, id: SubscriberId) -> usize {
+        self.state()
+            .subscribers
+            .get(&id)
+            .map_or(0, Subscriber::count)
+    }
+
+    /// Subscribes the connection to the channels or patterns, and confirms each of them in
+    /// its mailbox.
+    pub fn subscribe(&self, id: SubscriberId, names: &[String], kind: Kind) {
+        let mut state = self.state();
+        if !state.subscribers.contains_key(&id) {
+            return;
+        }
+        for name in names {
+            let added = state
+                .index(kind)
+                .entry(name.clone())
+                .or_default()
+                .insert(id);
+            let subscriber = state.subscribers.get_mut(&id).unwrap();
+            if added {
+                subscriber.names(kind).push(name.clone());
+            }
+            let push = Push::Subscription {
+                command: match kind {
+                    Kind::Channel => "subscribe",
+                    Kind::Pattern => "psubscribe",
+                },
+                name: Some(name.clone()),
+                count: subscriber.count(),
+            };
+            subscriber.mailbox.push(push);
+        }
+    }
+
+    /// Unsubscribes the connection from the channels or patterns, or from all of them if none
+    /// are given, and confirms each of them in its mailbox.
+    pub fn unsubscribe(&self, id: SubscriberId, names: &[String], kind: Kind) {
+        let mut state = self.state();
+        let names: Vec<Option<String>> = match state.subscribers.get_mut(&id) {
+            Some(subscriber) if names.is_empty() => {
+                let all = subscriber.names(kind);
+                if all.is_empty() {
+                    vec![None]
+                } else {
+                    all.iter().cloned().map(Some).collect()
+                }
+            }
+            Some(_) => names.iter().cloned().map(Some).collect(),
+            None => return,
+        };
+        for name in names {
+            if let Some(name) = &name {
+                state.remove(id, name, kind);
+            }
+            let subscriber = &state.subscribers[&id];
+            let push = Push::Subscription {
+                command: match kind {
+                    Kind::Channel => "unsubscribe",
+                    Kind::Pattern => "punsubscribe",
+                },
+                name,
+                count: subscriber.count(),
+            };
+            subscriber.mailbox.push(push);
+        }
+    }
+
+    /// Pushes the message to the connections subscribed to the channel, and to those
+    /// subscribed to patterns matching it. Returns the number of pushed messages.
+    pub fn publish(&self, channel: &str, payload: &str) -> usize {
+        let mut notified: Vec<Arc<Mailbox>> = Vec::new();
+        let mut count = 0;
+        {
+            let state = self.state();
+            let mut send = |id: &SubscriberId, push: Push| {
+                let mailbox = &state.subscribers[id].mailbox;
+                mailbox.push(push);
+                if !notified.iter().any(|other| Arc::ptr_eq(other, mailbox)) {
+                    notified.push(mailbox.clone());
+                }
+                count += 1;
+            };
+            for id in state.channels.get(channel).into_iter().flatten() {
+                let push = Push::Message {
+                    channel: channel.to_string(),
+                    payload: payload.to_string(),
+                };
+                send(id, push);
+            }
+            for (pattern, ids) in state.patterns.iter() {
+                if !glob_match(pattern, channel) {
+                    continue;
+                }
+                for id in ids {
+                    let push = Push::PatternMessage {
+                        pattern: pattern.clone(),
+                        channel: channel.to_string(),
+                        payload: payload.to_string(),
+                    };
+                    send(id, push);
+                }
+            }
+        }
+        // the servers are told after the lock is released, as they may send the pushes at once
+        for mailbox in &notified {
+            (mailbox.on_push)(mailbox);
+        }
+        count
+    }
+}
+
+/// Matches the text against a glob pattern, as Redis does: `*` matches any text, `?` any
+/// single character, `[abc]`, `[a-z]` and `[^a]` a character of the set or not in it, and `\`
+/// escapes the next character.
+///
+/// Matching takes at most O(n·m) steps for a pattern of n characters and a text of m, with no
+/// recursion: after a failure, only the last star takes one more character.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::redis::pubsub::glob_match;
+///
+/// assert!(glob_match("news.*", "news.tech"));
+/// assert!(glob_match("h?llo", "hello"));
+/// assert!(glob_match("h[a-e]llo", "hello"));
+/// assert!(!glob_match("h[^e]llo", "hello"));
+/// assert!(glob_match("a\\*", "a*"));
+/// assert!(!glob_match("a\\*", "ab"));
+/// ```
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // where to go back to when a match fails after a star: the pattern after the star, and
+    // the text the star is to take one more character of
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, t));
+            continue;
+        }
+        if let Some(next) = match_one(&pattern, p, text[t]) {
+            p = next;
+            t += 1;
+            continue;
+        }
+        match star {
+            Some((after_star, taken)) => {
+                p = after_star;
+                t = taken + 1;
+                star = Some((after_star, t));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Matches the character against the element of the pattern at `p`. Returns where the pattern
+// goes on if it matches.
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match *pattern.get(p)? {
+        '?' => Some(p + 1),
+        '[' => match match_set(&pattern[p + 1..], c) {
+            Some((true, rest)) => Some(pattern.len() - rest.len()),
+            Some((false, _)) => None,
+            // an unclosed bracket matches itself
+            None if c == '[' => Some(p + 1),
+            None => None,
+        },
+        '\\' if p + 1 < pattern.len() => Some(p + 2).filter(|_| pattern[p + 1] == c),
+        other => Some(p + 1).filter(|_| other == c),
+    }
+}
+
+// Matches the character against the set which starts the pattern, after `[`. Returns whether
+// it matched, and the pattern after the set, or `None` if the set is not closed.
+fn match_set(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut i) = match pattern.first() {
+        Some('^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut matched = false;
+    loop {
+        match pattern.get(i)? {
+            ']' => return Some((matched != negated, &pattern[i + 1..])),
+            '\\' => {
+                matched |= *pattern.get(i + 1)? == c;
+                i += 2;
+            }
+            &low if pattern.get(i + 1) == Some(&'-')
+                && pattern.get(i + 2).is_some_and(|&h| h != ']') =>
+            {
+                let high = pattern[i + 2];
+                let (low, high) = if low <= high {
+                    (low, high)
+                } else {
+                    (high, low)
+                };
+                matched |= low <= c && c <= high;
+                i += 3;
+            }
+            &other => {
+                matched |= other == c;
+                i += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod pubsub_tests {
+    use crate::redis::pubsub::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use spectral::prelude::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn message(channel: &str, payload: &str) -> Push {
+        Push::Message {
+            channel: channel.into(),
+            payload: payload.into(),
+        }
+    }
+
+    fn subscription(command: &'static str, name: Option<&str>, count: usize) -> Push {
+        Push::Subscription {
+            command,
+            name: name.map(String::from),
+            count,
+        }
+    }
+
+    #[test]
+    fn should_match_glob_patterns() {
+        let cases = vec![
+            ("*", "", true),
+            ("news.*", "news.", true),
+            ("news.*", "weather.news", false),
+            ("*.tech.*", "news.tech.rust", true),
+            ("a**b", "axxb", true),
+            ("h?llo", "hallo", true),
+            ("h?llo", "hllo", false),
+            ("h[ae]llo", "hello", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h[z-a]llo", "hello", true),
+            ("h[\\]]llo", "h]llo", true),
+            ("h[a", "h[a", true),
+            ("h\\?llo", "h?llo", true),
+            ("h\\?llo", "hello", false),
+            ("été.*", "été.chaud", true),
+            ("*a*b", "xaybzb", true),
+            ("*a*b", "xaybzc", false),
+            ("a*", "a", true),
+            ("", "", true),
+            ("", "a", false),
+        ];
+        for (pattern, text, matched) in cases {
+            assert_that!(glob_match(pattern, text))
+                .named(&format!("{} against {}", pattern, text))
+                .is_equal_to(matched);
+        }
+    }
+
+    #[test]
+    fn should_match_long_patterns_quickly() {
+        // a recursive matcher overflows the stack on the first one, and backtracks
+        // exponentially on the second
+        let pattern = "*a".repeat(100_000);
+        let text = "a".repeat(100_000);
+        assert_that!(glob_match(&pattern, &text)).is_true();
+        let pattern = "*a".repeat(30) + "b";
+        assert_that!(glob_match(&pattern, &"a".repeat(200))).is_false();
+    }
+
+    #[test]
+    fn should_push_to_channel_and_pattern_subscribers() {
+        let broker = Broker::new();
+        let first = Arc::new(Mailbox::new(|_| {}));
+        let second = Arc::new(Mailbox::new(|_| {}));
+        let (a, b) = (
+            broker.register(first.clone()),
+            broker.register(second.clone()),
+        );
+        broker.subscribe(a, &names(&["news", "news"]), Kind::Channel);
+        broker.subscribe(b, &names(&["n*"]), Kind::Pattern);
+        broker.subscribe(b, &names(&["news"]), Kind::Channel);
+        assert_that!(first.take()).is_equal_to(vec![
+            subscription("subscribe", Some("news"), 1),
+            subscription("subscribe", Some("news"), 1),
+        ]);
+        assert_that!(second.take()).has_length(2);
+
+        assert_that!(broker.publish("news", "hello")).is_equal_to(3);
+        assert_that!(broker.publish("nothing", "x")).is_equal_to(1);
+        assert_that!(broker.publish("weather", "sunny")).is_equal_to(0);
+        assert_that!(first.take()).is_equal_to(vec![message("news", "hello")]);
+        let pattern_message = |channel: &str, payload: &str| Push::PatternMessage {
+            pattern: "n*".into(),
+            channel: channel.into(),
+            payload: payload.into(),
+        };
+        assert_that!(second.take()).is_equal_to(vec![
+            message("news", "hello"),
+            pattern_message("news", "hello"),
+            pattern_message("nothing", "x"),
+        ]);
+        assert_that!(broker.subscriptions(b)).is_equal_to(2);
+    }
+
+    #[test]
+    fn should_unsubscribe() {
+        let broker = Broker::new();
+        let mailbox = Arc::new(Mailbox::new(|_| {}));
+        let id = broker.register(mailbox.clone());
+        broker.unsubscribe(id, &[], Kind::Channel);
+        broker.subscribe(id, &names(&["a", "b", "c"]), Kind::Channel);
+        broker.subscribe(id, &names(&["*"]), Kind::Pattern);
+        mailbox.take();
+        broker.unsubscribe(id, &names(&["b", "none"]), Kind::Channel);
+        broker.unsubscribe(id, &[], Kind::Channel);
+        assert_that!(mailbox.take()).is_equal_to(vec![
+            subscription("unsubscribe", Some("b"), 3),
+            subscription("unsubscribe", Some("none"), 3),
+            subscription("unsubscribe", Some("a"), 2),
+            subscription("unsubscribe", Some("c"), 1),
+        ]);
+        assert_that!(broker.publish("a", "x")).is_equal_to(1);
+
+        broker.unregister(id);
+        assert_that!(broker.publish("a", "x")).is_equal_to(0);
+        assert_that!(broker.subscriptions(id)).is_equal_to(0);
+    }
+
+    #[test]
+    fn should_tell_about_published_messages_only() {
+        let broker = Broker::new();
+        let told = Arc::new(AtomicUsize::new(0));
+        let mailbox = {
+            let told = told.clone();
+            Arc::new(Mailbox::new(move |mailbox| {
+                told.fetch_add(mailbox.take().len(), Ordering::SeqCst);
+            }))
+        };
+        let id = broker.register(mailbox.clone());
+        broker.subscribe(id, &names(&["a"]), Kind::Channel);
+        broker.subscribe(id, &names(&["a*"]), Kind::Pattern);
+        assert_that!(told.load(Ordering::SeqCst)).is_equal_to(0);
+        // one call for both messages, which are in the mailbox after the confirmations
+        broker.publish("a", "x");
+        assert_that!(told.load(Ordering::SeqCst)).is_equal_to(4);
+    }
+
+    #[test]
+    fn should_overflow_mailbox_of_slow_subscriber() {
+        let broker = Broker::new();
+        let mailbox = Arc::new(Mailbox::new(|_| {}));
+        let id = broker.register(mailbox.clone());
+        broker.subscribe(id, &names(&["c"]), Kind::Channel);
+        let payload = "x".repeat(MAX_PENDING_PUSHES / 4);
+        for _ in 0..3 {
+            broker.publish("c", &payload);
+        }
+        assert_that!(mailbox.overflowed()).is_false();
+        // taking the pushes makes room again
+        assert_that!(mailbox.take()).has_length(4);
+        for _ in 0..4 {
+            broker.publish("c", &payload);
+        }
+        assert_that!(mailbox.overflowed()).is_true();
+        assert_that!(mailbox.take()).is_empty();
+        broker.publish("c", "x");
+        assert_that!(mailbox.take()).is_empty();
+    }
+}
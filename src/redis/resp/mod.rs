@@ -0,0 +1,373 @@
+//! RESP2, the protocol of Redis: values are encoded as a type byte, a header line, and for bulk
+//! strings and arrays their contents. Every line ends with `\r\n`.
+//!
+//! The [`Parser`] collects bytes as they come from the socket, so a value may arrive in many
+//! partial reads, and many values may arrive in one. It resumes where it stopped, so a value
+//! sent in pieces is parsed in time proportional to its length, not to the number of pieces.
+//!
+//! [`Parser`]: struct.Parser.html
+
+use std::error::Error;
+use std::fmt;
+use std::str;
+
+/// The longest bulk string accepted, the same as in Redis.
+pub const MAX_BULK_LENGTH: usize = 512 * 1024 * 1024;
+
+/// The longest line accepted: the header of a value, a simple string or an error, or a line of
+/// the plain text protocol.
+pub const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// The deepest nesting of arrays accepted. Commands are flat arrays, so this is plenty.
+pub const MAX_DEPTH: usize = 32;
+
+/// A RESP2 value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// `+OK\r\n`
+    Simple(String),
+    /// `-ERR message\r\n`
+    Error(String),
+    /// `:42\r\n`
+    Integer(i64),
+    /// `$5\r\nhello\r\n`
+    Bulk(Vec<u8>),
+    /// The null bulk string `$-1\r\n`. The null array `*-1\r\n` is parsed as the same value.
+    Null,
+    /// `*2\r\n` followed by the elements
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// Creates a bulk string with the bytes of `s`.
+    pub fn bulk(s: &str) -> Value {
+        Value::Bulk(s.as_bytes().to_vec())
+    }
+
+    /// Encodes the value as bytes to be sent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_experiments::redis::resp::Value;
+    ///
+    /// let value = Value::Array(vec![Value::bulk("RETRIEVE"), Value::Integer(-1), Value::Null]);
+    /// assert_eq!(value.encode(), b"*3\r\n$8\r\nRETRIEVE\r\n:-1\r\n$-1\r\n".to_vec());
+    /// ```
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.encode_into(&mut bytes);
+        bytes
+    }
+
+    fn encode_into(&self, bytes: &mut Vec<u8>) {
+        match self {
+            // line breaks would end the line early, so they are replaced
+            Value::Simple(s) => bytes.extend(format!("+{}\r\n", single_line(s)).as_bytes()),
+            Value::Error(s) => bytes.extend(format!("-{}\r\n", single_line(s)).as_bytes()),
+            Value::Integer(n) => bytes.extend(format!(":{}\r\n", n).as_bytes()),
+            Value::Bulk(data) => {
+                bytes.extend(format!("${}\r\n", data.len()).as_bytes());
+                bytes.extend(data);
+                bytes.extend(b"\r\n");
+            }
+            Value::Null => bytes.extend(b"$-1\r\n"),
+            Value::Array(values) => {
+                bytes.extend(format!("*{}\r\n", values.len()).as_bytes());
+                values.iter().for_each(|value| value.encode_into(bytes));
+            }
+        }
+    }
+}
+
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+/// The errors of malformed input. After one of them the connection cannot be trusted to be
+/// in sync, so it should be closed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolError {
+    /// The value starts with a byte which is not a RESP2 type.
+    UnknownType(u8),
+    /// The header of an integer, bulk string or array is not a number, or is out of range.
+    InvalidLength(String),
+    /// A bulk string does not end with `\r\n` after the given number of bytes.
+    MissingCrlf,
+    /// A simple string or an error is not valid UTF-8.
+    InvalidUtf8,
+    /// Arrays are nested deeper than [`MAX_DEPTH`].
+    ///
+    /// [`MAX_DEPTH`]: constant.MAX_DEPTH.html
+    TooDeep,
+    /// A line is longer than [`MAX_LINE_LENGTH`], or does not end before that.
+    ///
+    /// [`MAX_LINE_LENGTH`]: constant.MAX_LINE_LENGTH.html
+    LineTooLong,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::UnknownType(byte) => write!(f, "unknown type byte '{}'", *byte as char),
+            ProtocolError::InvalidLength(text) => write!(f, "invalid number '{}'", text),
+            ProtocolError::MissingCrlf => write!(f, "bulk string not followed by CRLF"),
+            ProtocolError::InvalidUtf8 => write!(f, "invalid UTF-8 in a simple string"),
+            ProtocolError::TooDeep => write!(f, "arrays nested too deep"),
+            ProtocolError::LineTooLong => write!(f, "line too long"),
+        }
+    }
+}
+
+impl Error for ProtocolError {}
+
+/// An incremental parser: bytes are added with [`feed`] as they arrive, and complete values
+/// or lines are taken from the front of the buffer.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::redis::resp::*;
+///
+/// let mut parser = Parser::new();
+/// parser.feed(b"*1\r\n$4\r\nPI");
+/// assert_eq!(parser.next_value(), Ok(None));
+/// parser.feed(b"NG\r\n+OK\r\n");
+/// assert_eq!(parser.next_value(), Ok(Some(Value::Array(vec![Value::bulk("PING")]))));
+/// assert_eq!(parser.next_value(), Ok(Some(Value::Simple("OK".to_string()))));
+/// assert_eq!(parser.next_value(), Ok(None));
+/// ```
+///
+/// [`feed`]: #method.feed
+#[derive(Debug, Default)]
+pub struct Parser {
+    buffer: Vec<u8>,
+    // the bytes before `start` are taken already, and dropped from time to time
+    start: usize,
+    // where parsing of the value at `start` continues
+    position: usize,
+    // how far the line at `position` was searched for its end
+    scanned: usize,
+    // the length of a bulk string whose header is parsed, but whose contents are not all here
+    bulk: Option<usize>,
+    // the arrays being filled, with the number of elements they still miss
+    arrays: Vec<(Vec<Value>, usize)>,
+    #[cfg(test)]
+    examined: usize,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Parser::default()
+    }
+
+    /// Appends the bytes to the buffer.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        // the taken bytes are dropped once they are at least half of the buffer, so that every
+        // byte is moved a constant number of times on average
+        if self.start > 0 && self.start * 2 >= self.buffer.len() {
+            self.buffer.drain(..self.start);
+            self.position -= self.start;
+            self.scanned = self.scanned.saturating_sub(self.start);
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// The number of bytes in the buffer, including those of a value which is parsed only
+    /// partly.
+    pub fn len(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+
+    /// Returns `true` if there is nothing left in the buffer.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Takes the next complete value from the buffer. Returns `Ok(None)` if the value is not
+    /// complete yet; the part already parsed is kept, and parsing continues from its end
+    /// the next time.
+    pub fn next_value(&mut self) -> Result<Option<Value>, ProtocolError> {
+        loop {
+            let value = match self.parse_next()? {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+            if let Some(value) = self.add_to_array(value) {
+                self.start = self.position;
+                return Ok(Some(value));
+            }
+        }
+    }
+
+    /// Takes the next line ending with `\n` from the buffer, without the line ending, for
+    /// the plain text protocol. Returns `Ok(None)` if there is no complete line yet.
+    pub fn next_line(&mut self) -> Result<Option<String>, ProtocolError> {
+        let from = self.scanned.max(self.start);
+        let end = match self.buffer[from..].iter().position(|&b| b == b'\n') {
+            Some(end) => end + from,
+            None => {
+                self.examine(self.buffer.len() - from);
+                self.scanned = self.buffer.len();
+                return check_line_length(self.buffer.len() - self.start).map(|_| None);
+            }
+        };
+        self.examine(end + 1 - from);
+        check_line_length(end - self.start)?;
+        let line = String::from_utf8_lossy(&self.buffer[self.start..end]);
+        let line = line.trim_end_matches('\r').to_string();
+        self.start = end + 1;
+        self.position = self.start;
+        Ok(Some(line))
+    }
+
+    // Parses the next value at `position`, which is either complete or the header of an array,
+    // and so starts a new array. Returns `None` if the buffer ends first.
+    fn parse_next(&mut self) -> Result<Option<Value>, ProtocolError> {
+        if let Some(len) = self.bulk {
+            return self.parse_bulk(len);
+        }
+        let kind = match self.buffer.get(self.position) {
+            Some(&kind) => kind,
+            None => return Ok(None),
+        };
+        // checked before looking for the end of the line, so that garbage is not buffered
+        if !b"+-:$*".contains(&kind) {
+            return Err(ProtocolError::UnknownType(kind));
+        }
+        let (end, next) = match self.find_line(self.position + 1)? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let line = &self.buffer[self.position + 1..end];
+        let text = || {
+            str::from_utf8(line)
+                .map(|s| s.to_string())
+                .map_err(|_| ProtocolError::InvalidUtf8)
+        };
+
+        let value = match kind {
+            b'+' => Value::Simple(text()?),
+            b'-' => Value::Error(text()?),
+            b':' => Value::Integer(parse_number(line)?),
+            b'$' => match parse_length(line, MAX_BULK_LENGTH)? {
+                None => Value::Null,
+                Some(len) => {
+                    self.position = next;
+                    self.bulk = Some(len);
+                    return self.parse_bulk(len);
+                }
+            },
+            b'*' => match parse_length(line, i32::MAX as usize)? {
+                None => Value::Null,
+                Some(0) => Value::Array(Vec::new()),
+                Some(_) if self.arrays.len() == MAX_DEPTH => return Err(ProtocolError::TooDeep),
+                Some(len) => {
+                    // every element takes at least 3 bytes, which bounds the allocation
+                    let capacity = len.min((self.buffer.len() - next) / 3);
+                    self.arrays.push((Vec::with_capacity(capacity), len));
+                    self.position = next;
+                    return self.parse_next();
+                }
+            },
+            _ => unreachable!("the type byte is checked above"),
+        };
+        self.position = next;
+        Ok(Some(value))
+    }
+
+    // Parses the contents of a bulk string of `len` bytes at `position`.
+    fn parse_bulk(&mut self, len: usize) -> Result<Option<Value>, ProtocolError> {
+        let start = self.position;
+        if self.buffer.len() < start + len + 2 {
+            return Ok(None);
+        }
+        if &self.buffer[start + len..start + len + 2] != b"\r\n" {
+            return Err(ProtocolError::MissingCrlf);
+        }
+        self.examine(len + 2);
+        self.bulk = None;
+        self.position = start + len + 2;
+        Ok(Some(Value::Bulk(self.buffer[start..start + len].to_vec())))
+    }
+
+    // Adds the value to the innermost array being filled, and every array it completes to its
+    // parent. Returns the value, or the outermost array, if that is complete.
+    fn add_to_array(&mut self, mut value: Value) -> Option<Value> {
+        loop {
+            let (values, missing) = match self.arrays.last_mut() {
+                Some(array) => array,
+                None => return Some(value),
+            };
+            values.push(value);
+            *missing -= 1;
+            if *missing > 0 {
+                return None;
+            }
+            let (values, _) = self.arrays.pop().expect("the array was just found");
+            value = Value::Array(values);
+        }
+    }
+
+    // Finds the end of the line starting at `start`, and returns it with the position after
+    // `\r\n`. The search continues where the previous one for the same line stopped.
+    fn find_line(&mut self, start: usize) -> Result<Option<(usize, usize)>, ProtocolError> {
+        // the last byte searched may be the `\r`
+        let from = (self.scanned.max(start + 1) - 1).max(start);
+        match self.buffer[from..].windows(2).position(|w| w == b"\r\n") {
+            Some(end) => {
+                self.examine(end + 2);
+                check_line_length(end + from - start)?;
+                Ok(Some((end + from, end + from + 2)))
+            }
+            None => {
+                self.examine(self.buffer.len() - from);
+                self.scanned = self.buffer.len();
+                check_line_length(self.buffer.len() - start).map(|_| None)
+            }
+        }
+    }
+
+    // Counts the bytes looked at, for the tests to check that they are not parsed again
+    // after every read.
+    fn examine(&mut self, _bytes: usize) {
+        #[cfg(test)]
+        {
+            self.examined += _bytes;
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn examined(&self) -> usize {
+        self.examined
+    }
+}
+
+// Fails if a line of `len` bytes, without its line ending, is too long.
+fn check_line_length(len: usize) -> Result<(), ProtocolError> {
+    if len > MAX_LINE_LENGTH {
+        Err(ProtocolError::LineTooLong)
+    } else {
+        Ok(())
+    }
+}
+
+fn parse_number(line: &[u8]) -> Result<i64, ProtocolError> {
+    str::from_utf8(line)
+        .ok()
+        .and_then(|text| text.parse::<i64>().ok())
+        .ok_or_else(|| ProtocolError::InvalidLength(String::from_utf8_lossy(line).into_owned()))
+}
+
+// Parses a length of a bulk string or an array: -1 means null, and larger numbers have to fit
+// within `max`.
+fn parse_length(line: &[u8], max: usize) -> Result<Option<usize>, ProtocolError> {
+    match parse_number(line)? {
+        -1 => Ok(None),
+        n if n >= 0 && n as u64 <= max as u64 => Ok(Some(n as usize)),
+        _ => Err(ProtocolError::InvalidLength(
+            String::from_utf8_lossy(line).into_owned(),
+        )),
+    }
+}
@@ -0,0 +1,374 @@
+#[cfg(test)]
+mod resp_tests {
+    use crate::redis::pubsub::MAX_PATTERN_LENGTH;
+    use crate::redis::resp::*;
+    use crate::redis::*;
+
+    use std::io::{self, Cursor, Write};
+    use std::sync::{Arc, Mutex};
+
+    use spectral::prelude::*;
+
+    // The replies to a client, which can be looked at after handing the writer to the server.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Output {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Writer for Output {
+        fn closer(&self) -> io::Result<Box<dyn Fn() + Send + Sync>> {
+            Ok(Box::new(|| {}))
+        }
+    }
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Serves a client which sends all of its input at once, and returns the outcome with the
+    // replies.
+    fn serve(
+        input: &[u8],
+        store: &Store,
+        broker: &Broker,
+        protocol: Protocol,
+    ) -> (Result<bool, String>, String) {
+        let output = Output::default();
+        let served = handle(
+            Cursor::new(input.to_vec()),
+            output.clone(),
+            store,
+            broker,
+            protocol,
+        );
+        (served, output.text())
+    }
+
+    fn request(words: &[&str]) -> Vec<u8> {
+        Value::Array(words.iter().map(|w| Value::bulk(w)).collect()).encode()
+    }
+
+    fn sample_values() -> Vec<Value> {
+        vec![
+            Value::Simple("OK".into()),
+            Value::Error("ERR no such key".into()),
+            Value::Integer(-42),
+            Value::bulk("hello\r\nworld"),
+            Value::Bulk(Vec::new()),
+            Value::Null,
+            Value::Array(vec![]),
+            Value::Array(vec![
+                Value::Integer(1),
+                Value::Array(vec![Value::bulk("nested"), Value::Null]),
+            ]),
+        ]
+    }
+
+    #[test]
+    fn should_parse_values_fed_byte_by_byte() {
+        let bytes: Vec<u8> = sample_values().iter().flat_map(|v| v.encode()).collect();
+        let mut parser = Parser::new();
+        let mut parsed = Vec::new();
+        for &byte in &bytes {
+            parser.feed(&[byte]);
+            while let Some(value) = parser.next_value().unwrap() {
+                parsed.push(value);
+            }
+        }
+        assert_that!(parsed).is_equal_to(sample_values());
+        assert_that!(parser.is_empty()).is_true();
+    }
+
+    #[test]
+    fn should_parse_null_array() {
+        let mut parser = Parser::new();
+        parser.feed(b"*-1\r\n");
+        assert_that!(parser.next_value()).is_equal_to(Ok(Some(Value::Null)));
+    }
+
+    #[test]
+    fn should_reject_malformed_values() {
+        let cases: Vec<(&[u8], ProtocolError)> = vec![
+            (b"?x\r\n", ProtocolError::UnknownType(b'?')),
+            (b"PING", ProtocolError::UnknownType(b'P')),
+            (b":12a\r\n", ProtocolError::InvalidLength("12a".into())),
+            (b"$-2\r\n", ProtocolError::InvalidLength("-2".into())),
+            (b"$3\r\nabcd\r\n", ProtocolError::MissingCrlf),
+            (b"*1\r\n$x\r\n", ProtocolError::InvalidLength("x".into())),
+        ];
+        for (bytes, error) in cases {
+            let mut parser = Parser::new();
+            parser.feed(bytes);
+            assert_that!(parser.next_value()).is_equal_to(Err(error));
+        }
+    }
+
+    #[test]
+    fn should_parse_each_byte_once_when_fed_in_chunks() {
+        let value = Value::Array(vec![Value::bulk("a"); 100_000]);
+        let bytes = value.encode();
+        let mut parser = Parser::new();
+        let mut parsed = None;
+        for chunk in bytes.chunks(4096) {
+            let before = parser.examined();
+            parser.feed(chunk);
+            if let Some(value) = parser.next_value().unwrap() {
+                parsed = Some(value);
+            }
+            // re-parsing the buffered part of the array would take more after every chunk
+            assert_that!(parser.examined() - before).is_less_than_or_equal_to(chunk.len() + 2);
+        }
+        assert_that!(parsed).is_equal_to(Some(value));
+        assert_that!(parser.is_empty()).is_true();
+    }
+
+    #[test]
+    fn should_reject_arrays_nested_too_deep() {
+        let mut parser = Parser::new();
+        parser.feed(&b"*1\r\n".repeat(200_000));
+        assert_that!(parser.next_value()).is_equal_to(Err(ProtocolError::TooDeep));
+
+        let mut parser = Parser::new();
+        let mut value = Value::Null;
+        for _ in 0..MAX_DEPTH {
+            value = Value::Array(vec![value]);
+        }
+        parser.feed(&value.encode());
+        assert_that!(parser.next_value()).is_equal_to(Ok(Some(value)));
+    }
+
+    #[test]
+    fn should_split_lines() {
+        let mut parser = Parser::new();
+        parser.feed(b"ENQUEUE a,b\r\nRETR");
+        assert_that!(parser.next_line()).is_equal_to(Ok(Some("ENQUEUE a,b".to_string())));
+        assert_that!(parser.next_line()).is_equal_to(Ok(None));
+        parser.feed(b"IEVE\n");
+        assert_that!(parser.next_line()).is_equal_to(Ok(Some("RETRIEVE".to_string())));
+    }
+
+    #[test]
+    fn should_reject_lines_too_long() {
+        let long_line = vec![b'a'; MAX_LINE_LENGTH + 1];
+        for &kind in b"+$".iter() {
+            // without the end of the line, the error comes as soon as it is too long
+            let mut parser = Parser::new();
+            parser.feed(&[kind]);
+            for chunk in long_line.chunks(4096) {
+                assert_that!(parser.next_value()).is_equal_to(Ok(None));
+                parser.feed(chunk);
+            }
+            assert_that!(parser.next_value()).is_equal_to(Err(ProtocolError::LineTooLong));
+
+            let mut parser = Parser::new();
+            parser.feed(&[kind]);
+            parser.feed(&long_line);
+            parser.feed(b"\r\n");
+            assert_that!(parser.next_value()).is_equal_to(Err(ProtocolError::LineTooLong));
+        }
+
+        let mut parser = Parser::new();
+        parser.feed(&long_line);
+        assert_that!(parser.next_line()).is_equal_to(Err(ProtocolError::LineTooLong));
+        let mut parser = Parser::new();
+        parser.feed(&long_line[2..]);
+        parser.feed(b"\r\n");
+        assert_that!(parser.next_line()).is_equal_to(Ok(Some("a".repeat(MAX_LINE_LENGTH - 1))));
+    }
+
+    #[test]
+    fn should_parse_commands() {
+        assert_that!(Command::from_line("enqueue a, b,c\n")).is_equal_to(Command {
+            name: "ENQUEUE".into(),
+            args: vec!["a".into(), "b".into(), "c".into()],
+        });
+        assert_that!(Command::from_line("RETRIEVE")).is_equal_to(Command {
+            name: "RETRIEVE".into(),
+            args: vec![],
+        });
+        let value = Value::Array(vec![Value::bulk("enqueue"), Value::bulk("a,b")]);
+        assert_that!(Command::from_value(value)).is_equal_to(Ok(Command {
+            name: "ENQUEUE".into(),
+            args: vec!["a,b".into()],
+        }));
+        assert_that!(Command::from_value(Value::Array(vec![]))).is_err();
+        assert_that!(Command::from_value(Value::Array(vec![Value::Integer(1)]))).is_err();
+    }
+
+    #[test]
+    fn should_serve_resp_client() {
+        let mut input = request(&["PING"]);
+        input.extend(request(&["ENQUEUE", "q", "a", "b"]));
+        input.extend(request(&["retrieve", "q"]));
+        input.extend(request(&["RETRIEVE", "q"]));
+        input.extend(request(&["RETRIEVE", "q"]));
+        input.extend(request(&["RETRIEVE"]));
+        input.extend(request(&["NOPE"]));
+        input.extend(request(&["STOP"]));
+        input.extend(request(&["PING"]));
+
+        let (served, output) = serve(&input, &Store::new(), &Broker::new(), Protocol::Resp);
+        assert_that!(served).is_equal_to(Ok(true));
+        let expected = "$4\r\nPONG\r\n+OK\r\n$1\r\na\r\n$1\r\nb\r\n\
+                        -ERR Empty RETRIEVE\r\n\
+                        -ERR wrong number of arguments for 'retrieve' command\r\n\
+                        -ERR Unrecognized command NOPE\r\n+OK\r\n";
+        assert_that!(output).is_equal_to(expected.to_string());
+    }
+
+    #[test]
+    fn should_serve_key_value_commands() {
+        let requests: Vec<&[&str]> = vec![
+            &["SET", "k", "v"],
+            &["GET", "k"],
+            &["GET", "none"],
+            &["TTL", "k"],
+            &["EXPIRE", "k", "100"],
+            &["EXPIRE", "k", "9223372036854775807"],
+            &["TTL", "k"],
+            &["TTL", "none"],
+            &["EXISTS", "k", "k", "none"],
+            &["RPUSH", "l", "b", "c"],
+            &["LPUSH", "l", "a"],
+            &["LRANGE", "l", "0", "-1"],
+            &["LLEN", "l"],
+            &["LPOP", "l"],
+            &["RPOP", "l"],
+            &["GET", "l"],
+            &["ENQUEUE", "l", "d"],
+            &["RETRIEVE", "l"],
+            &["EXPIRE", "l", "0"],
+            &["LPOP", "l"],
+            &["DEL", "k", "l"],
+        ];
+        let input: Vec<u8> = requests.iter().flat_map(|words| request(words)).collect();
+
+        let (served, output) = serve(&input, &Store::new(), &Broker::new(), Protocol::Resp);
+        assert_that!(served).is_equal_to(Ok(false));
+        let expected = "+OK\r\n$1\r\nv\r\n$-1\r\n:-1\r\n:1\r\n\
+                        -ERR invalid expire time\r\n:100\r\n:-2\r\n:2\r\n\
+                        :2\r\n:3\r\n*3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n:3\r\n\
+                        $1\r\na\r\n$1\r\nc\r\n\
+                        -ERR WRONGTYPE Operation against a key holding the wrong kind of value\r\n\
+                        +OK\r\n$1\r\nb\r\n:1\r\n$-1\r\n:1\r\n";
+        assert_that!(output).is_equal_to(expected.to_string());
+    }
+
+    #[test]
+    fn should_serve_subscription_commands() {
+        let long_pattern = "*".repeat(MAX_PATTERN_LENGTH + 1);
+        let long_psubscribe = ["PSUBSCRIBE", &long_pattern];
+        let requests: Vec<&[&str]> = vec![
+            &["PUBLISH", "a", "hello"],
+            &["UNSUBSCRIBE"],
+            &["SUBSCRIBE", "a", "b"],
+            &long_psubscribe,
+            &["PSUBSCRIBE", "c.*"],
+            &["GET", "k"],
+            &["PING"],
+            &["UNSUBSCRIBE", "b"],
+            &["PUNSUBSCRIBE"],
+            &["UNSUBSCRIBE"],
+            &["GET", "k"],
+        ];
+        let input: Vec<u8> = requests.iter().flat_map(|words| request(words)).collect();
+        let broker = Broker::new();
+
+        let (served, output) = serve(&input, &Store::new(), &broker, Protocol::Resp);
+        assert_that!(served).is_equal_to(Ok(false));
+        let expected = ":0\r\n*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n\
+                        *3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n\
+                        *3\r\n$9\r\nsubscribe\r\n$1\r\nb\r\n:2\r\n\
+                        -ERR pattern is longer than 1024 characters\r\n\
+                        *3\r\n$10\r\npsubscribe\r\n$3\r\nc.*\r\n:3\r\n\
+                        -ERR Can't execute 'get': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / \
+                        STOP are allowed in this context\r\n\
+                        $4\r\nPONG\r\n\
+                        *3\r\n$11\r\nunsubscribe\r\n$1\r\nb\r\n:2\r\n\
+                        *3\r\n$12\r\npunsubscribe\r\n$3\r\nc.*\r\n:1\r\n\
+                        *3\r\n$11\r\nunsubscribe\r\n$1\r\na\r\n:0\r\n$-1\r\n";
+        assert_that!(output).is_equal_to(expected.to_string());
+        // the subscriptions end with the connection
+        assert_that!(broker.publish("a", "hello")).is_equal_to(0);
+    }
+
+    #[test]
+    fn should_close_connection_after_protocol_error() {
+        let mut input = request(&["ENQUEUE", "q", "a"]);
+        input.extend(b"ENQUEUE q,b\r\n");
+        let store = Store::new();
+
+        let (served, output) = serve(&input, &store, &Broker::new(), Protocol::Resp);
+        assert_that!(served).is_err();
+        assert_that!(output)
+            .is_equal_to("+OK\r\n-ERR Protocol error: unknown type byte 'E'\r\n".to_string());
+        assert_that!(store.range("q", 0, -1)).is_equal_to(Ok(vec!["a".to_string()]));
+    }
+
+    #[test]
+    fn should_serve_line_client() {
+        let input = b"ENQUEUE q,a,b\nRETRIEVE q\nFOO\nRETRIEVE q\nRPUSH l,x,y\nLRANGE l,0,-1\n\
+                      SUBSCRIBE a\nUNSUBSCRIBE\nUNSUBSCRIBE\n";
+
+        // the client disconnects without STOP
+        let (served, output) = serve(input, &Store::new(), &Broker::new(), Protocol::Line);
+        assert_that!(served).is_equal_to(Ok(false));
+        assert_that!(output).is_equal_to(
+            "a\nError: Unrecognized command FOO\nb\n2\nx,y\n\
+             subscribe,a,1\nunsubscribe,a,0\nunsubscribe,,0\n"
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn should_reply_nil_after_bretrieve_timeout() {
+        let mut input = request(&["ENQUEUE", "q", "a"]);
+        input.extend(request(&["BRETRIEVE", "q", "0.01"]));
+        input.extend(request(&["BRETRIEVE", "q", "0.01"]));
+        input.extend(request(&["BRETRIEVE", "q", "-1"]));
+        input.extend(request(&["BRETRIEVE", "q", "1e20"]));
+        input.extend(request(&["BRETRIEVE", "q", "1e19"]));
+        input.extend(request(&["BRETRIEVE", "q"]));
+        let (store, broker) = (Store::new(), Broker::new());
+
+        let (served, output) = serve(&input, &store, &broker, Protocol::Resp);
+        assert_that!(served).is_equal_to(Ok(false));
+        let expected = "+OK\r\n$1\r\na\r\n$-1\r\n-ERR timeout is negative\r\n\
+                        -ERR timeout is out of range\r\n-ERR timeout is out of range\r\n\
+                        -ERR wrong number of arguments for 'bretrieve' command\r\n";
+        assert_that!(output).is_equal_to(expected.to_string());
+
+        let input = b"BRETRIEVE q,0.01\nBRETRIEVE q,x\n";
+        let (served, output) = serve(input, &store, &broker, Protocol::Line);
+        assert_that!(served).is_equal_to(Ok(false));
+        assert_that!(output)
+            .is_equal_to("(nil)\nError: timeout is not a float or out of range\n".to_string());
+    }
+
+    quickcheck! {
+        fn encoded_bulk_strings_parse_back(words: Vec<Vec<u8>>, split: usize) -> bool {
+            let value = Value::Array(words.into_iter().map(Value::Bulk).collect());
+            let bytes = value.encode();
+            let split = split % (bytes.len() + 1);
+            let mut parser = Parser::new();
+            parser.feed(&bytes[..split]);
+            let early = parser.next_value();
+            if split < bytes.len() && early != Ok(None) {
+                return false;
+            }
+            parser.feed(&bytes[split..]);
+            let parsed = if split == bytes.len() { early } else { parser.next_value() };
+            parsed == Ok(Some(value)) && parser.is_empty()
+        }
+    }
+}
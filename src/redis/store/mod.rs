@@ -0,0 +1,411 @@
+//! The data shared by all the clients: strings and lists under their keys, some of them with
+//! an expiration time. Expired keys are removed when they are next touched.
+//!
+//! A named queue is a list, published to at the back and retrieved from the front. Clients
+//! which wait for a word with `BRETRIEVE` line up, and every word pushed to a list goes to the
+//! first of them in line, before anything is left in the list. So there are never words in a
+//! list while someone waits for it, and waiting clients are served in the order they came.
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The place of a waiting client in the line.
+pub type Ticket = u64;
+
+/// The error of a command used on a key holding the other kind of value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WrongType;
+
+impl fmt::Display for WrongType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        )
+    }
+}
+
+impl Error for WrongType {}
+
+/// An end of a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Front,
+    Back,
+}
+
+enum Data {
+    Text(String),
+    List(VecDeque<String>),
+}
+
+struct Entry {
+    data: Data,
+    expires: Option<Instant>,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<String, Entry>,
+    // the tickets waiting for each list, in order
+    lines: HashMap<String, VecDeque<Ticket>>,
+    // the list each ticket waits for
+    waiting: HashMap<Ticket, String>,
+    // the words handed to the tickets, with their lists
+    delivered: HashMap<Ticket, (String, String)>,
+    next_ticket: Ticket,
+}
+
+impl State {
+    // Returns the entry under the key, after removing it if expired.
+    fn entry(&mut self, key: &str) -> Option<&mut Entry> {
+        let now = Instant::now();
+        if let Some(Entry {
+            expires: Some(expires),
+            ..
+        }) = self.entries.get(key)
+        {
+            if *expires <= now {
+                self.entries.remove(key);
+            }
+        }
+        self.entries.get_mut(key)
+    }
+
+    fn list(&mut self, key: &str) -> Result<Option<&mut VecDeque<String>>, WrongType> {
+        match self.entry(key) {
+            None => Ok(None),
+            Some(Entry {
+                data: Data::List(list),
+                ..
+            }) => Ok(Some(list)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    // Removes the list if it is empty, as lists exist only with elements.
+    fn remove_if_empty(&mut self, key: &str) {
+        if let Some(Entry {
+            data: Data::List(list),
+            ..
+        }) = self.entries.get(key)
+        {
+            if list.is_empty() {
+                self.entries.remove(key);
+            }
+        }
+    }
+
+    fn push<I: IntoIterator<Item = String>>(
+        &mut self,
+        key: &str,
+        words: I,
+        end: End,
+    ) -> Result<usize, WrongType> {
+        if self.list(key)?.is_none() {
+            let entry = Entry {
+                data: Data::List(VecDeque::new()),
+                expires: None,
+            };
+            self.entries.insert(key.to_string(), entry);
+        }
+        let list = self.list(key)?.unwrap();
+        match end {
+            End::Front => words.into_iter().for_each(|word| list.push_front(word)),
+            End::Back => list.extend(words),
+        }
+        Ok(list.len())
+    }
+
+    // Hands the words at the front of the list to the clients waiting for it. Returns `true`
+    // if any were handed over.
+    fn deliver(&mut self, key: &str) -> bool {
+        let mut delivered = false;
+        while let Some(line) = self.lines.get_mut(key) {
+            let word = match self.entries.get_mut(key) {
+                Some(Entry {
+                    data: Data::List(list),
+                    ..
+                }) => match list.pop_front() {
+                    Some(word) => word,
+                    None => break,
+                },
+                _ => break,
+            };
+            let ticket = line.pop_front().unwrap();
+            if line.is_empty() {
+                self.lines.remove(key);
+            }
+            self.waiting.remove(&ticket);
+            self.delivered.insert(ticket, (key.to_string(), word));
+            delivered = true;
+        }
+        self.remove_if_empty(key);
+        delivered
+    }
+
+    fn leave_line(&mut self, ticket: Ticket) {
+        if let Some(key) = self.waiting.remove(&ticket) {
+            if let Some(line) = self.lines.get_mut(&key) {
+                line.retain(|&waiting| waiting != ticket);
+                if line.is_empty() {
+                    self.lines.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+/// The keys and values of the server.
+///
+/// # Examples
+///
+/// ```
+/// use rust_experiments::redis::{End, Store};
+///
+/// let store = Store::new();
+/// let first = store.join("jobs").unwrap().unwrap_err();
+/// let second = store.join("jobs").unwrap().unwrap_err();
+/// store.push("jobs", vec!["a".to_string(), "b".to_string(), "c".to_string()], End::Back).unwrap();
+/// assert_eq!(store.collect(second), Some("b".to_string()));
+/// assert_eq!(store.collect(first), Some("a".to_string()));
+/// assert_eq!(store.pop("jobs", End::Front), Ok(Some("c".to_string())));
+/// ```
+#[derive(Default)]
+pub struct Store {
+    state: Mutex<State>,
+    delivered: Condvar,
+    listeners: Mutex<Vec<Box<dyn Fn() + Send>>>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Store::default()
+    }
+
+    // Nothing panics while holding the lock, so the state is consistent even if poisoned.
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn notify(&self) {
+        self.delivered.notify_all();
+        let listeners = self
+            .listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        listeners.iter().for_each(|listener| listener());
+    }
+
+    /// Returns the string under the key.
+    pub fn get(&self, key: &str) -> Result<Option<String>, WrongType> {
+        match self.state().entry(key) {
+            None => Ok(None),
+            Some(Entry {
+                data: Data::Text(text),
+                ..
+            }) => Ok(Some(text.clone())),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// Sets the string under the key, replacing any value, and removing its expiration.
+    pub fn set(&self, key: &str, value: String) {
+        let entry = Entry {
+            data: Data::Text(value),
+            expires: None,
+        };
+        self.state().entries.insert(key.to_string(), entry);
+    }
+
+    /// Removes the keys, and returns how many of them there were.
+    pub fn del<'a, I: IntoIterator<Item = &'a str>>(&self, keys: I) -> usize {
+        let mut state = self.state();
+        keys.into_iter()
+            .filter(|key| state.entry(key).is_some() && state.entries.remove(*key).is_some())
+            .count()
+    }
+
+    /// Counts the keys which exist, as many times as they are given.
+    pub fn exists<'a, I: IntoIterator<Item = &'a str>>(&self, keys: I) -> usize {
+        let mut state = self.state();
+        keys.into_iter()
+            .filter(|key| state.entry(key).is_some())
+            .count()
+    }
+
+    /// Makes the key expire at the given instant. Returns `false` if there is no such key.
+    pub fn expire_at(&self, key: &str, at: Instant) -> bool {
+        match self.state().entry(key) {
+            Some(entry) => {
+                entry.expires = Some(at);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the time to live of the key: `None` if there is no such key, and `Some(None)`
+    /// if the key does not expire.
+    pub fn ttl(&self, key: &str) -> Option<Option<Duration>> {
+        let now = Instant::now();
+        self.state()
+            .entry(key)
+            .map(|entry| entry.expires.map(|expires| expires - now))
+    }
+
+    /// Pushes the words to an end of the list, creating it if needed, and returns its length.
+    /// Words pushed to the front end up in reverse order. If clients wait for the list, the
+    /// words at its front are handed to them.
+    pub fn push<I: IntoIterator<Item = String>>(
+        &self,
+        key: &str,
+        words: I,
+        end: End,
+    ) -> Result<usize, WrongType> {
+        let mut state = self.state();
+        let len = state.push(key, words, end)?;
+        if state.deliver(key) {
+            drop(state);
+            self.notify();
+        }
+        Ok(len)
+    }
+
+    /// Takes a word from an end of the list.
+    pub fn pop(&self, key: &str, end: End) -> Result<Option<String>, WrongType> {
+        let mut state = self.state();
+        let word = match state.list(key)? {
+            Some(list) => match end {
+                End::Front => list.pop_front(),
+                End::Back => list.pop_back(),
+            },
+            None => None,
+        };
+        state.remove_if_empty(key);
+        Ok(word)
+    }
+
+    /// Returns the length of the list, 0 if there is none.
+    pub fn len(&self, key: &str) -> Result<usize, WrongType> {
+        Ok(self.state().list(key)?.map_or(0, |list| list.len()))
+    }
+
+    /// Returns the words of the list from `start` to `stop` inclusive. Negative indices count
+    /// from the end, as in `LRANGE`.
+    pub fn range(&self, key: &str, start: i64, stop: i64) -> Result<Vec<String>, WrongType> {
+        let mut state = self.state();
+        let list = match state.list(key)? {
+            Some(list) => list,
+            None => return Ok(Vec::new()),
+        };
+        let len = list.len() as i64;
+        let resolve = |index: i64| if index < 0 { len + index } else { index };
+        let (start, stop) = (resolve(start).max(0), resolve(stop).min(len - 1));
+        if start > stop {
+            return Ok(Vec::new());
+        }
+        Ok(list
+            .iter()
+            .skip(start as usize)
+            .take((stop - start + 1) as usize)
+            .cloned()
+            .collect())
+    }
+
+    /// Takes the first word of the list if there is one, or gets in line for the next ones
+    /// and returns the ticket to [`collect`] the word with.
+    ///
+    /// [`collect`]: #method.collect
+    pub fn join(&self, key: &str) -> Result<Result<String, Ticket>, WrongType> {
+        let mut state = self.state();
+        let word = state.list(key)?.and_then(|list| list.pop_front());
+        state.remove_if_empty(key);
+        Ok(match word {
+            Some(word) => Ok(word),
+            None => {
+                let ticket = state.next_ticket;
+                state.next_ticket += 1;
+                state
+                    .lines
+                    .entry(key.to_string())
+                    .or_default()
+                    .push_back(ticket);
+                state.waiting.insert(ticket, key.to_string());
+                Err(ticket)
+            }
+        })
+    }
+
+    /// Takes the word handed to the ticket, if it was.
+    pub fn collect(&self, ticket: Ticket) -> Option<String> {
+        self.state().delivered.remove(&ticket).map(|(_, word)| word)
+    }
+
+    /// Leaves the line. Returns the word handed to the ticket in the meantime, if any.
+    pub fn cancel(&self, ticket: Ticket) -> Option<String> {
+        let mut state = self.state();
+        state.leave_line(ticket);
+        state.delivered.remove(&ticket).map(|(_, word)| word)
+    }
+
+    /// Leaves the line for good, e.g. when the client disconnects. A word handed to the
+    /// ticket in the meantime is given back to its list.
+    pub fn abandon(&self, ticket: Ticket) {
+        let delivered = {
+            let mut state = self.state();
+            state.leave_line(ticket);
+            state.delivered.remove(&ticket)
+        };
+        if let Some((key, word)) = delivered {
+            self.give_back(&key, word);
+        }
+    }
+
+    /// Puts the word back at the front of the list, e.g. when it could not be sent to the
+    /// client which took it. If someone waits for the list, it is handed to them instead.
+    pub fn give_back(&self, key: &str, word: String) {
+        // a key which became a string in the meantime loses the word
+        let _ = self.push(key, Some(word), End::Front);
+    }
+
+    /// Blocks until a word is handed to the ticket, or until the deadline. Returns `None` if
+    /// the deadline passed first, and the ticket is then out of line.
+    pub fn wait(&self, ticket: Ticket, deadline: Option<Instant>) -> Option<String> {
+        let mut state = self.state();
+        loop {
+            if let Some((_, word)) = state.delivered.remove(&ticket) {
+                return Some(word);
+            }
+            state = match deadline {
+                None => self
+                    .delivered
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        state.leave_line(ticket);
+                        return None;
+                    }
+                    self.delivered
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+    }
+
+    /// Registers a function called whenever words are handed to waiting clients, e.g. to wake
+    /// up an event loop in which they wait.
+    pub fn on_delivery<F: Fn() + Send + 'static>(&self, listener: F) {
+        self.listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(listener));
+    }
+}
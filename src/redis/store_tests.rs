@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod store_tests {
+    use crate::redis::*;
+
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use spectral::prelude::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn should_keep_queues_apart() {
+        let store = Store::new();
+        store.push("a", words(&["1", "2"]), End::Back).unwrap();
+        store.push("b", words(&["3"]), End::Back).unwrap();
+        store.push("a", words(&["4"]), End::Back).unwrap();
+        assert_that!(store.len("a")).is_equal_to(Ok(3));
+        assert_that!(store.pop("a", End::Front)).is_equal_to(Ok(Some("1".to_string())));
+        assert_that!(store.join("a").unwrap()).is_equal_to(Ok("2".to_string()));
+        assert_that!(store.pop("b", End::Front)).is_equal_to(Ok(Some("3".to_string())));
+        assert_that!(store.pop("b", End::Front)).is_equal_to(Ok(None));
+        // an emptied list is removed
+        assert_that!(store.exists(vec!["a", "b"])).is_equal_to(1);
+    }
+
+    #[test]
+    fn should_push_and_pop_at_both_ends() {
+        let store = Store::new();
+        assert_that!(store.push("l", words(&["a", "b"]), End::Front)).is_equal_to(Ok(2));
+        assert_that!(store.push("l", words(&["c", "d"]), End::Back)).is_equal_to(Ok(4));
+        assert_that!(store.range("l", 0, -1)).is_equal_to(Ok(words(&["b", "a", "c", "d"])));
+        assert_that!(store.range("l", -3, 1)).is_equal_to(Ok(words(&["a"])));
+        assert_that!(store.range("l", 2, 100)).is_equal_to(Ok(words(&["c", "d"])));
+        assert_that!(store.range("l", 3, 2)).is_equal_to(Ok(vec![]));
+        assert_that!(store.range("none", 0, -1)).is_equal_to(Ok(vec![]));
+        assert_that!(store.pop("l", End::Back)).is_equal_to(Ok(Some("d".to_string())));
+        assert_that!(store.pop("l", End::Front)).is_equal_to(Ok(Some("b".to_string())));
+        assert_that!(store.len("l")).is_equal_to(Ok(2));
+    }
+
+    #[test]
+    fn should_keep_strings_and_lists_apart() {
+        let store = Store::new();
+        store.set("s", "value".to_string());
+        store.push("l", words(&["a"]), End::Back).unwrap();
+        assert_that!(store.get("s")).is_equal_to(Ok(Some("value".to_string())));
+        assert_that!(store.get("l")).is_equal_to(Err(WrongType));
+        assert_that!(store.push("s", words(&["a"]), End::Back)).is_equal_to(Err(WrongType));
+        assert_that!(store.pop("s", End::Front)).is_equal_to(Err(WrongType));
+        assert_that!(store.len("s")).is_equal_to(Err(WrongType));
+        assert_that!(store.join("s")).is_equal_to(Err(WrongType));
+        // SET replaces a value of any kind
+        store.set("l", "other".to_string());
+        assert_that!(store.get("l")).is_equal_to(Ok(Some("other".to_string())));
+        assert_that!(store.del(vec!["s", "l", "none"])).is_equal_to(2);
+        assert_that!(store.get("s")).is_equal_to(Ok(None));
+    }
+
+    fn after(duration: Duration) -> Instant {
+        Instant::now() + duration
+    }
+
+    #[test]
+    fn should_expire_keys() {
+        let store = Store::new();
+        store.set("s", "value".to_string());
+        store.push("l", words(&["a"]), End::Back).unwrap();
+        assert_that!(store.ttl("s")).is_equal_to(Some(None));
+        assert_that!(store.ttl("none")).is_none();
+        assert_that!(store.expire_at("none", after(Duration::from_secs(1)))).is_false();
+        assert_that!(store.expire_at("s", after(Duration::from_millis(30)))).is_true();
+        assert_that!(store.expire_at("l", after(Duration::from_millis(30)))).is_true();
+        assert_that!(store.expire_at("l", after(Duration::from_secs(100)))).is_true();
+        let ttl = store.ttl("s").unwrap().unwrap();
+        assert_that!(ttl > Duration::from_millis(0) && ttl <= Duration::from_millis(30)).is_true();
+
+        thread::sleep(Duration::from_millis(40));
+        assert_that!(store.get("s")).is_equal_to(Ok(None));
+        assert_that!(store.exists(vec!["s", "l"])).is_equal_to(1);
+        // SET removes the expiration
+        store.set("l", "value".to_string());
+        assert_that!(store.ttl("l")).is_equal_to(Some(None));
+    }
+
+    #[test]
+    fn should_serve_waiting_clients_in_order() {
+        let store = Store::new();
+        let tickets: Vec<Ticket> = (0..3)
+            .map(|_| store.join("q").unwrap().unwrap_err())
+            .collect();
+        let other = store.join("other").unwrap().unwrap_err();
+        assert_that!(store.push("q", words(&["a", "b"]), End::Back)).is_equal_to(Ok(2));
+        // the words go to the waiting clients, not to the list
+        assert_that!(store.exists(vec!["q"])).is_equal_to(0);
+        assert_that!(store.collect(tickets[2])).is_none();
+        assert_that!(store.collect(other)).is_none();
+        assert_that!(store.collect(tickets[1])).is_equal_to(Some("b".to_string()));
+        assert_that!(store.collect(tickets[0])).is_equal_to(Some("a".to_string()));
+
+        store.push("q", words(&["c", "d"]), End::Front).unwrap();
+        assert_that!(store.collect(tickets[2])).is_equal_to(Some("d".to_string()));
+        assert_that!(store.pop("q", End::Front)).is_equal_to(Ok(Some("c".to_string())));
+    }
+
+    #[test]
+    fn should_skip_cancelled_tickets() {
+        let store = Store::new();
+        let first = store.join("q").unwrap().unwrap_err();
+        let second = store.join("q").unwrap().unwrap_err();
+        assert_that!(store.cancel(first)).is_none();
+        store.push("q", words(&["a"]), End::Back).unwrap();
+        assert_that!(store.collect(second)).is_equal_to(Some("a".to_string()));
+
+        // a word handed over before cancelling is returned by it
+        let third = store.join("q").unwrap().unwrap_err();
+        store.push("q", words(&["b"]), End::Back).unwrap();
+        assert_that!(store.cancel(third)).is_equal_to(Some("b".to_string()));
+    }
+
+    #[test]
+    fn should_give_back_words() {
+        let store = Store::new();
+        store.push("q", words(&["b"]), End::Back).unwrap();
+        store.give_back("q", "a".to_string());
+        assert_that!(store.range("q", 0, -1)).is_equal_to(Ok(words(&["a", "b"])));
+        store.del(vec!["q"]);
+
+        let first = store.join("q").unwrap().unwrap_err();
+        let second = store.join("q").unwrap().unwrap_err();
+        store.push("q", words(&["c"]), End::Back).unwrap();
+        // the first client is gone, and its word goes to the next one
+        store.abandon(first);
+        assert_that!(store.collect(second)).is_equal_to(Some("c".to_string()));
+    }
+
+    #[test]
+    fn should_time_out_waiting() {
+        let store = Store::new();
+        let ticket = store.join("q").unwrap().unwrap_err();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        assert_that!(store.wait(ticket, Some(deadline))).is_none();
+        assert_that!(Instant::now() >= deadline).is_true();
+        // the ticket is out of line, so the next word stays in the list
+        store.push("q", words(&["a"]), End::Back).unwrap();
+        assert_that!(store.len("q")).is_equal_to(Ok(1));
+    }
+
+    #[test]
+    fn should_wake_up_waiting_threads() {
+        let store = Arc::new(Store::new());
+        let (sender, receiver) = std::sync::mpsc::channel();
+        store.on_delivery(move || sender.send(()).unwrap());
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let ticket = store.join("q").unwrap().unwrap_err();
+                let store = store.clone();
+                thread::spawn(move || store.wait(ticket, None))
+            })
+            .collect();
+        store
+            .push("q", words(&["a", "b", "c", "d"]), End::Back)
+            .unwrap();
+        let received: Vec<Option<String>> =
+            handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_that!(received).is_equal_to(
+            words(&["a", "b", "c", "d"])
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>(),
+        );
+        assert_that!(receiver.try_recv()).is_ok();
+    }
+}
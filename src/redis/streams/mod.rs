@@ -1,17 +1,64 @@
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::net::TcpStream;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
 
-pub fn read(reader: &mut TcpStream) -> Result<String, String> {
-    let mut buf_str = String::new();
-    match BufReader::new(reader).read_line(&mut buf_str) {
-        Ok(_) => Ok(buf_str),
-        Err(err) => Err(err.to_string()),
+use super::core::{next_command, Command, Protocol};
+use super::resp::Parser;
+
+/// The reading side of a blocking client connection: reads requests in one of the protocols.
+/// Incoming bytes are buffered, so requests may be split between reads, or sent many at once.
+pub struct Connection<R: Read> {
+    stream: R,
+    parser: Parser,
+}
+
+impl<R: Read> Connection<R> {
+    pub fn new(stream: R) -> Self {
+        Connection {
+            stream,
+            parser: Parser::new(),
+        }
+    }
+
+    // Reads more bytes into the parser. Returns `false` at the end of the stream.
+    fn fill(&mut self) -> Result<bool, String> {
+        let mut buf = [0; 4096];
+        match self.stream.read(&mut buf) {
+            Ok(0) => Ok(false),
+            Ok(n) => {
+                self.parser.feed(&buf[..n]);
+                Ok(true)
+            }
+            Err(err) => Err(err.to_string()),
+        }
     }
+
+    /// Reads the next command. Returns `None` if the client disconnected.
+    pub fn read_command(&mut self, protocol: Protocol) -> Result<Option<Command>, String> {
+        loop {
+            if let Some(command) = next_command(&mut self.parser, protocol)? {
+                return Ok(Some(command));
+            }
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// The writing side of a client connection in the threaded server, shared with the thread
+/// sending the client the messages published to it.
+pub trait Writer: Write + Send + 'static {
+    /// Returns a function closing the connection, which works even while a write to it is
+    /// blocked, to drop a client which does not keep up with its messages.
+    fn closer(&self) -> io::Result<Box<dyn Fn() + Send + Sync>>;
 }
 
-pub fn write(writer: &mut TcpStream, word: &str) -> Result<(), String> {
-    match BufWriter::new(writer).write(format!("{}\n", word).as_bytes()) {
-        Ok(_) => Ok(()),
-        Err(err) => Err(err.to_string()),
+impl Writer for TcpStream {
+    fn closer(&self) -> io::Result<Box<dyn Fn() + Send + Sync>> {
+        let stream = self.try_clone()?;
+        Ok(Box::new(move || {
+            // the connection may be closed already
+            let _ = stream.shutdown(Shutdown::Both);
+        }))
     }
 }
@@ -0,0 +1,371 @@
+#[cfg(test)]
+mod conformance_tests {
+    // Model-based tests: random sequences of operations are applied both to `USet`/`UMap`
+    // and to `BTreeSet`/`BTreeMap`, and every intermediate state is compared.
+    // On failure quickcheck shrinks the sequence to a minimal one.
+
+    use crate::utils::umap::*;
+    use crate::utils::uset::*;
+
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use quickcheck::{Arbitrary, Gen};
+
+    // small identifiers, so that the operations often hit the same values
+    const MAX_ID: usize = 64;
+
+    fn gen_id<G: Gen>(g: &mut G) -> usize {
+        usize::arbitrary(g) % MAX_ID
+    }
+
+    fn gen_ids<G: Gen>(g: &mut G) -> Vec<usize> {
+        Vec::<usize>::arbitrary(g)
+            .into_iter()
+            .map(|id| id % MAX_ID)
+            .collect()
+    }
+
+    // small values, so that different identifiers often hold equal values
+    fn gen_value<G: Gen>(g: &mut G) -> u8 {
+        u8::arbitrary(g) % 4
+    }
+
+    #[derive(Debug, Clone)]
+    enum SetOp {
+        Push(usize),
+        Remove(usize),
+        Truncate(usize),
+        Drain(usize),
+        Pop(usize),
+        PushAll(Vec<usize>),
+        RemoveAll(Vec<usize>),
+        Union(Vec<usize>),
+        UnionRange(usize, usize),
+        Difference(Vec<usize>),
+        Intersection(Vec<usize>),
+        Xor(Vec<usize>),
+        ShrinkToFit,
+        Clear,
+    }
+
+    impl Arbitrary for SetOp {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            match u8::arbitrary(g) % 14 {
+                0 => SetOp::Push(gen_id(g)),
+                1 => SetOp::Remove(gen_id(g)),
+                2 => SetOp::Truncate(gen_id(g) % 16),
+                3 => SetOp::Drain(gen_id(g) % 16),
+                4 => SetOp::Pop(gen_id(g) % 16),
+                5 => SetOp::PushAll(gen_ids(g)),
+                6 => SetOp::RemoveAll(gen_ids(g)),
+                7 => SetOp::Union(gen_ids(g)),
+                8 => {
+                    let (a, b) = (gen_id(g), gen_id(g));
+                    SetOp::UnionRange(a.min(b), a.max(b))
+                }
+                9 => SetOp::Difference(gen_ids(g)),
+                10 => SetOp::Intersection(gen_ids(g)),
+                11 => SetOp::Xor(gen_ids(g)),
+                12 => SetOp::ShrinkToFit,
+                _ => SetOp::Clear,
+            }
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            match self.clone() {
+                SetOp::Push(id) => Box::new(id.shrink().map(SetOp::Push)),
+                SetOp::Remove(id) => Box::new(id.shrink().map(SetOp::Remove)),
+                SetOp::Truncate(len) => Box::new(len.shrink().map(SetOp::Truncate)),
+                SetOp::Drain(len) => Box::new(len.shrink().map(SetOp::Drain)),
+                SetOp::Pop(index) => Box::new(index.shrink().map(SetOp::Pop)),
+                SetOp::PushAll(ids) => Box::new(ids.shrink().map(SetOp::PushAll)),
+                SetOp::RemoveAll(ids) => Box::new(ids.shrink().map(SetOp::RemoveAll)),
+                SetOp::Union(ids) => Box::new(ids.shrink().map(SetOp::Union)),
+                SetOp::UnionRange(a, b) => Box::new(
+                    (a, b)
+                        .shrink()
+                        .filter(|&(a, b)| a <= b)
+                        .map(|(a, b)| SetOp::UnionRange(a, b)),
+                ),
+                SetOp::Difference(ids) => Box::new(ids.shrink().map(SetOp::Difference)),
+                SetOp::Intersection(ids) => Box::new(ids.shrink().map(SetOp::Intersection)),
+                SetOp::Xor(ids) => Box::new(ids.shrink().map(SetOp::Xor)),
+                SetOp::ShrinkToFit | SetOp::Clear => Box::new(std::iter::empty()),
+            }
+        }
+    }
+
+    /// Applies the operation to both the set and the model.
+    /// Returns `false` if the operation itself returned something the model disagrees with.
+    fn apply_set_op(op: &SetOp, set: &mut USet, model: &mut BTreeSet<usize>) -> bool {
+        match op {
+            SetOp::Push(id) => {
+                set.push(*id);
+                model.insert(*id);
+                true
+            }
+            SetOp::Remove(id) => {
+                set.remove(*id);
+                model.remove(id);
+                true
+            }
+            SetOp::Truncate(len) => {
+                set.truncate(*len);
+                *model = model.iter().cloned().take(*len).collect();
+                true
+            }
+            SetOp::Drain(len) => {
+                let drained = set.drain(*len);
+                let expected: BTreeSet<usize> = model.iter().cloned().skip(*len).collect();
+                *model = model.iter().cloned().take(*len).collect();
+                check_set(&drained, &expected)
+            }
+            SetOp::Pop(index) => {
+                let popped = set.pop(*index);
+                let expected = model.iter().cloned().nth(*index);
+                if let Some(id) = expected {
+                    model.remove(&id);
+                }
+                popped == expected
+            }
+            SetOp::PushAll(ids) => {
+                set.push_all(ids);
+                model.extend(ids.iter().cloned());
+                true
+            }
+            SetOp::RemoveAll(ids) => {
+                set.remove_all(&USet::from_slice(ids));
+                ids.iter().for_each(|id| {
+                    model.remove(id);
+                });
+                true
+            }
+            SetOp::Union(ids) => {
+                *set = &*set + &USet::from_slice(ids);
+                model.extend(ids.iter().cloned());
+                true
+            }
+            SetOp::UnionRange(start, end) => {
+                *set = &*set + &USet::from(*start..*end);
+                model.extend(*start..*end);
+                true
+            }
+            SetOp::Difference(ids) => {
+                *set = &*set - &USet::from_slice(ids);
+                let other: BTreeSet<usize> = ids.iter().cloned().collect();
+                *model = model.difference(&other).cloned().collect();
+                true
+            }
+            SetOp::Intersection(ids) => {
+                *set = &*set * &USet::from_slice(ids);
+                let other: BTreeSet<usize> = ids.iter().cloned().collect();
+                *model = model.intersection(&other).cloned().collect();
+                true
+            }
+            SetOp::Xor(ids) => {
+                *set = &*set ^ &USet::from_slice(ids);
+                let other: BTreeSet<usize> = ids.iter().cloned().collect();
+                *model = model.symmetric_difference(&other).cloned().collect();
+                true
+            }
+            SetOp::ShrinkToFit => {
+                set.shrink_to_fit();
+                true
+            }
+            SetOp::Clear => {
+                set.clear();
+                model.clear();
+                true
+            }
+        }
+    }
+
+    /// Compares the observable state of the set with the model and checks the internal invariants.
+    fn check_set(set: &USet, model: &BTreeSet<usize>) -> bool {
+        let expected: Vec<usize> = model.iter().cloned().collect();
+        let forward: Vec<usize> = set.iter().collect();
+        let backward: Vec<usize> = set.iter().rev().collect();
+
+        let layout_ok = match (set.min(), set.max()) {
+            (Some(min), Some(max)) => {
+                set.offset() <= min && min <= max && max - set.offset() < set.capacity()
+            }
+            (None, None) => true,
+            _ => false,
+        };
+
+        set.len() == model.len()
+            && set.is_empty() == model.is_empty()
+            && set.min() == model.iter().next().cloned()
+            && set.max() == model.iter().next_back().cloned()
+            && layout_ok
+            && forward == expected
+            && backward.into_iter().rev().eq(expected.iter().cloned())
+            && (0..=MAX_ID).all(|id| set.contains(id) == model.contains(&id))
+            && (0..=model.len()).all(|index| set.at_index(index) == expected.get(index).cloned())
+            && *set == USet::from_slice(&expected)
+    }
+
+    #[derive(Debug, Clone)]
+    enum MapOp {
+        Put(usize, u8),
+        Push(u8),
+        Replace(usize, u8),
+        Remove(usize),
+        Truncate(usize),
+        Drain(usize),
+        Pop(usize),
+        PutAll(Vec<(usize, u8)>),
+        RemoveAll(Vec<usize>),
+        ShrinkToFit,
+        Clear,
+    }
+
+    impl Arbitrary for MapOp {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            match u8::arbitrary(g) % 11 {
+                0 => MapOp::Put(gen_id(g), gen_value(g)),
+                1 => MapOp::Push(gen_value(g)),
+                2 => MapOp::Replace(gen_id(g), gen_value(g)),
+                3 => MapOp::Remove(gen_id(g)),
+                4 => MapOp::Truncate(gen_id(g) % 16),
+                5 => MapOp::Drain(gen_id(g) % 16),
+                6 => MapOp::Pop(gen_id(g) % 16),
+                7 => MapOp::PutAll(
+                    gen_ids(g)
+                        .into_iter()
+                        .map(|id| (id, gen_value(g)))
+                        .collect(),
+                ),
+                8 => MapOp::RemoveAll(gen_ids(g)),
+                9 => MapOp::ShrinkToFit,
+                _ => MapOp::Clear,
+            }
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            match self.clone() {
+                MapOp::Put(id, v) => Box::new((id, v).shrink().map(|(id, v)| MapOp::Put(id, v))),
+                MapOp::Push(v) => Box::new(v.shrink().map(MapOp::Push)),
+                MapOp::Replace(id, v) => {
+                    Box::new((id, v).shrink().map(|(id, v)| MapOp::Replace(id, v)))
+                }
+                MapOp::Remove(id) => Box::new(id.shrink().map(MapOp::Remove)),
+                MapOp::Truncate(len) => Box::new(len.shrink().map(MapOp::Truncate)),
+                MapOp::Drain(len) => Box::new(len.shrink().map(MapOp::Drain)),
+                MapOp::Pop(index) => Box::new(index.shrink().map(MapOp::Pop)),
+                MapOp::PutAll(pairs) => Box::new(pairs.shrink().map(MapOp::PutAll)),
+                MapOp::RemoveAll(ids) => Box::new(ids.shrink().map(MapOp::RemoveAll)),
+                MapOp::ShrinkToFit | MapOp::Clear => Box::new(std::iter::empty()),
+            }
+        }
+    }
+
+    fn apply_map_op(op: &MapOp, map: &mut UMap<u8>, model: &mut BTreeMap<usize, u8>) -> bool {
+        match op {
+            MapOp::Put(id, v) => {
+                map.put(*id, *v);
+                model.insert(*id, *v);
+                true
+            }
+            MapOp::Push(v) => {
+                let id = map.push(*v);
+                let expected = model.keys().next_back().map_or(1, |max| max + 1);
+                model.insert(expected, *v);
+                id == expected
+            }
+            MapOp::Replace(id, v) => {
+                map.replace(*id, *v);
+                model.insert(*id, *v);
+                true
+            }
+            MapOp::Remove(id) => map.remove(*id) == model.remove(id),
+            MapOp::Truncate(len) => {
+                map.truncate(*len);
+                *model = model.iter().map(|(&k, &v)| (k, v)).take(*len).collect();
+                true
+            }
+            MapOp::Drain(len) => {
+                let drained = map.drain(*len);
+                let expected: BTreeMap<usize, u8> =
+                    model.iter().map(|(&k, &v)| (k, v)).skip(*len).collect();
+                *model = model.iter().map(|(&k, &v)| (k, v)).take(*len).collect();
+                check_map(&drained, &expected)
+            }
+            MapOp::Pop(index) => {
+                let popped = map.pop(*index);
+                let expected = model.iter().map(|(&k, &v)| (k, v)).nth(*index);
+                if let Some((id, _)) = expected {
+                    model.remove(&id);
+                }
+                popped == expected
+            }
+            MapOp::PutAll(pairs) => {
+                map.put_all(pairs);
+                model.extend(pairs.iter().cloned());
+                true
+            }
+            MapOp::RemoveAll(ids) => {
+                map.remove_all(&USet::from_slice(ids));
+                ids.iter().for_each(|id| {
+                    model.remove(id);
+                });
+                true
+            }
+            MapOp::ShrinkToFit => {
+                map.shrink_to_fit();
+                true
+            }
+            MapOp::Clear => {
+                map.clear();
+                model.clear();
+                true
+            }
+        }
+    }
+
+    fn check_map(map: &UMap<u8>, model: &BTreeMap<usize, u8>) -> bool {
+        let expected: Vec<(usize, u8)> = model.iter().map(|(&k, &v)| (k, v)).collect();
+        let forward: Vec<(usize, u8)> = map.iter().map(|(k, &v)| (k, v)).collect();
+        let backward: Vec<(usize, u8)> = map.iter().rev().map(|(k, &v)| (k, v)).collect();
+        let keys: Vec<usize> = model.keys().cloned().collect();
+
+        let layout_ok = match (map.min(), map.max()) {
+            (Some(min), Some(max)) => {
+                map.offset() <= min && min <= max && max - map.offset() < map.capacity()
+            }
+            (None, None) => true,
+            _ => false,
+        };
+
+        map.len() == model.len()
+            && map.is_empty() == model.is_empty()
+            && map.min() == model.keys().next().cloned()
+            && map.max() == model.keys().next_back().cloned()
+            && layout_ok
+            && forward == expected
+            && backward.into_iter().rev().eq(expected.iter().cloned())
+            && (0..=MAX_ID).all(|id| {
+                map.contains(id) == model.contains_key(&id)
+                    && map.get(id) == model.get(&id).cloned()
+                    && map.get_ref(id) == model.get(&id)
+            })
+            && (0..=model.len()).all(|index| map.at_index(index) == expected.get(index).cloned())
+            && map.keys() == USet::from_slice(&keys)
+            && *map == UMap::from_slice(&expected)
+    }
+
+    quickcheck! {
+        fn uset_conforms_to_btreeset(ops: Vec<SetOp>) -> bool {
+            let mut set = USet::new();
+            let mut model = BTreeSet::new();
+            ops.iter().all(|op| apply_set_op(op, &mut set, &mut model) && check_set(&set, &model))
+        }
+
+        fn umap_conforms_to_btreemap(ops: Vec<MapOp>) -> bool {
+            let mut map = UMap::new();
+            let mut model = BTreeMap::new();
+            ops.iter().all(|op| apply_map_op(op, &mut map, &mut model) && check_map(&map, &model))
+        }
+    }
+}
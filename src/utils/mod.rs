@@ -2,6 +2,8 @@ pub mod prng;
 pub mod umap;
 pub mod uset;
 
+#[cfg(test)]
+mod conformance_tests;
 #[cfg(test)]
 mod prng_tests;
 #[cfg(test)]
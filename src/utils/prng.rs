@@ -37,6 +37,45 @@ fn new_seed() -> [u32; 4] {
     [s1, s2, s3, s4]
 }
 
+/// Expands a single number into a seed for `XorShiftRng` or `PseudoNormalRng`.
+/// Different numbers give different seeds and the result is never all zeros,
+/// which `XorShiftRng` does not accept.
+pub fn seed_from_u64(seed: u64) -> [u32; 4] {
+    // splitmix64
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    let (a, b) = (next(), next());
+    let seed = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    if seed == [0; 4] {
+        [1, 0, 0, 0]
+    } else {
+        seed
+    }
+}
+
+/// Creates a fast, uniformly distributed random number generator which always produces
+/// the same numbers for the same `seed`.
+///
+/// # Examples
+///
+/// ```
+/// use rand::Rng;
+/// use rust_experiments::utils::prng::seeded_rng;
+///
+/// let x: u32 = seeded_rng(42).gen();
+/// let y: u32 = seeded_rng(42).gen();
+/// assert_eq!(x, y);
+/// ```
+pub fn seeded_rng(seed: u64) -> XorShiftRng {
+    XorShiftRng::from_seed(seed_from_u64(seed))
+}
+
 impl PseudoNormalRng {
     #[inline]
     pub fn with_seed(a: f64, seed: [u32; 4]) -> Self {
@@ -191,6 +191,24 @@ where
         self.vec.len()
     }
 
+    /// Returns the identifier stored at the beginning of the internal vector.
+    /// It is never larger than the smallest identifier in the map, but may be smaller
+    /// if the map was not shrunk after removing elements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use crate::rust_experiments::utils::umap::*;
+    ///
+    /// let mut map = UMap::from_slice(&[(3, "a"), (5, "b")]);
+    /// assert_eq!(map.offset(), 3);
+    /// map.put(1, "c");
+    /// assert_eq!(map.offset(), 1);
+    /// ```
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
     /// Shrinks the map to the minimal size able to hold its elements.
     ///
     /// # Examples
@@ -520,7 +538,7 @@ where
                     self.max = id
                 }
             }
-            _ => {}
+            _ => self.vec[id - self.offset] = Some(value),
         }
     }
 
@@ -537,7 +555,7 @@ where
     /// assert_eq!(1, map.len());
     /// ```
     pub fn contains(&self, id: usize) -> bool {
-        id >= self.min && id <= self.max && self.vec[id - self.offset].is_some()
+        !self.is_empty() && id >= self.min && id <= self.max && self.vec[id - self.offset].is_some()
     }
 
     /// Returns `Some` with a copy of the element under the given id, or `None` otherwise.
@@ -553,7 +571,7 @@ where
     /// assert_eq!(None, c);
     /// ```
     pub fn get(&self, id: usize) -> Option<T> {
-        if id >= self.min && id <= self.max {
+        if !self.is_empty() && id >= self.min && id <= self.max {
             unsafe { self.vec.get_unchecked(id - self.offset).clone() }
         } else {
             None
@@ -573,7 +591,7 @@ where
     /// assert_eq!(None, c);
     /// ```
     pub fn get_ref(&self, id: usize) -> Option<&T> {
-        if id >= self.min && id <= self.max {
+        if !self.is_empty() && id >= self.min && id <= self.max {
             unsafe {
                 if let Some(ref v) = self.vec.get_unchecked(id - self.offset) {
                     Some(v)
@@ -602,7 +620,7 @@ where
     /// assert_eq!(None, c);
     /// ```
     pub fn get_ref_mut(&mut self, id: usize) -> Option<&mut T> {
-        if id >= self.min && id <= self.max {
+        if !self.is_empty() && id >= self.min && id <= self.max {
             unsafe {
                 if let Some(ref mut v) = self.vec.get_unchecked_mut(id - self.offset) {
                     Some(v)
@@ -818,12 +836,12 @@ where
                 (*id, *id, 1, vec![Some(value.clone()); 1])
             }
             MinMaxResult::MinMax(&(min, _), &(max, _)) => {
-                let len = slice.len();
                 let capacity = cmp::max(INITIAL_CAPACITY, max + 1 - min);
                 let mut vec = vec![None; capacity];
                 slice
                     .iter()
                     .for_each(|(id, value)| vec[*id - min] = Some(value.clone()));
+                let len = vec.iter().filter(|v| v.is_some()).count(); // the slice may contain duplicates
                 (min, max, len, vec)
             }
         }
@@ -912,23 +930,21 @@ where
                 if min >= self.min && max <= self.max {
                     slice.iter().for_each(|(ref id, value)| {
                         if self.vec[*id - self.offset].is_none() {
-                            self.vec[*id - self.offset] = Some(value.clone());
                             self.len += 1;
                         }
+                        self.vec[*id - self.offset] = Some(value.clone());
                     })
                 } else {
                     let new_min = cmp::min(self.min, min);
                     let new_max = cmp::max(self.max, max);
                     let mut new_vec = vec![None; new_max - new_min + 1];
                     self.iter()
-                        .skip(self.min - self.offset)
-                        .take(self.max - self.min + 1)
                         .for_each(|(id, value)| new_vec[id - new_min] = Some(value.clone()));
                     slice.iter().for_each(|(ref id, value)| {
                         if new_vec[*id - new_min].is_none() {
-                            new_vec[*id - new_min] = Some(value.clone());
                             self.len += 1;
                         }
+                        new_vec[*id - new_min] = Some(value.clone());
                     });
                     self.min = new_min;
                     self.offset = new_min;
@@ -9,6 +9,15 @@ use std::ops::{Add, BitXor, Mul, Sub};
 use crate::utils::umap::UMap;
 use itertools::{Itertools, MinMaxResult};
 
+/// Creates a `USet` with the given values.
+/// Equivalent to calling [`from_slice`].
+///
+/// [`from_slice`]: #method.from_slice
+#[allow(unused_macros)]
+macro_rules! uset {
+    ($($x:expr),*) => (USet::from_slice(&vec![$($x),*]))
+}
+
 /// A set of unsigned integers (usizes) implemented as a vector of booleans
 /// where `vec[n - offset] == true` means that the set contains `n`. Intended for
 /// handling small to medium number of identifiers.
@@ -21,16 +30,10 @@ use itertools::{Itertools, MinMaxResult};
 /// (so `offset == min`) and `max - offset == capacity`. However, for performance
 /// purposes, if the operation does not require new allocation, the capacity might be
 /// left bigger than `max - min`.
-
-/// Creates a `USet` with the given values.
-/// Equivalent to calling [`from_slice`].
 ///
-/// [`from_slice`]: #method.from_slice
-#[allow(unused_macros)]
-macro_rules! uset {
-    ($($x:expr),*) => (USet::from_slice(&vec![$($x),*]))
-}
-
+/// Getting an element by its position (`at_index`) is O(n), but after the first `pop` the set
+/// keeps an index of positions (a Fenwick tree), which makes `at_index` and `pop` O(log n) for
+/// as long as values are added and removed within the capacity.
 #[derive(Debug, Default, Clone)]
 pub struct USet {
     vec: Vec<bool>,
@@ -38,6 +41,8 @@ pub struct USet {
     offset: usize,
     min: usize,
     max: usize,
+    // ranks[i] is the number of values in a range of vec ending at i - 1, as in a Fenwick tree
+    ranks: Option<Vec<usize>>,
 }
 
 pub struct USetIter<'a> {
@@ -138,6 +143,7 @@ impl USet {
             offset: 0,
             min: 0,
             max: 0,
+            ranks: None,
         }
     }
 
@@ -186,6 +192,24 @@ impl USet {
         self.vec.len()
     }
 
+    /// Returns the identifier stored at the beginning of the internal vector.
+    /// It is never larger than the smallest identifier in the set, but may be smaller
+    /// if the set was not shrunk after removing elements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use crate::rust_experiments::utils::uset::*;
+    ///
+    /// let mut set = USet::from_slice(&[3, 5]);
+    /// assert_eq!(set.offset(), 3);
+    /// set.push(1);
+    /// assert_eq!(set.offset(), 1);
+    /// ```
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
     /// Shrinks the set to the minimal size able to hold given values.
     ///
     /// # Examples
@@ -201,6 +225,7 @@ impl USet {
     /// assert!(set.capacity() == 1);
     /// ```
     pub fn shrink_to_fit(&mut self) {
+        self.ranks = None;
         // TODO: Possible performance optimization with Vec::shrink_to_fit and other in-place operations when possible
         if !self.is_empty() && (!self.vec[0] || !self.vec[self.vec.len() - 1]) {
             let mut vec = vec![false; self.max - self.min + 1];
@@ -261,6 +286,7 @@ impl USet {
     /// [`drain`]: #method.drain
     /// [`shrink_to_fit`]: #method.shrink_to_fit
     pub fn truncate(&mut self, len: usize) {
+        self.ranks = None;
         if !self.is_empty() && len > 0 && len < self.len {
             let mut values_left = len;
             let mut new_max = 0usize;
@@ -334,6 +360,7 @@ impl USet {
     /// [`truncate`]: #method.truncate
     /// [`shrink_to_fit`]: #method.shrink_to_fit
     pub fn drain(&mut self, len: usize) -> Self {
+        self.ranks = None;
         if !self.is_empty() && len > 0 && len < self.len {
             let mut new_set = USet::with_capacity(self.len - len);
             let mut values_left = len;
@@ -415,6 +442,7 @@ impl USet {
     /// assert_eq!(11, set.capacity());
     /// ```
     pub fn enlarge_capacity_to(&mut self, new_capacity: usize) {
+        self.ranks = None;
         if new_capacity > self.capacity() {
             self.vec.resize(new_capacity, false);
         }
@@ -433,6 +461,7 @@ impl USet {
     /// assert_eq!(set, USet::from_slice(&[1, 2, 3]));
     /// ```
     pub fn push(&mut self, id: usize) {
+        let before = (self.offset, self.capacity(), self.len);
         match id {
             _ if self.capacity() == 0 => {
                 self.vec = vec![false; INITIAL_WORKING_CAPACITY];
@@ -477,6 +506,7 @@ impl USet {
             }
             _ => {}
         }
+        self.update_ranks(before, id);
     }
 
     /// Removes the id from the set. Does nothing if the id is not in the set.
@@ -491,6 +521,7 @@ impl USet {
     /// assert_eq!(set, USet::from_slice(&[1, 3]));
     /// ```
     pub fn remove(&mut self, id: usize) {
+        let before = (self.offset, self.capacity(), self.len);
         match id {
             _ if id < self.min || id > self.max || !self.contains(id) => {}
             _ if self.len == 1 => {
@@ -521,6 +552,53 @@ impl USet {
             }
             _ => {}
         }
+        self.update_ranks(before, id);
+    }
+
+    // Keeps the index of positions up to date after `id` was pushed or removed, or drops it
+    // if the values were moved within the vector.
+    fn update_ranks(&mut self, before: (usize, usize, usize), id: usize) {
+        let (offset, capacity, len) = before;
+        if self.offset != offset || self.capacity() != capacity {
+            self.ranks = None;
+        } else if let (Some(ranks), true) = (self.ranks.as_mut(), self.len != len) {
+            let mut i = id - self.offset + 1;
+            while i < ranks.len() {
+                if self.len > len {
+                    ranks[i] += 1;
+                } else {
+                    ranks[i] -= 1;
+                }
+                i += i & i.wrapping_neg();
+            }
+        }
+    }
+
+    fn build_ranks(&self) -> Vec<usize> {
+        let mut ranks = vec![0; self.vec.len() + 1];
+        for i in 1..ranks.len() {
+            ranks[i] += self.vec[i - 1] as usize;
+            let parent = i + (i & i.wrapping_neg());
+            if parent < ranks.len() {
+                ranks[parent] += ranks[i];
+            }
+        }
+        ranks
+    }
+
+    // Finds the value at `index` by descending the Fenwick tree, in O(log n).
+    fn find_by_rank(&self, ranks: &[usize], index: usize) -> usize {
+        let mut position = 0;
+        let mut remaining = index + 1;
+        let mut step = (ranks.len() - 1).next_power_of_two();
+        while step > 0 {
+            if position + step < ranks.len() && ranks[position + step] < remaining {
+                position += step;
+                remaining -= ranks[position];
+            }
+            step /= 2;
+        }
+        position + self.offset
     }
 
     /// Removes all the identifiers belonging to the `other` set from `self`. Ignores identifiers
@@ -575,6 +653,7 @@ impl USet {
 
     /// Removes and returns the element at position `index` within the set.
     /// Returns `None` if `index` is out of bounds.
+    /// The first call builds an index of positions in O(n), the following ones take O(log n).
     ///
     /// # Examples
     ///
@@ -586,6 +665,9 @@ impl USet {
     /// assert_eq!(set, USet::from_slice(&[1, 3]));
     /// ```
     pub fn pop(&mut self, index: usize) -> Option<usize> {
+        if self.ranks.is_none() && index < self.len {
+            self.ranks = Some(self.build_ranks());
+        }
         let d = self.at_index(index);
         if let Some(id) = d {
             self.remove(id);
@@ -629,7 +711,7 @@ impl USet {
     /// assert_eq!(set.contains(2), false);
     /// ```
     pub fn contains(&self, id: usize) -> bool {
-        id >= self.min && id <= self.max && self.vec[id - self.offset]
+        !self.is_empty() && id >= self.min && id <= self.max && self.vec[id - self.offset]
     }
 
     /// The set allows to access its values by index.
@@ -650,6 +732,8 @@ impl USet {
     pub fn at_index(&self, index: usize) -> Option<usize> {
         if index >= self.len {
             None
+        } else if let Some(ranks) = &self.ranks {
+            Some(self.find_by_rank(ranks, index))
         } else {
             let mut it = self.iter();
             for _i in 0..index {
@@ -714,10 +798,10 @@ impl USet {
             MinMaxResult::NoElements => (0, 0, 0, Vec::<bool>::new()),
             MinMaxResult::OneElement(&min) => (min, min, 1, vec![true]),
             MinMaxResult::MinMax(&min, &max) => {
-                let len = slice.len();
                 let capacity = cmp::max(INITIAL_WORKING_CAPACITY, max + 1 - min);
                 let mut vec = vec![false; capacity];
                 slice.iter().for_each(|&id| vec[id - min] = true);
+                let len = vec.iter().filter(|&b| *b).count(); // the slice may contain duplicates
                 (min, max, len, vec)
             }
         }
@@ -749,6 +833,7 @@ impl USet {
                 offset: min,
                 min,
                 max,
+                ranks: None,
             }
         }
     }
@@ -774,7 +859,7 @@ impl USet {
             EMPTY_SET.clone()
         } else {
             let offset = r.start;
-            let max = r.end;
+            let max = r.end - 1;
             let len = r.len();
             let capacity = cmp::max(INITIAL_WORKING_CAPACITY, r.len());
             let mut vec = vec![false; capacity];
@@ -785,6 +870,7 @@ impl USet {
                 offset,
                 min: offset,
                 max,
+                ranks: None,
             }
         }
     }
@@ -806,7 +892,7 @@ impl USet {
     /// assert!(set.contains(6));
     /// ```
     pub fn from_fields(vec: Vec<bool>, offset: usize) -> Self {
-        if vec.is_empty() {
+        if !vec.contains(&true) {
             EMPTY_SET.clone()
         } else {
             let len = vec.iter().filter(|&b| *b).count();
@@ -829,6 +915,7 @@ impl USet {
                 offset,
                 min,
                 max,
+                ranks: None,
             }
         }
     }
@@ -859,6 +946,7 @@ impl USet {
     /// assert!(set.contains(5));
     /// ```
     pub fn push_all(&mut self, slice: &[usize]) {
+        self.ranks = None;
         if !slice.is_empty() {
             if self.is_empty() {
                 let (min, max, len, new_vec) = USet::make_from_slice(slice);
@@ -885,10 +973,7 @@ impl USet {
                     let new_min = cmp::min(self.min, min);
                     let new_max = cmp::max(self.max, max);
                     let mut new_vec = vec![false; new_max - new_min + 1];
-                    self.iter()
-                        .skip(self.min - self.offset)
-                        .take(self.max - self.min + 1)
-                        .for_each(|id| new_vec[id - new_min] = true);
+                    self.iter().for_each(|id| new_vec[id - new_min] = true);
                     slice.iter().for_each(|&id| {
                         if !new_vec[id - new_min] {
                             new_vec[id - new_min] = true;
@@ -937,6 +1022,7 @@ impl USet {
                 offset: min,
                 min,
                 max,
+                ranks: None,
             }
         }
     }
@@ -974,6 +1060,7 @@ impl USet {
                 offset: self.offset,
                 min,
                 max,
+                ranks: None,
             }
         }
     }
@@ -1006,6 +1093,7 @@ impl USet {
                         offset: min,
                         min,
                         max,
+                        ranks: None,
                     }
                 } else {
                     EMPTY_SET.clone()
@@ -1051,6 +1139,7 @@ impl USet {
                         offset: min,
                         min,
                         max,
+                        ranks: None,
                     }
                 } else {
                     EMPTY_SET.clone()
@@ -315,4 +315,64 @@ mod uset_tests {
         assert_that!(set.contains(11));
         assert_that!(set.contains(8) == false);
     }
+
+    #[test]
+    fn should_pop_by_index_after_pushes_and_removals() {
+        let mut set = USet::from(0..100);
+        assert_that!(set.pop(10)).is_equal_to(Some(10));
+        set.remove(0);
+        set.push(10);
+        set.remove(50);
+        assert_that!(set.at_index(0)).is_equal_to(Some(1));
+        assert_that!(set.at_index(49)).is_equal_to(Some(51));
+        assert_that!(set.pop(97)).is_equal_to(Some(99));
+        assert_that!(set.pop(97)).is_equal_to(None);
+        // pushing outside the capacity moves the values, and the index has to be rebuilt
+        set.push(500);
+        set.push(0);
+        assert_that!(set.pop(0)).is_equal_to(Some(0));
+        assert_that!(set.pop(set.len() - 1)).is_equal_to(Some(500));
+        assert_that!(set.len()).is_equal_to(97);
+    }
+
+    quickcheck! {
+        // `pop` and `at_index` have to agree with a sorted vector through any mix of changes.
+        fn pop_agrees_with_sorted_vec(initial: Vec<u8>, ops: Vec<(u8, u8)>) -> bool {
+            let mut set: USet = initial.iter().map(|&id| id as usize).collect();
+            let mut model = to_unique_sorted_vec(&initial.iter().map(|&id| id as usize).collect::<Vec<usize>>());
+            for &(op, value) in &ops {
+                let value = value as usize;
+                match op % 4 {
+                    0 => {
+                        set.push(value);
+                        if let Err(pos) = model.binary_search(&value) {
+                            model.insert(pos, value);
+                        }
+                    }
+                    1 => {
+                        set.remove(value);
+                        if let Ok(pos) = model.binary_search(&value) {
+                            model.remove(pos);
+                        }
+                    }
+                    2 => {
+                        let expected = if value < model.len() {
+                            Some(model.remove(value))
+                        } else {
+                            None
+                        };
+                        if set.pop(value) != expected {
+                            return false;
+                        }
+                    }
+                    _ => set.shrink_to_fit(),
+                }
+                let all_match = (0..=model.len()).all(|i| set.at_index(i) == model.get(i).cloned());
+                if !all_match || set.len() != model.len() {
+                    return false;
+                }
+            }
+            true
+        }
+    }
 }
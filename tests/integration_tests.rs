@@ -1,24 +1,61 @@
+extern crate rand;
 extern crate rust_experiments;
 
+use rand::Rng;
 use rust_experiments::cities::*;
+use rust_experiments::utils::prng::seeded_rng;
+
+const CHAIN_SIZE: usize = 10_000_000;
+
+// Makes a map where the cities form a single road: `order[0]` is the farthest city
+// and the last one is the capital.
+fn gen_chain(order: &[usize]) -> Vec<usize> {
+    let mut map = vec![0; order.len()];
+    order.windows(2).for_each(|w| map[w[0]] = w[1]);
+    let capital = order[order.len() - 1];
+    map[capital] = capital;
+    map
+}
 
 #[test]
 fn generate_and_solve_standard() {
-    let map = gen_cities(1000, 5, 0.1, 25);
-    let result = find_city_distances(&map);
+    let map = gen_cities(&CityGenConfig::new(1000));
+    let result = find_city_distances(&map).unwrap();
     println!("{:?}", result);
 }
 
 #[test]
 fn generate_and_solve_uset() {
     let map = gen_cities_uset(5, 25);
-    let result = find_city_distances(&map);
+    let result = find_city_distances(&map).unwrap();
     println!("{:?}", result);
 }
 
 #[test]
 fn generate_and_solve_hashset() {
     let map = gen_cities_hashset(1000, 25);
-    let result = find_city_distances(&map);
+    let result = find_city_distances(&map).unwrap();
     println!("{:?}", result);
 }
+
+#[test]
+fn solve_long_chain_leading_to_the_last_city() {
+    // the first city is the farthest from the capital, so the whole chain is walked at once
+    let order: Vec<usize> = (0..CHAIN_SIZE).collect();
+    let result = find_city_distances(&gen_chain(&order)).unwrap();
+    assert_eq!(result.len(), CHAIN_SIZE);
+    assert!(result.iter().all(|&n| n == 1));
+}
+
+#[test]
+fn solve_long_shuffled_chain() {
+    let mut order: Vec<usize> = (0..CHAIN_SIZE).collect();
+    seeded_rng(30).shuffle(&mut order);
+    let map = gen_chain(&order);
+    let result = find_city_distances(&map).unwrap();
+    assert_eq!(result.len(), CHAIN_SIZE);
+    assert!(result.iter().all(|&n| n == 1));
+
+    let weighted = find_weighted_city_distances_by(&map, |_| 1u64).unwrap();
+    assert_eq!(weighted.distances[order[0]], (CHAIN_SIZE - 1) as u64);
+}
@@ -0,0 +1,615 @@
+extern crate rust_experiments;
+
+#[cfg(feature = "event-loop")]
+use rust_experiments::redis::event_loop::{self, MAX_PENDING_INPUT, MAX_PENDING_OUTPUT};
+use rust_experiments::redis::pubsub::MAX_PENDING_PUSHES;
+use rust_experiments::redis::resp::{Parser, Value};
+use rust_experiments::redis::*;
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CLIENTS: usize = 64;
+const WORDS_PER_CLIENT: usize = 50;
+
+type Server = fn(TcpListener, Arc<Store>, Arc<Broker>, Protocol);
+
+// Starts a server on a free port, in the background, and returns its address.
+fn start_server(server: Server) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (store, broker) = (Arc::new(Store::new()), Arc::new(Broker::new()));
+        server(listener, store, broker, Protocol::Resp)
+    });
+    addr
+}
+
+#[cfg(feature = "event-loop")]
+fn serve_event_loop(
+    listener: TcpListener,
+    store: Arc<Store>,
+    broker: Arc<Broker>,
+    protocol: Protocol,
+) {
+    event_loop::serve(listener, store, broker, protocol).unwrap();
+}
+
+fn push(words: &[&str]) -> Value {
+    Value::Array(words.iter().map(|w| Value::bulk(w)).collect())
+}
+
+fn confirmation(command: &str, name: &str, count: i64) -> Value {
+    Value::Array(vec![
+        Value::bulk(command),
+        Value::bulk(name),
+        Value::Integer(count),
+    ])
+}
+
+struct Client {
+    stream: TcpStream,
+    parser: Parser,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        // a server which blocks clients fails the test instead of hanging it
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        Client {
+            stream,
+            parser: Parser::new(),
+        }
+    }
+
+    fn send(&mut self, words: &[&str]) {
+        let request = Value::Array(words.iter().map(|w| Value::bulk(w)).collect());
+        self.stream.write_all(&request.encode()).unwrap();
+    }
+
+    fn call(&mut self, words: &[&str]) -> Value {
+        self.send(words);
+        self.reply()
+    }
+
+    fn reply(&mut self) -> Value {
+        let mut buf = [0; 1024];
+        loop {
+            if let Some(value) = self.parser.next_value().unwrap() {
+                return value;
+            }
+            let n = self
+                .stream
+                .read(&mut buf)
+                .expect("no reply from the server");
+            assert!(n > 0, "the server closed the connection");
+            self.parser.feed(&buf[..n]);
+        }
+    }
+}
+
+fn check_idle_client_does_not_block_others(addr: SocketAddr) {
+    let mut idle = Client::connect(addr);
+    assert_eq!(idle.call(&["PING"]), Value::bulk("PONG"));
+
+    let mut other = Client::connect(addr);
+    assert_eq!(
+        other.call(&["ENQUEUE", "q", "word"]),
+        Value::Simple("OK".into())
+    );
+    assert_eq!(idle.call(&["RETRIEVE", "q"]), Value::bulk("word"));
+}
+
+fn check_many_clients_at_once(addr: SocketAddr) {
+    let barrier = Arc::new(Barrier::new(CLIENTS));
+
+    let handles: Vec<_> = (0..CLIENTS)
+        .map(|id| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let mut client = Client::connect(addr);
+                // every client is connected before any of them sends a command
+                barrier.wait();
+                let words: Vec<String> = (0..WORDS_PER_CLIENT)
+                    .map(|n| format!("{}-{}", id, n))
+                    .collect();
+                for word in &words {
+                    assert_eq!(
+                        client.call(&["ENQUEUE", "q", word]),
+                        Value::Simple("OK".into())
+                    );
+                }
+                let retrieved: Vec<Value> = (0..WORDS_PER_CLIENT)
+                    .map(|_| client.call(&["RETRIEVE", "q"]))
+                    .collect();
+                assert_eq!(client.call(&["STOP"]), Value::Simple("OK".into()));
+                retrieved
+            })
+        })
+        .collect();
+
+    let retrieved: Vec<Value> = handles
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap())
+        .collect();
+
+    // each client retrieves as many words as it published, so the queue never runs dry,
+    // and every word is retrieved exactly once
+    let unique: HashSet<String> = retrieved
+        .iter()
+        .map(|value| match value {
+            Value::Bulk(data) => String::from_utf8(data.clone()).unwrap(),
+            other => panic!("unexpected reply {:?}", other),
+        })
+        .collect();
+    assert_eq!(retrieved.len(), CLIENTS * WORDS_PER_CLIENT);
+    assert_eq!(unique.len(), CLIENTS * WORDS_PER_CLIENT);
+
+    let mut client = Client::connect(addr);
+    assert_eq!(
+        client.call(&["RETRIEVE", "q"]),
+        Value::Error("ERR Empty RETRIEVE".into())
+    );
+}
+
+fn check_pipelined_commands(addr: SocketAddr) {
+    let mut client = Client::connect(addr);
+    // all the requests go out before any reply is read, in one write
+    let mut requests = Vec::new();
+    for n in 0..1000 {
+        let word = n.to_string();
+        let request = Value::Array(vec![
+            Value::bulk("ENQUEUE"),
+            Value::bulk("q"),
+            Value::bulk(&word),
+        ]);
+        requests.extend(request.encode());
+        requests.extend(Value::Array(vec![Value::bulk("RETRIEVE"), Value::bulk("q")]).encode());
+    }
+    client.stream.write_all(&requests).unwrap();
+    for n in 0..1000 {
+        assert_eq!(client.reply(), Value::Simple("OK".into()));
+        assert_eq!(client.reply(), Value::bulk(&n.to_string()));
+    }
+    client.send(&["PING"]);
+    client.send(&["STOP"]);
+    client.send(&["PING"]);
+    assert_eq!(client.reply(), Value::bulk("PONG"));
+    assert_eq!(client.reply(), Value::Simple("OK".into()));
+    // nothing after STOP is answered
+    let mut buf = [0; 16];
+    assert_eq!(client.stream.read(&mut buf).unwrap(), 0);
+}
+
+// Sends BRETRIEVE from each of the clients, leaving the server time to line them up.
+fn line_up(clients: &mut [Client], timeout: &str) {
+    for client in clients.iter_mut() {
+        client.send(&["BRETRIEVE", "q", timeout]);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn check_bretrieve_in_fifo_order(addr: SocketAddr) {
+    let mut waiting: Vec<Client> = (0..3).map(|_| Client::connect(addr)).collect();
+    line_up(&mut waiting, "0");
+    let mut publisher = Client::connect(addr);
+    assert_eq!(
+        publisher.call(&["ENQUEUE", "q", "a", "b"]),
+        Value::Simple("OK".into())
+    );
+    assert_eq!(waiting[0].reply(), Value::bulk("a"));
+    assert_eq!(waiting[1].reply(), Value::bulk("b"));
+    // no word is left for RETRIEVE while someone waits
+    assert_eq!(
+        publisher.call(&["RETRIEVE", "q"]),
+        Value::Error("ERR Empty RETRIEVE".into())
+    );
+    assert_eq!(
+        publisher.call(&["ENQUEUE", "q", "c", "d"]),
+        Value::Simple("OK".into())
+    );
+    assert_eq!(waiting[2].reply(), Value::bulk("c"));
+    // a word already in the queue is taken at once
+    assert_eq!(waiting[0].call(&["BRETRIEVE", "q", "0"]), Value::bulk("d"));
+}
+
+fn check_bretrieve_timeout(addr: SocketAddr) {
+    let mut client = Client::connect(addr);
+    let start = Instant::now();
+    assert_eq!(client.call(&["BRETRIEVE", "q", "0.2"]), Value::Null);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    // the timed out client is out of line
+    let mut publisher = Client::connect(addr);
+    assert_eq!(
+        publisher.call(&["ENQUEUE", "q", "a"]),
+        Value::Simple("OK".into())
+    );
+    assert_eq!(client.call(&["RETRIEVE", "q"]), Value::bulk("a"));
+    // timeouts too large for a deadline are refused, and the server keeps going
+    for timeout in &["1e20", "1e19"] {
+        assert_eq!(
+            client.call(&["BRETRIEVE", "q", timeout]),
+            Value::Error("ERR timeout is out of range".into())
+        );
+    }
+    assert_eq!(client.call(&["PING"]), Value::bulk("PONG"));
+}
+
+fn check_named_queues_and_keys(addr: SocketAddr) {
+    let mut waiting = Client::connect(addr);
+    waiting.send(&["BRETRIEVE", "x", "0"]);
+    thread::sleep(Duration::from_millis(50));
+    let mut other = Client::connect(addr);
+    assert_eq!(
+        other.call(&["ENQUEUE", "y", "a"]),
+        Value::Simple("OK".into())
+    );
+    assert_eq!(other.call(&["SET", "k", "v"]), Value::Simple("OK".into()));
+    assert_eq!(other.call(&["EXPIRE", "k", "100"]), Value::Integer(1));
+    assert_eq!(other.call(&["RPUSH", "x", "b", "c"]), Value::Integer(2));
+    // the waiting client takes the first word of its own list only
+    assert_eq!(waiting.reply(), Value::bulk("b"));
+    assert_eq!(waiting.call(&["GET", "k"]), Value::bulk("v"));
+    assert_eq!(waiting.call(&["TTL", "k"]), Value::Integer(100));
+    assert_eq!(
+        waiting.call(&["LRANGE", "x", "0", "-1"]),
+        Value::Array(vec![Value::bulk("c")])
+    );
+    assert_eq!(waiting.call(&["RETRIEVE", "y"]), Value::bulk("a"));
+}
+
+fn check_messages_to_all_subscribers(addr: SocketAddr) {
+    let mut subscribers: Vec<Client> = (0..3).map(|_| Client::connect(addr)).collect();
+    subscribers[0].send(&["SUBSCRIBE", "news", "weather"]);
+    assert_eq!(subscribers[0].reply(), confirmation("subscribe", "news", 1));
+    assert_eq!(
+        subscribers[0].reply(),
+        confirmation("subscribe", "weather", 2)
+    );
+    assert_eq!(
+        subscribers[1].call(&["SUBSCRIBE", "news"]),
+        confirmation("subscribe", "news", 1)
+    );
+    assert_eq!(
+        subscribers[2].call(&["PSUBSCRIBE", "n*"]),
+        confirmation("psubscribe", "n*", 1)
+    );
+
+    let mut publisher = Client::connect(addr);
+    assert_eq!(
+        publisher.call(&["PUBLISH", "news", "hello"]),
+        Value::Integer(3)
+    );
+    assert_eq!(
+        publisher.call(&["PUBLISH", "weather", "sunny"]),
+        Value::Integer(1)
+    );
+    assert_eq!(subscribers[0].reply(), push(&["message", "news", "hello"]));
+    assert_eq!(
+        subscribers[0].reply(),
+        push(&["message", "weather", "sunny"])
+    );
+    assert_eq!(subscribers[1].reply(), push(&["message", "news", "hello"]));
+    assert_eq!(
+        subscribers[2].reply(),
+        push(&["pmessage", "n*", "news", "hello"])
+    );
+
+    // a subscriber leaving does not disturb the others
+    drop(subscribers.remove(1));
+    assert_eq!(
+        subscribers[0].call(&["UNSUBSCRIBE", "weather"]),
+        confirmation("unsubscribe", "weather", 1)
+    );
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(
+        publisher.call(&["PUBLISH", "news", "bye"]),
+        Value::Integer(2)
+    );
+    assert_eq!(subscribers[0].reply(), push(&["message", "news", "bye"]));
+    assert_eq!(
+        subscribers[1].reply(),
+        push(&["pmessage", "n*", "news", "bye"])
+    );
+    assert_eq!(
+        publisher.call(&["PUBLISH", "weather", "rain"]),
+        Value::Integer(0)
+    );
+    // the queues are still there, under their own command
+    assert_eq!(
+        publisher.call(&["ENQUEUE", "news", "a"]),
+        Value::Simple("OK".into())
+    );
+    assert_eq!(publisher.call(&["RETRIEVE", "news"]), Value::bulk("a"));
+}
+
+fn check_many_messages_in_order(addr: SocketAddr) {
+    let mut subscriber = Client::connect(addr);
+    assert_eq!(
+        subscriber.call(&["SUBSCRIBE", "c"]),
+        confirmation("subscribe", "c", 1)
+    );
+    let publishers: Vec<_> = (0..4)
+        .map(|id| {
+            thread::spawn(move || {
+                let mut publisher = Client::connect(addr);
+                for n in 0..WORDS_PER_CLIENT {
+                    let payload = format!("{}-{}", id, n);
+                    assert_eq!(
+                        publisher.call(&["PUBLISH", "c", &payload]),
+                        Value::Integer(1)
+                    );
+                }
+            })
+        })
+        .collect();
+    let mut next = [0; 4];
+    for _ in 0..4 * WORDS_PER_CLIENT {
+        let payload = match subscriber.reply() {
+            Value::Array(values) => values[2].clone(),
+            other => panic!("unexpected push {:?}", other),
+        };
+        let payload = String::from_utf8(payload_bytes(payload)).unwrap();
+        let mut parts = payload
+            .split('-')
+            .map(|part| part.parse::<usize>().unwrap());
+        let (id, n) = (parts.next().unwrap(), parts.next().unwrap());
+        // the messages of each publisher come in the order they were published
+        assert_eq!(n, next[id]);
+        next[id] += 1;
+    }
+    publishers
+        .into_iter()
+        .for_each(|handle| handle.join().unwrap());
+}
+
+fn check_slow_subscriber_is_disconnected(addr: SocketAddr) {
+    let mut slow = Client::connect(addr);
+    assert_eq!(
+        slow.call(&["SUBSCRIBE", "c"]),
+        confirmation("subscribe", "c", 1)
+    );
+    // far more than the server buffers, while the subscriber reads nothing
+    let payload = "x".repeat(64 * 1024);
+    let publishes = 16 * MAX_PENDING_PUSHES / payload.len();
+    let mut publisher = Client::connect(addr);
+    let dropped = (0..publishes).any(|_| match publisher.call(&["PUBLISH", "c", &payload]) {
+        Value::Integer(n) => n == 0,
+        other => panic!("unexpected reply {:?}", other),
+    });
+    // the subscriber was dropped on the way, and the publisher kept being served
+    assert!(dropped);
+    assert_eq!(publisher.call(&["PING"]), Value::bulk("PONG"));
+}
+
+fn payload_bytes(value: Value) -> Vec<u8> {
+    match value {
+        Value::Bulk(data) => data,
+        other => panic!("unexpected value {:?}", other),
+    }
+}
+
+#[test]
+fn idle_client_does_not_block_others() {
+    check_idle_client_does_not_block_others(start_server(serve));
+}
+
+#[test]
+fn many_clients_at_once() {
+    check_many_clients_at_once(start_server(serve));
+}
+
+#[test]
+fn pipelined_commands() {
+    check_pipelined_commands(start_server(serve));
+}
+
+#[test]
+fn bretrieve_in_fifo_order() {
+    check_bretrieve_in_fifo_order(start_server(serve));
+}
+
+#[test]
+fn bretrieve_timeout() {
+    check_bretrieve_timeout(start_server(serve));
+}
+
+#[test]
+fn named_queues_and_keys() {
+    check_named_queues_and_keys(start_server(serve));
+}
+
+#[test]
+fn messages_to_all_subscribers() {
+    check_messages_to_all_subscribers(start_server(serve));
+}
+
+#[test]
+fn many_messages_in_order() {
+    check_many_messages_in_order(start_server(serve));
+}
+
+#[test]
+fn slow_subscriber_is_disconnected() {
+    check_slow_subscriber_is_disconnected(start_server(serve));
+}
+
+#[cfg(feature = "event-loop")]
+mod event_loop_tests {
+    use super::*;
+
+    use rust_experiments::redis::resp::MAX_BULK_LENGTH;
+    use std::io::ErrorKind;
+
+    const IDLE_CLIENTS: usize = 2000;
+
+    #[test]
+    fn idle_client_does_not_block_others() {
+        check_idle_client_does_not_block_others(start_server(serve_event_loop));
+    }
+
+    #[test]
+    fn many_clients_at_once() {
+        check_many_clients_at_once(start_server(serve_event_loop));
+    }
+
+    #[test]
+    fn pipelined_commands() {
+        check_pipelined_commands(start_server(serve_event_loop));
+    }
+
+    #[test]
+    fn bretrieve_in_fifo_order() {
+        check_bretrieve_in_fifo_order(start_server(serve_event_loop));
+    }
+
+    #[test]
+    fn bretrieve_timeout() {
+        check_bretrieve_timeout(start_server(serve_event_loop));
+    }
+
+    #[test]
+    fn named_queues_and_keys() {
+        check_named_queues_and_keys(start_server(serve_event_loop));
+    }
+
+    #[test]
+    fn messages_to_all_subscribers() {
+        check_messages_to_all_subscribers(start_server(serve_event_loop));
+    }
+
+    #[test]
+    fn many_messages_in_order() {
+        check_many_messages_in_order(start_server(serve_event_loop));
+    }
+
+    #[test]
+    fn slow_subscriber_is_disconnected() {
+        check_slow_subscriber_is_disconnected(start_server(serve_event_loop));
+    }
+
+    #[test]
+    fn commands_pipelined_after_bretrieve_wait() {
+        let addr = start_server(serve_event_loop);
+        let mut client = Client::connect(addr);
+        client.send(&["BRETRIEVE", "q", "0"]);
+        client.send(&["PING"]);
+        thread::sleep(Duration::from_millis(50));
+        let mut publisher = Client::connect(addr);
+        assert_eq!(
+            publisher.call(&["ENQUEUE", "q", "a"]),
+            Value::Simple("OK".into())
+        );
+        assert_eq!(client.reply(), Value::bulk("a"));
+        assert_eq!(client.reply(), Value::bulk("PONG"));
+    }
+
+    #[test]
+    fn disconnected_client_leaves_the_line() {
+        let addr = start_server(serve_event_loop);
+        let mut waiting: Vec<Client> = (0..2).map(|_| Client::connect(addr)).collect();
+        line_up(&mut waiting, "0");
+        drop(waiting.remove(0));
+        thread::sleep(Duration::from_millis(50));
+        let mut publisher = Client::connect(addr);
+        assert_eq!(
+            publisher.call(&["ENQUEUE", "q", "a"]),
+            Value::Simple("OK".into())
+        );
+        assert_eq!(waiting[0].reply(), Value::bulk("a"));
+    }
+
+    #[test]
+    fn many_idle_clients() {
+        let addr = start_server(serve_event_loop);
+        let mut idle: Vec<Client> = (0..IDLE_CLIENTS).map(|_| Client::connect(addr)).collect();
+        let mut active = Client::connect(addr);
+        assert_eq!(
+            active.call(&["ENQUEUE", "q", "a", "b"]),
+            Value::Simple("OK".into())
+        );
+        // every connection is still served
+        assert_eq!(idle[0].call(&["RETRIEVE", "q"]), Value::bulk("a"));
+        assert_eq!(
+            idle[IDLE_CLIENTS - 1].call(&["RETRIEVE", "q"]),
+            Value::bulk("b")
+        );
+        for client in idle.iter_mut().step_by(100) {
+            assert_eq!(client.call(&["PING"]), Value::bulk("PONG"));
+        }
+    }
+
+    #[test]
+    fn client_with_many_pending_replies() {
+        let addr = start_server(serve_event_loop);
+        let mut greedy = Client::connect(addr);
+        // far more replies than the server buffers for one client
+        let request = Value::Array(vec![Value::bulk("PING")]).encode();
+        let count = 2 * MAX_PENDING_OUTPUT / request.len();
+        let writer = {
+            let mut stream = greedy.stream.try_clone().unwrap();
+            thread::spawn(move || {
+                for _ in 0..count {
+                    stream.write_all(&request).unwrap();
+                }
+            })
+        };
+        // meanwhile, the others are served
+        let mut other = Client::connect(addr);
+        assert_eq!(other.call(&["PING"]), Value::bulk("PONG"));
+
+        for _ in 0..count {
+            assert_eq!(greedy.reply(), Value::bulk("PONG"));
+        }
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn client_with_too_long_request() {
+        let addr = start_server(serve_event_loop);
+        let mut greedy = Client::connect(addr);
+        // the start of a bulk string far longer than the server buffers
+        let header = format!("*3\r\n$7\r\nENQUEUE\r\n$1\r\nq\r\n${}\r\n", MAX_BULK_LENGTH);
+        let writer = {
+            let mut stream = greedy.stream.try_clone().unwrap();
+            thread::spawn(move || {
+                stream.write_all(header.as_bytes()).unwrap();
+                let chunk = vec![b'x'; 64 * 1024];
+                for _ in 0..2 * MAX_PENDING_INPUT / chunk.len() {
+                    // fails once the server closes the connection
+                    if stream.write_all(&chunk).is_err() {
+                        break;
+                    }
+                }
+            })
+        };
+        // the reply with the error may be lost with the unread input, but the connection
+        // has to be closed, not left waiting for the rest of the request
+        let mut buf = [0; 1024];
+        loop {
+            match greedy.stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => continue,
+                Err(err) => {
+                    let timed_out = [ErrorKind::WouldBlock, ErrorKind::TimedOut];
+                    assert!(
+                        !timed_out.contains(&err.kind()),
+                        "the client was not dropped"
+                    );
+                    break;
+                }
+            }
+        }
+        writer.join().unwrap();
+
+        let mut other = Client::connect(addr);
+        assert_eq!(other.call(&["PING"]), Value::bulk("PONG"));
+    }
+}